@@ -1,10 +1,44 @@
-use crate::amount::AmountPool;
+use crate::amount::{Amount, AmountPool};
+use crate::color::ColorChoice;
 use crate::entry::Entry;
 use crate::envelope::{Envelope, EnvelopeType};
 use crate::errors::*;
 use crate::posting::Posting;
 use crate::utils;
+use chrono::NaiveDate;
+use regex::Regex;
 use std::cmp::Ordering;
+use std::io::{self, Write};
+
+/// Decides which accounts a report is about
+#[derive(Clone, Debug)]
+pub enum AccountMatcher {
+    /// Matches accounts with names that contain the string anywhere, so `cash` matches both
+    /// `assets:cash` and `expenses:cash-back`
+    Substring(String),
+
+    /// Matches accounts with names that match the regular expression
+    Regex(Regex),
+}
+
+impl AccountMatcher {
+    /// Compiles `pattern` into a regex matcher
+    pub fn regex(pattern: &str) -> Result<Self, SilverfoxError> {
+        Regex::new(pattern).map(Self::Regex).map_err(|e| {
+            SilverfoxError::Basic(format!(
+                "silverfox couldn't understand `{}` as a regular expression: {}",
+                pattern, e
+            ))
+        })
+    }
+
+    pub fn matches(&self, account_name: &str) -> bool {
+        match self {
+            Self::Substring(s) => account_name.contains(s.as_str()),
+            Self::Regex(r) => r.is_match(account_name),
+        }
+    }
+}
 
 pub struct Account {
     name: String,
@@ -12,8 +46,10 @@ pub struct Account {
     goal_envelopes: Vec<Envelope>,
 
     /// The real, actual value of this account, which ignores envelopes or virtual postings.
-    /// TODO: use this for balance statements
     real_value: AmountPool,
+
+    /// The amount of money that envelopes can't be filled with, set with `buffer` lines.
+    buffer: AmountPool,
 }
 
 impl Account {
@@ -44,12 +80,16 @@ impl Account {
             expense_envelopes,
             goal_envelopes,
             real_value: AmountPool::new(),
+            buffer: AmountPool::new(),
         };
 
         let mut envelope_chunk = String::new();
         for line in lines {
             let trimmed_line = line.trim();
-            if trimmed_line.starts_with("expense") || trimmed_line.starts_with("goal") {
+            if let Some(value) = trimmed_line.strip_prefix("buffer ") {
+                // a buffer belongs to the account, not to an envelope
+                account.buffer += Amount::parse(value, decimal_symbol)?;
+            } else if trimmed_line.starts_with("expense") || trimmed_line.starts_with("goal") {
                 // add a new envelope, if the chunk isn't blank
                 if !envelope_chunk.trim().is_empty() {
                     let new_envelope = Envelope::parse(
@@ -86,6 +126,24 @@ impl Account {
         Ok(account)
     }
 
+    /// Returns an account for ledger-level envelopes. It has no postings of its own; its value is
+    /// whatever the asset accounts have left over (see `set_pooled_value`). Its name has spaces,
+    /// so no posting can ever be mistaken for one of its own.
+    pub fn pooled() -> Self {
+        Account {
+            name: String::from("all asset accounts"),
+            expense_envelopes: Vec::new(),
+            goal_envelopes: Vec::new(),
+            real_value: AmountPool::new(),
+            buffer: AmountPool::new(),
+        }
+    }
+
+    /// Sets the value of an account made with `pooled`, which its envelopes are funded from
+    pub fn set_pooled_value(&mut self, value: AmountPool) {
+        self.real_value = value;
+    }
+
     // returns the name of the account
     fn parse_header(mut line: &str) -> Result<String, ParseError> {
         // remove comments
@@ -111,6 +169,49 @@ impl Account {
         &self.name
     }
 
+    /// Returns the real value of the account, which is the total of every posting to it that's
+    /// been processed. Envelope postings aren't included.
+    pub fn get_real_value(&self) -> &AmountPool {
+        &self.real_value
+    }
+
+    /// Returns a String that can be written in a file and parsed later on, giving the same account
+    /// and envelope definitions.
+    pub fn as_parsable(&self, date_format: &str) -> String {
+        let mut s = format!("account {}\n", self.name);
+
+        for amount in self.buffer.iter() {
+            s.push_str(&format!("    buffer {}\n", amount));
+        }
+
+        for envelope in self
+            .expense_envelopes
+            .iter()
+            .chain(self.goal_envelopes.iter())
+        {
+            for line in envelope.as_parsable(date_format).lines() {
+                s.push_str(&format!("    {}\n", line));
+            }
+        }
+
+        s
+    }
+
+    /// Returns all of this account's envelopes, expenses first, then goals
+    pub fn get_envelopes(&self) -> impl Iterator<Item = &Envelope> {
+        self.expense_envelopes
+            .iter()
+            .chain(self.goal_envelopes.iter())
+    }
+
+    /// Returns the envelope with the name given, if it exists in this account.
+    pub fn get_envelope(&self, name: &str) -> Option<&Envelope> {
+        self.expense_envelopes
+            .iter()
+            .chain(self.goal_envelopes.iter())
+            .find(|e| e.get_name() == name)
+    }
+
     pub fn add_envelope(&mut self, envelope: Envelope) -> Result<(), ValidationError> {
         let envelope_collection = match envelope.get_type() {
             EnvelopeType::Expense => &mut self.expense_envelopes,
@@ -135,9 +236,48 @@ impl Account {
         }
     }
 
+    /// Adds the envelopes of `other` (another definition of this same account) that this account
+    /// doesn't have yet. Envelopes are matched by name; if both accounts have an envelope with the
+    /// same name, this account's definition is kept. The same goes for buffers.
+    pub fn merge(&mut self, other: Account) {
+        for envelope in other
+            .expense_envelopes
+            .into_iter()
+            .chain(other.goal_envelopes)
+        {
+            // duplicates are exactly what's being skipped here, so the error doesn't matter
+            let _ = self.add_envelope(envelope);
+        }
+
+        if self.buffer.is_empty() {
+            self.buffer = other.buffer;
+        }
+    }
+
+    /// Clears the account's value and empties its envelopes, as if no entries had been processed
+    pub fn reset(&mut self) {
+        self.real_value = AmountPool::new();
+        for envelope in self
+            .expense_envelopes
+            .iter_mut()
+            .chain(self.goal_envelopes.iter_mut())
+        {
+            envelope.reset();
+        }
+    }
+
     /// Processes the Entry by looking for any changes to envelope amounts and applying them. Also
-    /// adds to the real_value of the Account.
+    /// adds to the real_value of the Account, checking any balance assertions along the way.
     pub fn process_entry(&mut self, entry: &Entry) -> Result<(), ProcessingError> {
+        self.process(entry, true)
+    }
+
+    /// Processes the Entry like `process_entry`, without checking its balance assertions
+    pub fn process_entry_unchecked(&mut self, entry: &Entry) -> Result<(), ProcessingError> {
+        self.process(entry, false)
+    }
+
+    fn process(&mut self, entry: &Entry, check_assertions: bool) -> Result<(), ProcessingError> {
         for envelope in self
             .expense_envelopes
             .iter_mut()
@@ -148,17 +288,13 @@ impl Account {
 
         for posting in entry.get_postings() {
             if *posting.get_account() == self.name && !posting.is_envelope() {
-                if let Some(a) = posting.get_amount() {
-                    self.real_value += a;
-                } else {
-                    match entry.get_blank_amount() {
-                        Ok(o) => {
-                            if let Some(a) = o {
-                                self.real_value += a;
-                            }
-                        }
-                        Err(e) => return Err(e),
+                self.real_value += entry.get_posting_amount(posting)?;
+
+                match posting.get_balance_assertion() {
+                    Some(asserted) if check_assertions => {
+                        self.check_balance_assertion(asserted, entry)?
                     }
+                    _ => (),
                 }
             }
         }
@@ -166,47 +302,182 @@ impl Account {
         Ok(())
     }
 
-    pub fn display_envelopes(&self) {
+    /// Returns an error if this account's balance in the currency of `asserted` isn't `asserted`.
+    /// Only that currency is checked; the account can hold any amount of others.
+    fn check_balance_assertion(
+        &self,
+        asserted: &Amount,
+        entry: &Entry,
+    ) -> Result<(), ProcessingError> {
+        let actual = Amount {
+            mag: self.real_value.only(&asserted.symbol).mag,
+            symbol: asserted.symbol.clone(),
+        };
+        if (actual.clone() - asserted).rounds_to_zero() {
+            return Ok(());
+        }
+
+        Err(ProcessingError::default()
+            .set_message(&format!(
+                "this entry asserts that `{}` has {}, but it has {}",
+                self.name,
+                asserted.to_string().trim_start(),
+                actual.to_string().trim_start()
+            ))
+            .set_context(&entry.as_full_string()))
+    }
+
+    /// Displays this account's envelopes. If `compact` is true, each envelope is displayed on a
+    /// single line. If `relative_to` is given, due dates are displayed relative to it.
+    pub fn display_envelopes(
+        &self,
+        compact: bool,
+        relative_to: Option<NaiveDate>,
+        color: ColorChoice,
+    ) {
+        self.write_envelopes(io::stdout(), compact, relative_to, color)
+            .unwrap();
+    }
+
+    /// Writes this account's envelopes, along with how much money is available and left to
+    /// assign. Nothing is written if the account has no envelopes.
+    pub fn write_envelopes<W: Write>(
+        &self,
+        mut writer: W,
+        compact: bool,
+        relative_to: Option<NaiveDate>,
+        color: ColorChoice,
+    ) -> io::Result<()> {
         // if no envelopes to display, quit
         if self.expense_envelopes.is_empty() && self.goal_envelopes.is_empty() {
-            return;
+            return Ok(());
         }
 
         // displays account name at top
-        println!("{}", self.name);
+        writeln!(writer, "{}", self.name)?;
+
+        // the money left to assign is the number that matters most, so it goes first
+        for (line, overspent) in self.get_unassigned_summary() {
+            if overspent {
+                writeln!(writer, "  {}", color.red(&line))?;
+            } else {
+                writeln!(writer, "  {}", color.green(&line))?;
+            }
+        }
 
         // display available balance
-        println!("  available");
+        writeln!(writer, "  available")?;
         let available_value = self.get_available_value();
         for amount in available_value.iter() {
             if amount.mag == 0.0 {
                 continue;
             }
-            println!("    {}", amount)
+            writeln!(writer, "    {}", amount)?;
         }
 
         // display expenses
         if !self.expense_envelopes.is_empty() {
-            println!("  expenses");
+            writeln!(writer, "  expenses")?;
             for envelope in self.expense_envelopes.iter() {
-                println!("{}", envelope);
+                Self::write_envelope(&mut writer, envelope, compact, relative_to)?;
             }
         }
 
-        // display goals
-        if !self.goal_envelopes.is_empty() {
-            println!("  goals");
-            for envelope in self.goal_envelopes.iter() {
-                println!("{}", envelope);
+        // display goals, with the one-time goals that are fully funded in a section of their own
+        let (achieved, goals): (Vec<&Envelope>, Vec<&Envelope>) =
+            self.goal_envelopes.iter().partition(|e| e.is_achieved());
+        for (heading, envelopes) in [("goals", goals), ("achieved", achieved)] {
+            if envelopes.is_empty() {
+                continue;
+            }
+
+            writeln!(writer, "  {}", heading)?;
+            for envelope in envelopes {
+                Self::write_envelope(&mut writer, envelope, compact, relative_to)?;
             }
         }
 
-        println!(); // do not remove; this is a separator
+        writeln!(writer) // do not remove; this is a separator
     }
 
-    pub fn get_filling_postings(&self) -> Vec<Posting> {
+    /// Displays how much needs to go into each of this account's envelopes to fill them by their
+    /// due dates (see `Envelope::contribution_plan`)
+    pub fn display_contribution_plans(&self, as_of: NaiveDate, date_format: &str) {
+        let plans = self
+            .expense_envelopes
+            .iter()
+            .chain(self.goal_envelopes.iter())
+            .filter_map(|e| e.contribution_plan(as_of).map(|p| (e.get_name(), p)))
+            .collect::<Vec<_>>();
+
+        if plans.is_empty() {
+            return;
+        }
+
+        println!("{}", self.name);
+        for (name, (amount, due_date)) in plans {
+            let plan = if amount.mag == 0.0 {
+                String::from("full")
+            } else {
+                match due_date {
+                    Some(d) => format!("{} a day until {}", amount, d.format(date_format)),
+                    None => format!("{} now (past due)", amount),
+                }
+            };
+
+            println!(
+                "    {:20} {}",
+                utils::truncate_with_ellipsis(name, 20),
+                plan
+            );
+        }
+
+        println!(); // separator
+    }
+
+    fn write_envelope<W: Write>(
+        mut writer: W,
+        envelope: &Envelope,
+        compact: bool,
+        relative_to: Option<NaiveDate>,
+    ) -> io::Result<()> {
+        match (compact, relative_to) {
+            (true, _) => writeln!(writer, "{:#}", envelope),
+            (false, Some(d)) => writeln!(writer, "{}", envelope.relative_to(d)),
+            (false, None) => writeln!(writer, "{}", envelope),
+        }
+    }
+
+    /// Renames this account and the accounts its envelopes refer to (see
+    /// `utils::renamed_account`)
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(n) = utils::renamed_account(&self.name, old, new) {
+            self.name = n;
+        }
+
+        for envelope in self
+            .expense_envelopes
+            .iter_mut()
+            .chain(self.goal_envelopes.iter_mut())
+        {
+            envelope.rename_account(old, new);
+        }
+    }
+
+    /// Returns postings that fill this account's envelopes for `today`
+    pub fn get_filling_postings(&self, today: NaiveDate) -> Vec<Posting> {
+        self.get_filling_postings_from(self.get_fillable_value(), today)
+            .0
+    }
+
+    /// Returns postings that fill this account's envelopes for `today` with `available_value`,
+    /// along with whatever is left of `available_value` after they're filled
+    pub fn get_filling_postings_from(
+        &self,
+        mut available_value: AmountPool,
+        today: NaiveDate,
+    ) -> (Vec<Posting>, AmountPool) {
         let mut postings: Vec<Posting> = Vec::new();
-        let mut available_value = self.get_available_value();
 
         // create an iterator, then reverse it so that envelopes are drained more safely in case
         // the account's available value is negative. goals will be drained first, starting at the
@@ -216,14 +487,25 @@ impl Account {
             .iter()
             .chain(self.goal_envelopes.iter());
         for envelope in iter.rev() {
-            let new_posting = Posting::from(envelope.get_filling_posting(&available_value));
+            let new_posting = Posting::from(envelope.get_filling_posting(&available_value, today));
             if let Some(new_amount) = new_posting.get_amount() {
-                available_value -= new_amount;
+                available_value -= &envelope.to_funding_currency(new_amount);
                 postings.push(new_posting);
             }
         }
 
-        postings
+        (postings, available_value)
+    }
+
+    /// Returns the available value of this account above its buffer, which is what envelopes can
+    /// be filled with
+    pub fn get_fillable_value(&self) -> AmountPool {
+        let mut available_value = self.get_available_value();
+        for amount in self.buffer.iter() {
+            available_value -= amount;
+        }
+
+        available_value
     }
 
     pub fn get_available_value(&self) -> AmountPool {
@@ -233,17 +515,43 @@ impl Account {
             .iter()
             .chain(self.goal_envelopes.iter())
         {
-            amount_pool = amount_pool - envelope.get_next_amount() - envelope.get_now_amount();
+            // envelopes in other currencies are paid for in the account's currency
+            amount_pool = amount_pool
+                - &envelope.to_funding_currency(envelope.get_next_amount())
+                - &envelope.to_funding_currency(envelope.get_now_amount());
         }
 
         amount_pool
     }
+
+    /// Returns a line for each currency in the available value (see `get_available_value`),
+    /// saying how much is left to assign to envelopes ("unassigned") or how much envelopes have
+    /// been given beyond what the account has ("overspent"). The second value of each tuple is
+    /// true if the line is an overspent line.
+    pub fn get_unassigned_summary(&self) -> Vec<(String, bool)> {
+        self.get_available_value()
+            .iter()
+            .filter(|a| a.mag != 0.0)
+            .map(|a| {
+                if a.mag > 0.0 {
+                    (format!("{} unassigned", a.to_string().trim_start()), false)
+                } else {
+                    let overspent = -a.clone();
+                    (
+                        format!("{} overspent", overspent.to_string().trim_start()),
+                        true,
+                    )
+                }
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::envelope::Frequency;
+    use std::collections::HashSet;
 
     const ACCOUNT_STR: &str = "account assets:checking
              goal yearly_goal due every year starting 2020/2/20
@@ -297,6 +605,165 @@ mod tests {
         }
     }
 
+    #[test]
+    fn achieved_goals_test() {
+        let account_str = "account assets:checking
+             goal camera by 2100/01/01
+                 amount 100
+             goal boat by 2100/01/01
+                 amount 5000";
+
+        let checking_name = String::from("assets:checking");
+        let accounts: HashSet<&String> = [&checking_name].iter().cloned().collect();
+        let entry = Entry::parse(
+            "2020/01/01 * move to envelopes
+                envelope assets:checking camera 100",
+            DEFAULT_DATE_FORMAT,
+            '.',
+            &accounts,
+        )
+        .unwrap();
+
+        let mut account = Account::parse(account_str, '.', DEFAULT_DATE_FORMAT).unwrap();
+        account.process_entry(&entry).unwrap();
+
+        let mut buffer = Vec::new();
+        account
+            .write_envelopes(&mut buffer, true, None, ColorChoice::Never)
+            .unwrap();
+        let written = String::from_utf8(buffer).unwrap();
+
+        // the boat is still a goal, but the camera has its own section
+        let goals_at = written.find("  goals\n").unwrap();
+        let achieved_at = written.find("  achieved\n").unwrap();
+        let boat_at = written.find("boat").unwrap();
+        let camera_at = written.find("camera").unwrap();
+        assert!(goals_at < boat_at && boat_at < achieved_at && achieved_at < camera_at);
+    }
+
+    #[test]
+    fn buffer_test() {
+        let account_str = "account assets:checking
+             buffer 500
+             expense rent due every 5th
+                 amount 300
+                 funding aggressive";
+
+        let mut accounts: HashSet<&String> = HashSet::new();
+        let checking_name = String::from("assets:checking");
+        let income_name = String::from("income:salary");
+        accounts.insert(&checking_name);
+        accounts.insert(&income_name);
+
+        let entry = match Entry::parse(
+            "2020/01/01 * Paycheck
+                assets:checking     600
+                income:salary",
+            DEFAULT_DATE_FORMAT,
+            '.',
+            &accounts,
+        ) {
+            Ok(e) => e,
+            Err(e) => panic!("{}", e),
+        };
+
+        let mut account = match Account::parse(account_str, '.', DEFAULT_DATE_FORMAT) {
+            Ok(a) => a,
+            Err(e) => panic!("{}", e),
+        };
+        account.process_entry(&entry).unwrap();
+
+        // only the 100 above the buffer can go to the envelope
+        let postings = account.get_filling_postings(NaiveDate::from_ymd(2020, 1, 15));
+        assert_eq!(postings.len(), 1);
+        assert_eq!(postings[0].get_amount().unwrap().mag, 100.0);
+
+        // without the buffer, the whole envelope is filled
+        account.buffer = AmountPool::new();
+        let postings = account.get_filling_postings(NaiveDate::from_ymd(2020, 1, 15));
+        assert_eq!(postings[0].get_amount().unwrap().mag, 300.0);
+    }
+
+    #[test]
+    fn foreign_currency_envelope_test() {
+        let account_str = "account assets:checking
+             goal trip due every year starting 2020/6/1
+                 amount 1000
+                 currency EUR
+                 price $1.25
+                 funding aggressive";
+
+        let mut accounts: HashSet<&String> = HashSet::new();
+        let checking_name = String::from("assets:checking");
+        let income_name = String::from("income:salary");
+        accounts.insert(&checking_name);
+        accounts.insert(&income_name);
+
+        let parse_entry = |s: &str| match Entry::parse(s, DEFAULT_DATE_FORMAT, '.', &accounts) {
+            Ok(e) => e,
+            Err(e) => panic!("{}", e),
+        };
+
+        let mut account = match Account::parse(account_str, '.', DEFAULT_DATE_FORMAT) {
+            Ok(a) => a,
+            Err(e) => panic!("{}", e),
+        };
+        account
+            .process_entry(&parse_entry(
+                "2020/01/01 * Paycheck
+                assets:checking     $2500
+                income:salary",
+            ))
+            .unwrap();
+
+        // $2500 buys 2000 EUR, which is plenty to fill the envelope
+        let postings = account.get_filling_postings(NaiveDate::from_ymd(2020, 1, 15));
+        assert_eq!(postings.len(), 1);
+        let amount = postings[0].get_amount().unwrap();
+        assert_eq!(amount.symbol, Some(String::from("EUR")));
+        assert_eq!(amount.mag, 1000.0);
+
+        // once filled, the envelope's 1000 EUR cost $1250 of the account's money
+        account
+            .process_entry(&parse_entry(
+                "2020/01/02 * Fill
+                envelope assets:checking trip 1000 EUR",
+            ))
+            .unwrap();
+        let available = account.get_available_value();
+        assert_eq!(available.only(&Some(String::from("$"))).mag, 1250.0);
+        assert_eq!(available.only(&Some(String::from("EUR"))).mag, 0.0);
+    }
+
+    #[test]
+    fn unassigned_summary_test() {
+        let mut account = match Account::parse(ACCOUNT_STR, '.', DEFAULT_DATE_FORMAT) {
+            Ok(a) => a,
+            Err(e) => panic!("{}", e),
+        };
+
+        // nothing to report for an empty account
+        assert!(account.get_unassigned_summary().is_empty());
+
+        account.real_value = AmountPool::from(Amount {
+            mag: 250.0,
+            symbol: Some(String::from("$")),
+        });
+        assert_eq!(
+            account.get_unassigned_summary(),
+            vec![(String::from("$ 250 unassigned"), false)]
+        );
+
+        account.real_value = AmountPool::from(Amount {
+            mag: -40.0,
+            symbol: Some(String::from("$")),
+        });
+        assert_eq!(
+            account.get_unassigned_summary(),
+            vec![(String::from("$ 40 overspent"), true)]
+        );
+    }
+
     #[test]
     fn blank_account_test() {
         let result = Account::parse(BLANK_ACCOUNT_STR, '.', DEFAULT_DATE_FORMAT);
@@ -308,4 +775,50 @@ mod tests {
         let result = Account::parse(ACCOUNT_WITH_SPACES_STR, '.', DEFAULT_DATE_FORMAT);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn balance_assertion_test() {
+        let mut accounts: HashSet<&String> = HashSet::new();
+        let checking_name = String::from("assets:checking");
+        let income_name = String::from("income:salary");
+        accounts.insert(&checking_name);
+        accounts.insert(&income_name);
+        let entry = |s: &str| Entry::parse(s, DEFAULT_DATE_FORMAT, '.', &accounts).unwrap();
+
+        let mut account =
+            Account::parse("account assets:checking", '.', DEFAULT_DATE_FORMAT).unwrap();
+        account
+            .process_entry(&entry(
+                "2020/01/01 * Paycheck
+                    assets:checking     1234.56 ! 1234.56
+                    income:salary",
+            ))
+            .unwrap();
+
+        // other currencies don't count toward the assertion
+        account
+            .process_entry(&entry(
+                "2020/01/02 * Gift
+                    assets:checking     20 EUR
+                    income:salary      -20 EUR",
+            ))
+            .unwrap();
+        account
+            .process_entry(&entry(
+                "2020/01/03 * Paycheck
+                    assets:checking     0.1 ! 1234.66
+                    income:salary",
+            ))
+            .unwrap();
+
+        let err = account
+            .process_entry(&entry(
+                "2020/01/04 * Paycheck
+                    assets:checking     100 ! 1243.66
+                    income:salary",
+            ))
+            .unwrap_err();
+        assert!(err.message.unwrap().contains("1334.66"));
+        assert!(err.context.unwrap().contains("2020-01-04"));
+    }
 }
@@ -1,7 +1,228 @@
 use crate::errors::*;
+use crate::utils;
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
 use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::RwLock;
+
+/// The number of decimal places that amounts are rounded to when they're displayed, if their
+/// currency's precision isn't known
+const DISPLAY_PLACES: i32 = 8;
+
+/// Whether `RoundingMode::HalfEven` is the rounding mode used for displaying amounts. Amounts are
+/// displayed in a lot of places that don't know about any options, so this is set once for the
+/// whole program (see `RoundingMode::set_default`).
+static ROUND_HALF_EVEN: AtomicBool = AtomicBool::new(false);
+
+/// How amounts are displayed. Like the rounding mode, this is set once for the whole program (see
+/// `AmountFormat::set_default`).
+static DISPLAY_FORMAT: RwLock<AmountFormat> = RwLock::new(AmountFormat::PLAIN);
+
+thread_local! {
+    /// Whether amounts are being written to be parsed again (see `AmountFormat::plain_scope`)
+    static WRITING_PLAIN: Cell<bool> = const { Cell::new(false) };
+
+    /// The most decimal places that amounts in each currency have been written with (see
+    /// `Amount::display_places`)
+    static CURRENCY_PLACES: RefCell<HashMap<Option<String>, i32>> = RefCell::new(HashMap::new());
+}
+
+/// The locale presets that amounts can be displayed with
+const LOCALES: &[(&str, AmountFormat)] = &[
+    ("en-US", AmountFormat::new('.', Some(','), false)),
+    ("en-GB", AmountFormat::new('.', Some(','), false)),
+    ("de-DE", AmountFormat::new(',', Some('.'), true)),
+    ("de-CH", AmountFormat::new('.', Some('\''), false)),
+    ("es-ES", AmountFormat::new(',', Some('.'), true)),
+    ("fr-FR", AmountFormat::new(',', Some(' '), true)),
+    ("it-IT", AmountFormat::new(',', Some('.'), true)),
+    ("ja-JP", AmountFormat::new('.', Some(','), false)),
+    ("pt-BR", AmountFormat::new(',', Some('.'), false)),
+];
+
+/// How the magnitude of an amount is written, and where its symbol goes. This only changes how
+/// amounts are displayed; amounts written to journals are always written plainly, so they can be
+/// parsed again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmountFormat {
+    /// The character between whole numbers and fractions
+    pub decimal_symbol: char,
+
+    /// The character between groups of three digits, if there is one
+    pub grouping: Option<char>,
+
+    /// Whether short symbols (like `$`) go after the magnitude instead of before it. Longer
+    /// symbols (like `BTC`) always go after.
+    pub symbol_after: bool,
+}
+
+impl AmountFormat {
+    /// Amounts as silverfox parses them: `.` decimals without grouping, like `$1234.5`
+    pub const PLAIN: Self = Self::new('.', None, false);
+
+    const fn new(decimal_symbol: char, grouping: Option<char>, symbol_after: bool) -> Self {
+        Self {
+            decimal_symbol,
+            grouping,
+            symbol_after,
+        }
+    }
+
+    /// Returns the preset for a locale like `en-US` or `de-DE`
+    pub fn from_locale(locale: &str) -> Result<Self, SilverfoxError> {
+        let locale = locale.replace('_', "-");
+        match LOCALES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&locale))
+        {
+            Some((_, format)) => Ok(*format),
+            None => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't a locale silverfox knows. try one of these: {}",
+                locale,
+                LOCALES
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
+    /// Returns the format amounts are displayed with right now. Amounts being written to be
+    /// parsed again are always plain.
+    pub fn get_default() -> Self {
+        if WRITING_PLAIN.with(|p| p.get()) {
+            return Self::PLAIN;
+        }
+
+        DISPLAY_FORMAT.read().map(|f| *f).unwrap_or(Self::PLAIN)
+    }
+
+    /// Sets the format used for displaying amounts
+    pub fn set_default(self) {
+        if let Ok(mut f) = DISPLAY_FORMAT.write() {
+            *f = self;
+        }
+    }
+
+    /// Makes amounts display plainly until the returned scope is dropped. Anything that writes
+    /// amounts to be parsed again (like entries appended to the journal) holds one of these.
+    pub fn plain_scope() -> PlainScope {
+        PlainScope {
+            was_plain: WRITING_PLAIN.with(|p| p.replace(true)),
+        }
+    }
+
+    /// Writes `number`, as Rust writes an f64 (maybe with a sign or a leading space), with this
+    /// format's decimal symbol and grouping
+    fn localize(self, number: &str) -> String {
+        if self == Self::PLAIN {
+            return number.to_string();
+        }
+
+        let (whole, fraction) = match number.split_once('.') {
+            Some((w, f)) => (w, Some(f)),
+            None => (number, None),
+        };
+        let digits_start = whole
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(whole.len());
+        let (sign, digits) = whole.split_at(digits_start);
+
+        let mut s = String::from(sign);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                s.extend(self.grouping);
+            }
+            s.push(c);
+        }
+
+        if let Some(f) = fraction {
+            s.push(self.decimal_symbol);
+            s.push_str(f);
+        }
+
+        s
+    }
+}
+
+/// Keeps amounts displaying plainly while it's alive (see `AmountFormat::plain_scope`)
+pub struct PlainScope {
+    was_plain: bool,
+}
+
+impl Drop for PlainScope {
+    fn drop(&mut self) {
+        let was_plain = self.was_plain;
+        WRITING_PLAIN.with(|p| p.set(was_plain));
+    }
+}
+
+/// How numbers exactly halfway between two others are rounded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RoundingMode {
+    /// Halves are rounded away from zero, so 2.5 becomes 3
+    #[default]
+    HalfUp,
+
+    /// Halves are rounded to the nearest even number, so 2.5 becomes 2 (banker's rounding)
+    HalfEven,
+}
+
+impl RoundingMode {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "half-up" => Ok(Self::HalfUp),
+            "half-even" | "bankers" => Ok(Self::HalfEven),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't a rounding mode silverfox knows. try `half-up` or `half-even`",
+                s
+            ))),
+        }
+    }
+
+    /// Returns the rounding mode used for displaying amounts
+    pub fn get_default() -> Self {
+        if ROUND_HALF_EVEN.load(AtomicOrdering::Relaxed) {
+            Self::HalfEven
+        } else {
+            Self::HalfUp
+        }
+    }
+
+    /// Sets the rounding mode used for displaying amounts
+    pub fn set_default(self) {
+        ROUND_HALF_EVEN.store(self == Self::HalfEven, AtomicOrdering::Relaxed);
+    }
+
+    /// Rounds `x` to `places` decimal places
+    pub fn round(self, x: f64, places: i32) -> f64 {
+        let factor = 10_f64.powi(places);
+
+        // floating point error can put a half like 2.675 just under it (267.49999999999997 when
+        // scaled), so the noise is rounded off before the half is
+        let scaled = (x * factor * 1e6).round() / 1e6;
+
+        let rounded = match self {
+            Self::HalfUp => scaled.round(),
+            Self::HalfEven => {
+                let truncated = scaled.trunc();
+                if (scaled - truncated).abs() != 0.5 {
+                    scaled.round()
+                } else if truncated % 2.0 == 0.0 {
+                    truncated
+                } else {
+                    truncated + scaled.signum()
+                }
+            }
+        };
+
+        rounded / factor
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Amount {
@@ -27,6 +248,15 @@ impl Amount {
             }
         };
 
+        // accounting statements write negatives in parentheses, like `(100.00)` or `($100.00)`
+        let (clump, parenthesized) = match (clump.find('('), clump.rfind(')')) {
+            (Some(i), Some(j)) if i < j => (
+                format!("{}{}{}", &clump[..i], &clump[i + 1..j], &clump[j + 1..]),
+                true,
+            ),
+            _ => (clump, false),
+        };
+
         // parse amount and currency in the same chunk
         // parse magnitude
         let mut raw_mag = clump
@@ -38,7 +268,13 @@ impl Amount {
             raw_mag = raw_mag.replace(decimal_symbol, ".");
         }
 
-        let mag = match raw_mag.parse::<f64>() {
+        // a sign can go before the symbol, after it, or both (`-$100`, `$-100`, or `-$-100`), so
+        // the signs are counted apart from the number
+        let unsigned_mag = raw_mag.trim_start_matches('-');
+        let negative = (raw_mag.len() - unsigned_mag.len()) % 2 == 1;
+
+        let mag = match unsigned_mag.parse::<f64>() {
+            Ok(m) if negative != parenthesized => -m,
             Ok(m) => m,
             Err(_) => {
                 return Err(ParseError {
@@ -59,9 +295,80 @@ impl Amount {
             _ => Some(trimmed_raw_sym.to_string()),
         };
 
+        let places = unsigned_mag
+            .find('.')
+            .map_or(0, |i| unsigned_mag.len() - i - 1);
+        Self::note_display_places(&symbol, places as i32);
+
         Ok(Self { mag, symbol })
     }
 
+    /// Remembers that an amount in `symbol` was written with `places` decimal places, so that
+    /// amounts in that currency are displayed with at least that many (see `display_places`)
+    fn note_display_places(symbol: &Option<String>, places: i32) {
+        CURRENCY_PLACES.with(|p| {
+            let mut p = p.borrow_mut();
+            let known = p.entry(symbol.clone()).or_insert(0);
+            *known = (*known).max(places);
+        });
+    }
+
+    /// Returns how many decimal places this amount is rounded to when it's displayed: the most
+    /// that any amount in its currency has been written with, but at least 2, like cents. Amounts
+    /// in currencies that have never been parsed are only cleaned of floating point noise.
+    fn display_places(&self) -> i32 {
+        CURRENCY_PLACES.with(|p| {
+            p.borrow()
+                .get(&self.symbol)
+                .map_or(DISPLAY_PLACES, |&places| places.max(2))
+        })
+    }
+
+    /// Returns an amount from a whole number of minor units, like cents. `minor_digits` is how
+    /// many digits the minor unit takes up after the decimal point: 2 for USD (1999 cents is 19.99)
+    /// or 0 for JPY, which has no minor unit.
+    pub fn from_minor_units(units: i64, symbol: Option<String>, minor_digits: u32) -> Self {
+        // dividing is exact to the nearest float, unlike multiplying by something like 0.01
+        Amount {
+            mag: units as f64 / 10_f64.powi(minor_digits as i32),
+            symbol,
+        }
+    }
+
+    /// Splits this amount into `n` parts that are as even as possible and add up to exactly this
+    /// amount. Parts are rounded to the amount's minor unit (see `minor_digits`), and the units
+    /// left over from rounding go to the first parts, one each (the largest remainder method),
+    /// so $100 split three ways is $33.34, $33.33, and $33.33. Returns no parts if `n` is zero.
+    pub fn split_evenly(&self, n: usize) -> Vec<Amount> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        let minor_digits = self.minor_digits();
+        let units = (self.mag * 10_f64.powi(minor_digits as i32)).round() as i64;
+        let n = n as i64;
+
+        // every part has the same remainder, so ties go to the first parts
+        let (base, remainder) = (units.abs() / n, units.abs() % n);
+        (0..n)
+            .map(|i| {
+                let part = if i < remainder { base + 1 } else { base };
+                Self::from_minor_units(units.signum() * part, self.symbol.clone(), minor_digits)
+            })
+            .collect()
+    }
+
+    /// Returns how many digits after the decimal point this amount's smallest unit takes up:
+    /// however many the magnitude has, but at least 2, like cents
+    fn minor_digits(&self) -> u32 {
+        (2..DISPLAY_PLACES as u32)
+            .find(|&d| {
+                let scaled = self.mag * 10_f64.powi(d as i32);
+                (scaled - scaled.round()).abs() < 1e-6
+            })
+            .unwrap_or(DISPLAY_PLACES as u32)
+    }
+
     /// Returns a blank amount without a symbol.
     pub fn zero() -> Self {
         Amount {
@@ -70,28 +377,76 @@ impl Amount {
         }
     }
 
+    /// Returns this amount as a JSON object, like `{"symbol":"$","mag":20.5}`. Amounts without a
+    /// symbol have a `null` symbol.
+    pub fn as_json(&self) -> String {
+        let symbol = match &self.symbol {
+            Some(s) => utils::json_string(s),
+            None => String::from("null"),
+        };
+
+        format!("{{\"symbol\":{},\"mag\":{}}}", symbol, self.mag)
+    }
+
+    /// Subtracts `other` from this Amount. Unlike the `-` operator, this doesn't panic if the
+    /// symbols differ; the result is a pool with both amounts instead.
+    pub fn sub_into_pool(self, other: Amount) -> AmountPool {
+        let mut pool = AmountPool::from(self);
+        pool -= &other;
+
+        pool
+    }
+
     /// Returns true if the char not a magnitude character, dot, or comma.
     fn is_symbol_char(c: char, decimal_symbol: char) -> bool {
         !is_mag_char(c, decimal_symbol) && c != '.' && c != ','
     }
 
-    fn rounded_mag(&self) -> f64 {
-        (self.mag * 100_000_000.0).round() / 100_000_000.0
+    /// Returns the magnitude rounded to its currency's precision (see `display_places`), which
+    /// also gets rid of floating-point noise like the 4 in 0.30000000000000004. The default
+    /// rounding mode is used (see `RoundingMode::set_default`).
+    pub fn rounded_mag(&self) -> f64 {
+        self.rounded_mag_with(RoundingMode::get_default())
+    }
+
+    fn rounded_mag_with(&self, rounding: RoundingMode) -> f64 {
+        // adding zero turns -0 into 0, so that tiny negative amounts don't display as `-0`
+        rounding.round(self.mag, self.display_places()) + 0.0
+    }
+
+    /// Returns true if this amount is zero once it's rounded (see `rounded_mag`). Sums that
+    /// should come out to zero, like an entry's postings, are checked with this, so that neither
+    /// floating point error nor anything finer than the currency's precision keeps them from it.
+    pub fn rounds_to_zero(&self) -> bool {
+        self.rounded_mag() == 0.0
     }
 }
 
 impl fmt::Display for Amount {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, AmountFormat::get_default(), RoundingMode::get_default())
+    }
+}
+
+impl Amount {
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        format: AmountFormat,
+        rounding: RoundingMode,
+    ) -> fmt::Result {
+        let mag = self.rounded_mag_with(rounding);
         let mag_fmt = if f.sign_plus() {
-            format!("{:+}", self.rounded_mag())
-        } else if self.mag < 0.0 {
-            format!("{}", self.rounded_mag())
+            format!("{:+}", mag)
+        } else if mag < 0.0 {
+            format!("{}", mag)
         } else {
-            format!(" {}", self.rounded_mag())
+            format!(" {}", mag)
         };
+        let mag_fmt = format.localize(&mag_fmt);
 
         if let Some(sym) = &self.symbol {
-            if sym.len() <= 2 {
+            if sym.len() <= 2 && !format.symbol_after {
                 write!(f, "{}{}", sym, mag_fmt)
             } else {
                 write!(f, "{} {}", mag_fmt, sym)
@@ -313,6 +668,17 @@ impl AddAssign<AmountPool> for AmountPool {
     }
 }
 
+/// negation operator, which negates every amount in the pool
+impl Neg for AmountPool {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self {
+            pool: self.pool.into_iter().map(|a| -a).collect(),
+        }
+    }
+}
+
 impl IntoIterator for AmountPool {
     type Item = Amount;
 
@@ -353,6 +719,23 @@ impl AmountPool {
         self.pool.iter()
     }
 
+    /// Returns the amounts in this pool as a JSON array (see `Amount::as_json`)
+    pub fn as_json(&self) -> String {
+        format!(
+            "[{}]",
+            self.pool
+                .iter()
+                .map(|a| a.as_json())
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    /// Returns a pool with the opposite of every amount in this one
+    pub fn negate(&self) -> Self {
+        -self.clone()
+    }
+
     /// Returns true if either (a) the pool is empty, or (b) all amounts in the pool have zero
     /// magnitiude.
     pub fn is_zero(&self) -> bool {
@@ -396,3 +779,257 @@ impl fmt::Display for AmountPool {
 fn is_mag_char(c: char, decimal_symbol: char) -> bool {
     c.is_digit(10) || c == decimal_symbol || c == '-'
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_minor_units_test() {
+        let usd = Amount::from_minor_units(1999, Some(String::from("USD")), 2);
+        assert_eq!(usd.mag, 19.99);
+        assert_eq!(usd.symbol, Some(String::from("USD")));
+        assert_eq!(Amount::from_minor_units(-5, None, 2).mag, -0.05);
+        assert_eq!(Amount::from_minor_units(10, None, 2).mag, 0.1);
+
+        let jpy = Amount::from_minor_units(1500, Some(String::from("JPY")), 0);
+        assert_eq!(jpy.mag, 1500.0);
+    }
+
+    #[test]
+    fn split_evenly_test() {
+        let minor_units = |parts: &[Amount], digits: i32| {
+            parts
+                .iter()
+                .map(|a| (a.mag * 10_f64.powi(digits)).round() as i64)
+                .collect::<Vec<i64>>()
+        };
+
+        let hundred = Amount {
+            mag: 100.0,
+            symbol: Some(String::from("$")),
+        };
+        let parts = hundred.split_evenly(3);
+        assert_eq!(
+            parts.iter().map(|a| a.mag).collect::<Vec<f64>>(),
+            vec![33.34, 33.33, 33.33]
+        );
+        assert!(parts.iter().all(|a| a.symbol == hundred.symbol));
+        assert_eq!(minor_units(&parts, 2).iter().sum::<i64>(), 10000);
+
+        // negative amounts are split the same way
+        let parts = Amount {
+            mag: -10.0,
+            symbol: None,
+        }
+        .split_evenly(4);
+        assert_eq!(minor_units(&parts, 2), vec![-250, -250, -250, -250]);
+
+        // amounts with more precision are split at their own precision
+        let parts = Amount {
+            mag: 0.0011,
+            symbol: None,
+        }
+        .split_evenly(3);
+        assert_eq!(minor_units(&parts, 4), vec![4, 4, 3]);
+
+        assert!(hundred.split_evenly(0).is_empty());
+    }
+
+    #[test]
+    fn rounding_mode_test() {
+        let round_all = |mode: RoundingMode| {
+            [0.5, 1.5, 2.5, -0.5, -1.5, -2.5]
+                .iter()
+                .map(|x| mode.round(*x, 0))
+                .collect::<Vec<f64>>()
+        };
+
+        assert_eq!(
+            round_all(RoundingMode::HalfUp),
+            [1.0, 2.0, 3.0, -1.0, -2.0, -3.0]
+        );
+        assert_eq!(
+            round_all(RoundingMode::HalfEven),
+            [0.0, 2.0, 2.0, -0.0, -2.0, -2.0]
+        );
+
+        // anything that isn't a half is rounded normally
+        assert_eq!(RoundingMode::HalfEven.round(2.6, 0), 3.0);
+        assert_eq!(RoundingMode::HalfEven.round(0.125, 2), 0.12);
+        assert_eq!(RoundingMode::HalfUp.round(0.125, 2), 0.13);
+
+        // halves that floating point puts just under the half are still halves
+        assert_eq!(RoundingMode::HalfUp.round(2.675, 2), 2.68);
+    }
+
+    #[test]
+    fn display_rounding_test() {
+        // dollars are written with two decimal places here, so they're displayed with two
+        Amount::parse("$10.50", '.').unwrap();
+        let dollars = |mag| Amount {
+            mag,
+            symbol: Some(String::from("$")),
+        };
+        let display =
+            |mag, rounding| Formatted(&dollars(mag), AmountFormat::PLAIN, rounding).to_string();
+
+        assert_eq!(display(2.125, RoundingMode::HalfUp), "$ 2.13");
+        assert_eq!(display(2.125, RoundingMode::HalfEven), "$ 2.12");
+        assert_eq!(display(2.135, RoundingMode::HalfEven), "$ 2.14");
+        assert_eq!(display(-2.125, RoundingMode::HalfEven), "$-2.12");
+        assert_eq!(display(-0.001, RoundingMode::HalfUp), "$ 0");
+
+        // the most precise amount written sets the currency's precision
+        Amount::parse("0.125 ETH", '.').unwrap();
+        let ether = Amount {
+            mag: 0.0625,
+            symbol: Some(String::from("ETH")),
+        };
+        assert_eq!(
+            Formatted(&ether, AmountFormat::PLAIN, RoundingMode::HalfEven).to_string(),
+            " 0.062 ETH"
+        );
+
+        // currencies that were never written are only cleaned of floating point noise
+        let bitcoin = Amount {
+            mag: 0.1 + 0.2,
+            symbol: Some(String::from("BTC")),
+        };
+        assert_eq!(bitcoin.to_string(), " 0.3 BTC");
+
+        assert!(dollars(0.004).rounds_to_zero());
+        assert!(dollars(-0.0049).rounds_to_zero());
+        assert!(!dollars(0.01).rounds_to_zero());
+    }
+
+    #[test]
+    fn sub_into_pool_test() {
+        let usd = Amount::parse("100 USD", '.').unwrap();
+        let eur = Amount::parse("50 EUR", '.').unwrap();
+
+        let pool = usd.clone().sub_into_pool(eur);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.only(&Some(String::from("USD"))).mag, 100.0);
+        assert_eq!(pool.only(&Some(String::from("EUR"))).mag, -50.0);
+
+        // same symbols still combine
+        let pool = usd.clone().sub_into_pool(usd);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.is_zero());
+    }
+
+    #[test]
+    fn negate_test() {
+        let mut pool = AmountPool::new();
+        pool += Amount {
+            mag: 100.0,
+            symbol: Some(String::from("$")),
+        };
+        pool += Amount {
+            mag: -2.5,
+            symbol: Some(String::from("EUR")),
+        };
+        pool += Amount::zero();
+
+        let negated = pool.negate();
+        assert_eq!(negated.len(), 3);
+        assert_eq!(negated.only(&Some(String::from("$"))).mag, -100.0);
+        assert_eq!(negated.only(&Some(String::from("EUR"))).mag, 2.5);
+        assert_eq!(negated.only(&None).mag, 0.0);
+
+        // negating twice gives the original pool back
+        let twice = -negated;
+        for amount in pool.iter() {
+            assert_eq!(twice.only(&amount.symbol).mag, amount.mag);
+        }
+    }
+
+    #[test]
+    fn parenthesized_negative_test() {
+        let amount = Amount::parse("(100.00)", '.').unwrap();
+        assert_eq!(amount.mag, -100.0);
+        assert_eq!(amount.symbol, None);
+
+        let amount = Amount::parse("($100.00)", '.').unwrap();
+        assert_eq!(amount.mag, -100.0);
+        assert_eq!(amount.symbol, Some(String::from("$")));
+
+        let amount = Amount::parse("(100.00) USD", '.').unwrap();
+        assert_eq!(amount.mag, -100.0);
+        assert_eq!(amount.symbol, Some(String::from("USD")));
+
+        let amount = Amount::parse("(1,5 EUR)", ',').unwrap();
+        assert_eq!(amount.mag, -1.5);
+        assert_eq!(amount.symbol, Some(String::from("EUR")));
+    }
+
+    #[test]
+    fn sign_before_symbol_test() {
+        for s in &["-$100", "-100$", "$-100", "- $100", "-USD 100"] {
+            assert_eq!(Amount::parse(s, '.').unwrap().mag, -100.0, "{}", s);
+        }
+
+        let amount = Amount::parse("-$100", '.').unwrap();
+        assert_eq!(amount.symbol, Some(String::from("$")));
+
+        // signs on both sides of the symbol cancel out
+        assert_eq!(Amount::parse("-$-100", '.').unwrap().mag, 100.0);
+        assert_eq!(Amount::parse("(-$100)", '.').unwrap().mag, 100.0);
+
+        // but a sign in the middle of the number isn't a sign
+        assert!(Amount::parse("$1-00", '.').is_err());
+    }
+
+    /// Displays an amount with a format and rounding mode other than the default
+    struct Formatted<'a>(&'a Amount, AmountFormat, RoundingMode);
+
+    impl fmt::Display for Formatted<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_with(f, self.1, self.2)
+        }
+    }
+
+    #[test]
+    fn locale_test() {
+        let dollars = Amount {
+            mag: -1234567.5,
+            symbol: Some(String::from("$")),
+        };
+        let bitcoin = Amount {
+            mag: 1234.5,
+            symbol: Some(String::from("BTC")),
+        };
+
+        let us = AmountFormat::from_locale("en-US").unwrap();
+        assert_eq!(
+            Formatted(&dollars, us, RoundingMode::HalfUp).to_string(),
+            "$-1,234,567.5"
+        );
+        assert_eq!(
+            Formatted(&bitcoin, us, RoundingMode::HalfUp).to_string(),
+            " 1,234.5 BTC"
+        );
+
+        let de = AmountFormat::from_locale("de_DE").unwrap();
+        assert_eq!(
+            Formatted(&dollars, de, RoundingMode::HalfUp).to_string(),
+            "-1.234.567,5 $"
+        );
+        assert_eq!(
+            format!("{:+}", Formatted(&bitcoin, de, RoundingMode::HalfUp)),
+            "+1.234,5 BTC"
+        );
+
+        // plain amounts are written the way they're parsed
+        assert_eq!(
+            Formatted(&dollars, AmountFormat::PLAIN, RoundingMode::HalfUp).to_string(),
+            "$-1234567.5"
+        );
+
+        match AmountFormat::from_locale("xx-XX") {
+            Err(e) => assert!(e.to_string().contains("en-US, en-GB, de-DE")),
+            Ok(_) => panic!("an unknown locale was accepted"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::errors::SilverfoxError;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// When silverfox should color its output
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+
+    /// Color output only if stdout is a terminal
+    #[default]
+    Auto,
+}
+
+impl ColorChoice {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            "auto" => Ok(Self::Auto),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't a color choice silverfox knows. try `always`, `never`, or `auto`",
+                s
+            ))),
+        }
+    }
+
+    /// Returns true if output should be colored with this choice
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => terminal_size::terminal_size().is_some(),
+        }
+    }
+
+    /// Returns `s` in red if coloring is enabled, or `s` as-is otherwise
+    pub fn red(self, s: &str) -> String {
+        self.paint(RED, s)
+    }
+
+    /// Returns `s` in green if coloring is enabled, or `s` as-is otherwise
+    pub fn green(self, s: &str) -> String {
+        self.paint(GREEN, s)
+    }
+
+    fn paint(self, color: &str, s: &str) -> String {
+        if self.is_enabled() {
+            format!("{}{}{}", color, s, RESET)
+        } else {
+            s.to_string()
+        }
+    }
+}
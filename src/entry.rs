@@ -1,12 +1,13 @@
+use crate::account::AccountMatcher;
 use crate::amount::{Amount, AmountPool};
 use crate::errors::*;
-use crate::posting::Posting;
+use crate::posting::{ClassicPosting, Posting, PostingFormat, MIN_ACCOUNT_WIDTH};
 use crate::utils;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum EntryStatus {
     /// `?`
     Pending,
@@ -59,17 +60,29 @@ pub struct Entry {
     payee: Option<String>,
     comment: Option<String>,
 
+    /// Metadata written in comments (like `// key: value`) on their own lines, before any of the
+    /// postings
+    metadata: HashMap<String, String>,
+
+    /// Tags written in comments (like `// :reviewed:`) on their own lines, before any of the
+    /// postings
+    tags: HashSet<String>,
+
     /// The postings in this Entry. This cannot be changed because Accounts and Envelopes process
     /// entries only once. Any modifications to entries can't be reflected elsewhere on the fly.
     postings: Vec<Posting>,
+
+    /// The order this Entry was added to its ledger in (see `set_seq`). Entries on the same date
+    /// are sorted by this, so they stay in the order they were written.
+    seq: usize,
 }
 
 impl fmt::Debug for Entry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Entry {{ date: {}, status: {}, description: {}, payee: {:?}, comment: {:?}, postings: {:?} }}",
-            self.date, self.status, self.description, self.payee, self.comment, self.postings
+            "Entry {{ date: {}, status: {}, description: {}, payee: {:?}, comment: {:?}, metadata: {:?}, tags: {:?}, postings: {:?} }}",
+            self.date, self.status, self.description, self.payee, self.comment, self.metadata, self.tags, self.postings
         )
     }
 }
@@ -95,7 +108,10 @@ impl Entry {
             description,
             payee,
             comment,
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
             postings,
+            seq: 0,
         }
     }
 
@@ -104,6 +120,29 @@ impl Entry {
         date_format: &str,
         decimal_symbol: char,
         accounts: &HashSet<&String>,
+    ) -> Result<Self, SilverfoxError> {
+        Self::parse_with_warnings(chunk, date_format, decimal_symbol, accounts, None)
+    }
+
+    /// Parses an entry like `parse` does, but forgives mistakes that have an obvious fix, like a
+    /// payee without its closing bracket. A warning is added to `warnings` for each one.
+    pub fn parse_forgiving(
+        chunk: &str,
+        date_format: &str,
+        decimal_symbol: char,
+        accounts: &HashSet<&String>,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, SilverfoxError> {
+        Self::parse_with_warnings(chunk, date_format, decimal_symbol, accounts, Some(warnings))
+    }
+
+    /// Parses an entry. Forgivable mistakes are errors unless `warnings` is given.
+    fn parse_with_warnings(
+        chunk: &str,
+        date_format: &str,
+        decimal_symbol: char,
+        accounts: &HashSet<&String>,
+        warnings: Option<&mut Vec<String>>,
     ) -> Result<Self, SilverfoxError> {
         let trimmed_chunk = chunk.trim();
         if trimmed_chunk.is_empty() {
@@ -117,7 +156,7 @@ impl Entry {
 
         // parse the header. parse_header returns the entry to start with
         let mut entry = if let Some(l) = lines.next() {
-            Self::parse_header(l, date_format)?
+            Self::parse_header(l, date_format, warnings)?
         } else {
             let err = ParseError {
                 context: Some(chunk.to_string()),
@@ -128,8 +167,30 @@ impl Entry {
 
         // parse postings
         for raw_posting in lines {
-            // if blank, skip
-            if raw_posting.trim().is_empty() {
+            // lines with only a comment can have metadata and tags for the posting above them, or
+            // for the entry if there aren't any postings yet. blank lines are skipped
+            if utils::remove_comments(raw_posting).trim().is_empty() {
+                let comment = match utils::get_comment(raw_posting) {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                if let Some((key, value)) = utils::parse_metadata(comment) {
+                    match entry.postings.last_mut() {
+                        Some(p) => p.set_metadata(key, value),
+                        None => {
+                            entry.metadata.insert(key, value);
+                        }
+                    }
+                }
+                for tag in utils::parse_tags(comment) {
+                    match entry.postings.last_mut() {
+                        Some(p) => p.add_tag(tag),
+                        None => {
+                            entry.tags.insert(tag);
+                        }
+                    }
+                }
                 continue;
             }
 
@@ -148,7 +209,11 @@ impl Entry {
         Ok(entry)
     }
 
-    fn parse_header(header: &str, date_format: &str) -> Result<Self, ParseError> {
+    fn parse_header(
+        header: &str,
+        date_format: &str,
+        warnings: Option<&mut Vec<String>>,
+    ) -> Result<Self, ParseError> {
         let clean_header = utils::remove_comments(header);
         let header_tokens = clean_header.split_whitespace().collect::<Vec<&str>>();
 
@@ -187,6 +252,17 @@ impl Entry {
                 let d = description_and_payee[..i].trim().to_string();
                 let p = description_and_payee[i + 1..j].trim().to_string();
 
+                (d, Some(p))
+            } else if let Some(warnings) = warnings {
+                // only opening bracket exists. when forgiving, the payee runs to the end of the
+                // line
+                warnings.push(format!(
+                    "the payee in `{}` doesn't have a closing square bracket (]), so silverfox read it to the end of the line",
+                    clean_header.trim()
+                ));
+                let d = description_and_payee[..i].trim().to_string();
+                let p = description_and_payee[i + 1..].trim().to_string();
+
                 (d, Some(p))
             } else {
                 // only opening bracket exists, and that's kind of an issue
@@ -205,10 +281,15 @@ impl Entry {
             date,
             status,
             postings: Vec::new(),
-            comment: None,
+            comment: utils::get_comment(header).map(String::from),
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
+            seq: 0,
         })
     }
 
+    /// Returns the amount that the blank posting of this Entry should have, if there is one.
+    /// Envelope postings are virtual, so they aren't included in this calculation.
     pub fn get_blank_amount(&self) -> Result<Option<Amount>, ProcessingError> {
         if !self.has_blank_posting() {
             // return None if the Entry has no blank amount
@@ -220,7 +301,7 @@ impl Entry {
                 // if any of the native amounts are None, the calculation fails and this function
                 // returns an error
                 let mut blank_amount = Amount::zero();
-                for posting in &self.postings {
+                for posting in self.get_classic_postings() {
                     match posting.get_original_native_value() {
                         Some(v) => blank_amount.mag -= v,
                         None => {
@@ -241,7 +322,7 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
             } else {
                 // for each posting, subtract that posting's amount from the blank amount (as long as
                 // `posting` doesn't have a blank amount)
-                let mut iter = self.postings.iter();
+                let mut iter = self.get_classic_postings();
 
                 // get starting blank amount by finding the first non-blank amount and then
                 // negating it
@@ -254,9 +335,10 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
                         unreachable!()
                     }
                 } else {
-                    // shouldn't be reachable, since there'd better be at least one non-blank
-                    // amount
-                    unreachable!()
+                    // every posting is blank, so there's nothing to balance them with
+                    return Err(ProcessingError::default()
+                        .set_message("silverfox can't infer the amounts of blank postings in an entry where no posting has an amount")
+                        .set_context(&self.as_full_string()));
                 };
 
                 // subtract the rest of the postings
@@ -271,17 +353,52 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
         }
     }
 
+    /// Returns the amount of a posting in this Entry. Blank postings are given the blank amount
+    /// (see `get_blank_amount`), split between them by their weights if there's more than one.
+    pub fn get_posting_amount(&self, posting: &Posting) -> Result<Amount, ProcessingError> {
+        if let Some(a) = posting.get_amount() {
+            return Ok(a.clone());
+        }
+
+        let blank_amount = self.get_blank_amount()?.unwrap_or_else(Amount::zero);
+        let total_weight: f64 = self
+            .get_classic_postings()
+            .filter(|p| p.get_amount().is_none())
+            .map(|p| p.get_weight().unwrap_or(1.0))
+            .sum();
+
+        Ok(Amount {
+            mag: blank_amount.mag * posting.get_weight().unwrap_or(1.0) / total_weight,
+            symbol: blank_amount.symbol,
+        })
+    }
+
+    /// Returns true if the Entry's blank postings can share the blank amount: either there's at
+    /// most one of them, or every one of them has a weight. Either way, some other posting needs an
+    /// amount for them to share.
+    fn has_shareable_blanks(&self) -> bool {
+        let (blanks, amounts): (Vec<&Posting>, Vec<&Posting>) = self
+            .get_classic_postings()
+            .partition(|p| p.get_amount().is_none());
+
+        blanks.is_empty()
+            || (!amounts.is_empty()
+                && (blanks.len() == 1 || blanks.iter().all(|p| p.get_weight().is_some())))
+    }
+
     /// Checks that the Entry is valid. Returns a ValidationError if it is invalid. An Entry is
     /// valid when all of the following are true:
     ///
-    /// - it contains no more than one blank posting amount
+    /// - it contains no more than one blank posting amount, unless every blank posting has a
+    ///   weight
+    /// - if it has blank posting amounts, at least one posting has an amount
     /// - it's balanced (the sum of its postings equals zero)
     /// - it contains no more than one type of currency when a blank posting amount exists (later
     ///   to be supported)
-    fn validate(&self, context: &str) -> Result<(), ValidationError> {
+    pub fn validate(&self, context: &str) -> Result<(), ValidationError> {
         let mut blank_amounts = 0;
         let mut symbol_set = HashSet::new();
-        for posting in &self.postings {
+        for posting in self.get_classic_postings() {
             // does amount exist?
             if let Some(a) = posting.get_amount() {
                 // if so, add its symbol to the set if it exists
@@ -290,16 +407,27 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
                 }
             } else {
                 blank_amounts += 1;
-
-                // if more than one blank amount, quit here and throw an error
-                if blank_amounts > 1 {
-                    return Err(ValidationError::default()
-                        .set_message("a single entry can't have more than one blank posting")
-                        .set_context(context));
-                }
             }
         }
 
+        // blank amounts are inferred from the others, so at least one posting needs an amount
+        if blank_amounts > 0
+            && self
+                .get_classic_postings()
+                .all(|p| p.get_amount().is_none())
+        {
+            return Err(ValidationError::default()
+                .set_message("silverfox can't infer the amounts of blank postings unless at least one posting in the entry has an amount")
+                .set_context(context));
+        }
+
+        // more than one blank amount needs weights to know how to split the blank amount
+        if !self.has_shareable_blanks() {
+            return Err(ValidationError::default()
+                .set_message("a single entry can't have more than one blank posting, unless each one has a weight (like `weight 2`)")
+                .set_context(context));
+        }
+
         // if there's a blank amount but the currencies aren't consistent, we can't infer the
         // blank's amount; there's a way around this that will be worked out in the future, but for
         // now it will be unsupported: TODO
@@ -310,6 +438,58 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
         Ok(())
     }
 
+    /// Returns true if this Entry's classic postings add up to zero, once the sum is rounded to
+    /// its currency's precision (see `Amount::rounds_to_zero`). Blank postings are assumed
+    /// to balance the rest, as long as their amounts can be inferred. Entries with mixed
+    /// currencies are balanced by their values in the native currency.
+    pub fn is_balanced(&self) -> bool {
+        if self.has_blank_posting() {
+            return self.has_shareable_blanks() && self.get_blank_amount().is_ok();
+        }
+
+        if self.has_mixed_currencies() {
+            // native values don't have a symbol
+            let mut residual = Amount::zero();
+            for posting in self.get_classic_postings() {
+                match posting.get_original_native_value() {
+                    Some(v) => residual.mag += v,
+                    None => return false,
+                }
+            }
+
+            residual.rounds_to_zero()
+        } else {
+            let mut amounts = self.get_classic_postings().filter_map(|p| p.get_amount());
+            let residual = match amounts.next() {
+                Some(first) => amounts.fold(first.clone(), |sum, a| sum + a),
+                None => return true,
+            };
+
+            residual.rounds_to_zero()
+        }
+    }
+
+    /// Adds a blank posting to `account` if this Entry doesn't balance and doesn't already have a
+    /// blank posting, so that the posting takes whatever's left over. Returns true if the posting
+    /// was added. Nothing is added if the posting wouldn't balance the Entry, like when it has
+    /// mixed currencies without native values.
+    pub fn balance_with(&mut self, account: &str) -> bool {
+        if self.is_balanced() || self.has_blank_posting() {
+            return false;
+        }
+
+        self.postings.push(Posting::from(ClassicPosting::new(
+            account, None, None, None,
+        )));
+
+        if self.is_balanced() {
+            true
+        } else {
+            self.postings.pop();
+            false
+        }
+    }
+
     pub fn as_full_string(&self) -> String {
         let payee = if let Some(p) = &self.payee {
             p
@@ -338,16 +518,143 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
         &self.date
     }
 
+    pub fn get_status(&self) -> &EntryStatus {
+        &self.status
+    }
+
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    /// Sets where this Entry falls among entries on the same date. Ledgers number entries as
+    /// they're added, so that same-date entries keep their file order when sorted (see
+    /// `cmp_order`).
+    pub fn set_seq(&mut self, seq: usize) {
+        self.seq = seq;
+    }
+
+    /// Compares entries by date, and then by the order they were added to their ledger in
+    pub fn cmp_order(&self, other: &Entry) -> std::cmp::Ordering {
+        (self.date, self.seq).cmp(&(other.date, other.seq))
+    }
+
+    /// Changes the status of this Entry. Unlike postings, the status can be changed freely,
+    /// because Accounts and Envelopes don't depend on it.
+    pub fn set_status(&mut self, status: EntryStatus) {
+        self.status = status;
+    }
+
+    /// Renames accounts in this Entry's postings (see `utils::renamed_account`). Returns true if
+    /// any posting was changed.
+    pub fn rename_account(&mut self, old: &str, new: &str) -> bool {
+        let mut renamed = false;
+        for posting in self.postings.iter_mut() {
+            renamed |= posting.rename_account(old, new);
+        }
+
+        renamed
+    }
+
+    /// Returns an Entry that undoes this one: the same postings with their amounts negated (see
+    /// `Posting::negated`), dated `date`, with a description like "Reversal of Groceries". Blank
+    /// postings are given the negated amounts they would have had, if they can be inferred.
+    pub fn reverse(&self, date: chrono::NaiveDate) -> Entry {
+        let postings = self
+            .postings
+            .iter()
+            .map(|p| {
+                let blank_amount = if p.is_classic() && p.get_amount().is_none() {
+                    self.get_posting_amount(p).ok()
+                } else {
+                    None
+                };
+
+                match blank_amount {
+                    Some(b) => {
+                        let mut materialized = Posting::from(ClassicPosting::new(
+                            p.get_account(),
+                            Some(-b),
+                            None,
+                            None,
+                        ));
+                        for (key, value) in p.get_metadata() {
+                            materialized.set_metadata(key.clone(), value.clone());
+                        }
+                        for tag in p.get_tags() {
+                            materialized.add_tag(tag.clone());
+                        }
+
+                        materialized
+                    }
+                    None => p.negated(),
+                }
+            })
+            .collect();
+
+        let mut reversal = Entry::new(
+            date,
+            self.status,
+            format!("Reversal of {}", self.description),
+            self.payee.clone(),
+            postings,
+            None,
+        );
+        reversal.metadata = self.metadata.clone();
+        reversal.tags = self.tags.clone();
+
+        reversal
+    }
+
     pub fn contains_account_posting(&self, account_name: &str) -> bool {
         self.postings
             .iter()
             .any(|p| p.get_account() == account_name)
     }
 
+    /// Returns true if this entry looks like the same transaction as `other`: the same date, the
+    /// same description, and the same amounts, rounded like they're displayed. Accounts aren't
+    /// compared, since imported entries are often recategorized afterwards.
+    pub fn is_duplicate_of(&self, other: &Entry) -> bool {
+        self.date == other.date
+            && self.description == other.description
+            && self.rounded_amounts() == other.rounded_amounts()
+    }
+
+    /// Returns the amounts of the entry's classic postings, rounded and sorted
+    fn rounded_amounts(&self) -> Vec<(Option<String>, f64)> {
+        let mut amounts = self
+            .postings
+            .iter()
+            .filter(|p| p.is_classic())
+            .filter_map(|p| self.get_posting_amount(p).ok())
+            .map(|a| (a.symbol.clone(), a.rounded_mag()))
+            .collect::<Vec<_>>();
+        amounts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        amounts
+    }
+
     pub fn get_postings(&self) -> &Vec<Posting> {
         &self.postings
     }
 
+    /// Returns the metadata of the entry itself. Postings can have metadata of their own; see
+    /// `Posting::effective_metadata`.
+    pub fn get_metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// Returns the tags of the entry itself. Postings can have tags of their own; see
+    /// `Posting::effective_tags`.
+    pub fn get_tags(&self) -> &HashSet<String> {
+        &self.tags
+    }
+
+    /// Returns an iterator over the postings that aren't envelope postings.
+    fn get_classic_postings(&self) -> impl Iterator<Item = &Posting> {
+        self.postings.iter().filter(|p| p.is_classic())
+    }
+
     pub fn has_blank_posting(&self) -> bool {
         for posting in &self.postings {
             if posting.get_amount().is_none() {
@@ -368,11 +675,13 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
         false
     }
 
+    /// Returns true if the classic postings of this Entry have more than one currency between
+    /// them.
     pub fn has_mixed_currencies(&self) -> bool {
         if self.postings.is_empty() {
             false
         } else {
-            let mut iter = self.postings.iter();
+            let mut iter = self.get_classic_postings();
             let symbol_to_match = if let Some(p) = iter.find(|&p| p.get_amount().is_some()) {
                 if let Some(a) = p.get_amount() {
                     a.symbol.clone()
@@ -402,34 +711,36 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
     pub fn as_register_data(
         &self,
         date_format: &str,
-        account_match: &Option<String>,
+        account_match: &Option<AccountMatcher>,
     ) -> Result<Option<EntryRegisterData>, ProcessingError> {
         // XXX: This closure is a duplicate of the one in
         // `ledger::display_register()`
         let is_account_name_focused = |account_name: &str| match account_match {
-            Some(match_str) => account_name.contains(match_str),
+            Some(matcher) => matcher.matches(account_name),
             // TODO: an issue ticket is open to further solidify whether or not an account is an
             // "asset", so this will be changed soon (it's kinda dumb right now)
             None => account_name.starts_with("asset"),
         };
 
-        let (positive_name, negative_name, amounts) = {
+        let (positive_name, negative_name, amounts, conversion_rate) = {
             let mut positive_names = HashSet::new();
             let mut negative_names = HashSet::new();
             let mut focused_amount = AmountPool::new();
 
+            // the amounts going into and out of accounts, for showing exchange rates
+            let mut positive_amount = None;
+            let mut negative_amount = None;
+
             for p in &self.postings {
                 let name = p.get_account();
-                let amount = if let Some(a) = p.get_amount() {
-                    a.clone()
-                } else {
-                    self.get_blank_amount()?.unwrap()
-                };
+                let amount = self.get_posting_amount(p)?;
 
                 if amount.mag > 0.0 {
                     positive_names.insert(name);
+                    positive_amount = Some(amount.clone());
                 } else if amount.mag < 0.0 {
                     negative_names.insert(name);
+                    negative_amount = Some(amount.clone());
                 }
 
                 if is_account_name_focused(name) {
@@ -455,7 +766,21 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
                 _ => "(multiple)".to_string(),
             };
 
-            (positive_name, negative_name, focused_amount)
+            // a rate only makes sense between exactly two accounts
+            let conversion_rate = if positive_names.len() == 1 && negative_names.len() == 1 {
+                positive_amount
+                    .zip(negative_amount)
+                    .and_then(|(to, from)| Self::conversion_rate(&from, &to))
+            } else {
+                None
+            };
+
+            (
+                positive_name,
+                negative_name,
+                focused_amount,
+                conversion_rate,
+            )
         };
 
         let account_flow = (negative_name.clone(), positive_name.clone());
@@ -463,18 +788,15 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
             negative_name.split(':').last().unwrap().to_string(),
             positive_name.split(':').last().unwrap().to_string(),
         );
-        let single_account_display = {
-            if !is_account_name_focused(&positive_name) {
-                positive_name.split(':').last().unwrap()
-            } else if !is_account_name_focused(&negative_name) {
-                negative_name.split(':').last().unwrap()
-            } else {
-                // both positive and negative accounts are focused, so this is
-                // probably a conversion
-                "(conversion)"
-            }
-        }
-        .to_string();
+        let single_account_display = if !is_account_name_focused(&positive_name) {
+            positive_name.split(':').last().unwrap().to_string()
+        } else if !is_account_name_focused(&negative_name) {
+            negative_name.split(':').last().unwrap().to_string()
+        } else {
+            // both positive and negative accounts are focused, so this is probably a conversion.
+            // show the exchange rate if there is one
+            conversion_rate.unwrap_or_else(|| String::from("(conversion)"))
+        };
 
         Ok(Some(EntryRegisterData {
             date: self.date.format(date_format).to_string(),
@@ -489,10 +811,34 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
             short_account_flow,
             single_account_display,
             amounts,
+            running_balance: AmountPool::new(),
         }))
     }
 
-    pub fn as_parsable(&self, date_format: &str) -> String {
+    /// Returns the exchange rate implied by converting `from` into `to`, like `1 USD = 0.9 EUR`, or
+    /// None if the amounts have the same currency or `from` is zero
+    fn conversion_rate(from: &Amount, to: &Amount) -> Option<String> {
+        if from.symbol == to.symbol || from.mag == 0.0 {
+            return None;
+        }
+
+        let one = Amount {
+            mag: 1.0,
+            symbol: from.symbol.clone(),
+        };
+        let rate = Amount {
+            mag: (to.mag / from.mag).abs(),
+            symbol: to.symbol.clone(),
+        };
+
+        Some(format!(
+            "{} = {}",
+            one.to_string().trim_start(),
+            rate.to_string().trim_start()
+        ))
+    }
+
+    pub fn as_parsable(&self, date_format: &str, posting_format: PostingFormat) -> String {
         let date = self.date.format(date_format);
 
         let mut s = String::new();
@@ -527,12 +873,122 @@ currency's worth in your native currency.").set_context(&self.as_full_string());
             },
         }
 
+        // size the account column to fit the longest account, so long accounts aren't crammed
+        // against their amounts and short ones aren't padded too far
+        let account_width = self
+            .postings
+            .iter()
+            .map(|p| p.get_account_column_len())
+            .max()
+            .unwrap_or(0)
+            .max(MIN_ACCOUNT_WIDTH);
+
+        s.push_str(&metadata_lines(&self.metadata, "    ", "//"));
+        s.push_str(&tags_line(&self.tags, "    ", "//"));
+
         for posting in &self.postings {
-            s.push_str(format!("    {}\n", posting.as_parsable()).as_str());
+            s.push_str(
+                format!(
+                    "    {}\n",
+                    posting.as_parsable(posting_format, account_width)
+                )
+                .as_str(),
+            );
+            s.push_str(&metadata_lines(posting.get_metadata(), "        ", "//"));
+            s.push_str(&tags_line(posting.get_tags(), "        ", "//"));
         }
 
         s
     }
+
+    /// Returns the entry as ledger-cli and hledger write it. Cleared and reconciled entries are
+    /// both cleared (`*`) in ledger, and pending entries are pending (`!`). The payee, metadata,
+    /// and tags are written as comments. Ledger has no envelopes, so envelope postings are written
+    /// as comments, too, and an entry with nothing but envelope postings is commented out
+    /// entirely.
+    pub fn as_ledger(&self) -> String {
+        let status = match self.status {
+            EntryStatus::Pending => '!',
+            EntryStatus::Cleared | EntryStatus::Reconciled => '*',
+        };
+
+        let mut s = format!(
+            "{} {} {}",
+            self.date.format("%Y-%m-%d"),
+            status,
+            self.description
+        );
+        if let Some(c) = &self.comment {
+            s.push_str(&format!("  ; {}", c));
+        }
+        s.push('\n');
+
+        if let Some(p) = &self.payee {
+            s.push_str(&format!("    ; payee: {}\n", p));
+        }
+        s.push_str(&metadata_lines(&self.metadata, "    ", ";"));
+        s.push_str(&tags_line(&self.tags, "    ", ";"));
+
+        let account_width = self
+            .postings
+            .iter()
+            .filter(|p| p.is_classic())
+            .map(|p| p.get_account_column_len())
+            .max()
+            .unwrap_or(0);
+
+        for posting in &self.postings {
+            // ledger has no weights, and a balance assertion without an amount is a balance
+            // assignment in ledger, so those blank amounts are filled in
+            let needs_amount =
+                posting.get_weight().is_some() || posting.get_balance_assertion().is_some();
+            let amount = if posting.is_classic() && posting.get_amount().is_none() && needs_amount {
+                self.get_posting_amount(posting).ok()
+            } else {
+                None
+            };
+
+            match posting.as_ledger(account_width, amount.as_ref()) {
+                Some(p) => s.push_str(&format!("    {}\n", p)),
+                None => s.push_str(&format!(
+                    "    ; {}\n",
+                    posting.as_parsable(PostingFormat::Classic, 0).trim_end()
+                )),
+            }
+            s.push_str(&metadata_lines(posting.get_metadata(), "        ", ";"));
+            s.push_str(&tags_line(posting.get_tags(), "        ", ";"));
+        }
+
+        if self.postings.iter().any(|p| p.is_classic()) {
+            s
+        } else {
+            s.lines().map(|l| format!("; {}\n", l)).collect()
+        }
+    }
+}
+
+/// Returns a comment line (starting with `marker`) for each key and value in `metadata`, sorted
+/// by key
+fn metadata_lines(metadata: &HashMap<String, String>, indent: &str, marker: &str) -> String {
+    let mut keys = metadata.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    keys.iter()
+        .map(|k| format!("{}{} {}: {}\n", indent, marker, k, metadata[*k]))
+        .collect()
+}
+
+/// Returns a comment line (starting with `marker`) with every tag in `tags`, sorted, or nothing
+/// if there aren't any tags
+fn tags_line(tags: &HashSet<String>, indent: &str, marker: &str) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+
+    let mut tags = tags.iter().map(String::as_str).collect::<Vec<&str>>();
+    tags.sort_unstable();
+
+    format!("{}{} :{}:\n", indent, marker, tags.join(":"))
 }
 
 pub struct EntryRegisterData {
@@ -544,6 +1000,37 @@ pub struct EntryRegisterData {
     pub short_account_flow: (String, String), // from, to
     pub single_account_display: String,
     pub amounts: AmountPool,
+
+    /// The total of `amounts` for this entry and every entry before it in the register. This is
+    /// filled in by the register, since an entry doesn't know what comes before it.
+    pub running_balance: AmountPool,
+}
+
+impl EntryRegisterData {
+    /// Returns this row of the register as a JSON object. Amounts are written as arrays of
+    /// `{"symbol", "mag"}` objects (see `AmountPool::as_json`).
+    pub fn as_json(&self) -> String {
+        let payee = match self
+            .payee
+            .strip_prefix('[')
+            .and_then(|p| p.strip_suffix(']'))
+        {
+            Some(p) => utils::json_string(p),
+            None => String::from("null"),
+        };
+
+        format!(
+            "{{\"date\":{},\"status\":{},\"description\":{},\"payee\":{},\"account_flow\":{{\"from\":{},\"to\":{}}},\"amounts\":{},\"running_balance\":{}}}",
+            utils::json_string(&self.date),
+            utils::json_string(&self.status.to_string()),
+            utils::json_string(&self.description),
+            payee,
+            utils::json_string(&self.account_flow.0),
+            utils::json_string(&self.account_flow.1),
+            self.amounts.as_json(),
+            self.running_balance.as_json()
+        )
+    }
 }
 
 #[cfg(test)]
@@ -554,6 +1041,386 @@ mod tests {
             assets:checking    -50
             expenses:groceries  50";
 
+    fn parse_with_accounts(s: &str) -> Entry {
+        let mut accounts: HashSet<&String> = HashSet::new();
+        let checking_name = String::from("assets:checking");
+        let expenses_name = String::from("expenses:groceries");
+        accounts.insert(&checking_name);
+        accounts.insert(&expenses_name);
+
+        match Entry::parse(s, "%Y/%m/%d", '.', &accounts) {
+            Ok(e) => e,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn unterminated_payee_test() {
+        let chunk = "2020/01/02 * Groceries [Grocery store
+                assets:checking    -50
+                expenses:groceries  50";
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        // strict parsing fails
+        assert!(Entry::parse(chunk, "%Y/%m/%d", '.', &accounts).is_err());
+
+        // forgiving parsing reads the payee to the end of the line, with a warning
+        let mut warnings = Vec::new();
+        let entry =
+            Entry::parse_forgiving(chunk, "%Y/%m/%d", '.', &accounts, &mut warnings).unwrap();
+        assert_eq!(entry.get_description(), "Groceries");
+        assert_eq!(entry.payee, Some(String::from("Grocery store")));
+        assert_eq!(entry.get_postings().len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("closing square bracket"));
+    }
+
+    #[test]
+    fn reverse_test() {
+        let entry = parse_with_accounts(
+            "2020/01/02 * Groceries [Grocery store]
+                assets:checking    -50 ! 100
+                expenses:groceries",
+        );
+        let date = chrono::NaiveDate::from_ymd(2020, 1, 5);
+        let reversal = entry.reverse(date);
+
+        assert_eq!(reversal.get_date(), &date);
+        assert_eq!(reversal.get_description(), "Reversal of Groceries");
+        assert_eq!(reversal.get_postings().len(), 2);
+
+        // the blank posting gets an amount, and every amount is negated
+        let amounts = reversal
+            .get_postings()
+            .iter()
+            .map(|p| p.get_amount().unwrap().mag)
+            .collect::<Vec<f64>>();
+        assert_eq!(amounts, vec![50.0, -50.0]);
+        assert!(reversal.is_balanced());
+
+        // together, the entry and its reversal don't change anything
+        let mut net = AmountPool::new();
+        for e in [&entry, &reversal] {
+            for posting in e.get_postings() {
+                net += e.get_posting_amount(posting).unwrap();
+            }
+        }
+        assert!(net.iter().all(|a| a.mag == 0.0));
+
+        // the balance assertion wouldn't hold anymore, so it's left out
+        assert!(!reversal
+            .as_parsable("%Y/%m/%d", PostingFormat::Classic)
+            .contains('!'));
+    }
+
+    #[test]
+    fn conversion_rate_test() {
+        let usd = String::from("assets:usd");
+        let eur = String::from("assets:eur");
+        let accounts: HashSet<&String> = [&usd, &eur].iter().cloned().collect();
+
+        let entry = Entry::parse(
+            "2020/01/02 * Exchange
+                assets:usd    -100 USD
+                assets:eur      90 EUR",
+            "%Y/%m/%d",
+            '.',
+            &accounts,
+        )
+        .unwrap();
+
+        let matcher = Some(AccountMatcher::Substring(String::from("assets")));
+        let data = entry
+            .as_register_data("%Y/%m/%d", &matcher)
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.single_account_display, "1 USD = 0.9 EUR");
+
+        // there's no rate between amounts of the same currency, or from nothing
+        let usd_amount = Amount {
+            mag: -100.0,
+            symbol: Some(String::from("USD")),
+        };
+        assert_eq!(
+            Entry::conversion_rate(&usd_amount, &-usd_amount.clone()),
+            None
+        );
+        assert_eq!(Entry::conversion_rate(&Amount::zero(), &usd_amount), None);
+    }
+
+    #[test]
+    fn account_width_test() {
+        let amount_column = |e: &Entry| {
+            let parsable = e.as_parsable("%Y/%m/%d", PostingFormat::Classic);
+            let posting_line = parsable.lines().nth(1).unwrap().to_string();
+            posting_line.find('-').unwrap()
+        };
+
+        // short accounts get the minimum width
+        let short = parse_with_accounts(ENTRY_STR);
+        assert_eq!(amount_column(&short), 4 + MIN_ACCOUNT_WIDTH + 1);
+
+        // a long account widens the column for every posting in the entry
+        let long_name = format!("expenses:{}", "x".repeat(60));
+        let checking = String::from("assets:checking");
+        let accounts: HashSet<&String> = [&checking, &long_name].iter().cloned().collect();
+        let long = Entry::parse(
+            &format!(
+                "2019/08/02 * Something\n    assets:checking  -50\n    {}  50",
+                long_name
+            ),
+            "%Y/%m/%d",
+            '.',
+            &accounts,
+        )
+        .unwrap();
+        assert_eq!(amount_column(&long), 4 + long_name.len() + 1);
+    }
+
+    #[test]
+    fn metadata_test() {
+        let entry = parse_with_accounts(
+            "2019/08/02 * Groceries // weekly shopping
+                ; category: food
+                // store: Fancy's
+                assets:checking    -50
+                expenses:groceries  50 ; category: groceries
+                    ; reviewed: yes",
+        );
+
+        assert_eq!(entry.comment.as_deref(), Some("weekly shopping"));
+        assert_eq!(entry.get_metadata().len(), 2);
+
+        // the checking posting has no metadata of its own, so it gets the entry's
+        let checking = &entry.get_postings()[0];
+        assert!(checking.get_metadata().is_empty());
+        let metadata = checking.effective_metadata(&entry);
+        assert_eq!(metadata["category"], "food");
+        assert_eq!(metadata["store"], "Fancy's");
+
+        // the groceries posting's own metadata wins
+        let groceries = &entry.get_postings()[1];
+        assert_eq!(groceries.get_metadata().len(), 2);
+        let metadata = groceries.effective_metadata(&entry);
+        assert_eq!(metadata["category"], "groceries");
+        assert_eq!(metadata["store"], "Fancy's");
+        assert_eq!(metadata["reviewed"], "yes");
+
+        // metadata survives being written out and parsed again
+        let reparsed = parse_with_accounts(&entry.as_parsable("%Y/%m/%d", PostingFormat::Classic));
+        assert_eq!(reparsed.get_metadata(), entry.get_metadata());
+        assert_eq!(
+            reparsed.get_postings()[1].get_metadata(),
+            groceries.get_metadata()
+        );
+    }
+
+    #[test]
+    fn tags_test() {
+        let entry = parse_with_accounts(
+            "2019/08/02 * Groceries // :not-a-tag:
+                ; :reviewed:
+                ; category: food
+                assets:checking    -50
+                expenses:groceries  50 ; split with Sam :shared:paid:
+                    ; :receipt:",
+        );
+
+        // header comments are kept as they are
+        assert_eq!(entry.comment.as_deref(), Some(":not-a-tag:"));
+        let set = |tags: &[&str]| {
+            tags.iter()
+                .map(|t| t.to_string())
+                .collect::<HashSet<String>>()
+        };
+        assert_eq!(entry.get_tags(), &set(&["reviewed"]));
+        assert_eq!(entry.get_metadata().len(), 1);
+
+        let checking = &entry.get_postings()[0];
+        assert!(checking.get_tags().is_empty());
+        assert_eq!(checking.effective_tags(&entry), set(&["reviewed"]));
+
+        let groceries = &entry.get_postings()[1];
+        assert_eq!(groceries.get_tags(), &set(&["shared", "paid", "receipt"]));
+        assert_eq!(
+            groceries.effective_tags(&entry),
+            set(&["reviewed", "shared", "paid", "receipt"])
+        );
+
+        // tags survive being written out and parsed again
+        let reparsed = parse_with_accounts(&entry.as_parsable("%Y/%m/%d", PostingFormat::Classic));
+        assert_eq!(reparsed.get_tags(), entry.get_tags());
+        assert_eq!(reparsed.get_postings()[1].get_tags(), groceries.get_tags());
+
+        // entries without tags are written like before
+        let untagged = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -50
+                expenses:groceries  50",
+        );
+        assert!(!untagged
+            .as_parsable("%Y/%m/%d", PostingFormat::Classic)
+            .contains("//"));
+    }
+
+    #[test]
+    fn weighted_blanks_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let household = String::from("expenses:household");
+        let accounts: HashSet<&String> = [&checking, &groceries, &household]
+            .iter()
+            .cloned()
+            .collect();
+        let parse = |s: &str| Entry::parse(s, "%Y/%m/%d", '.', &accounts);
+
+        let entry = parse(
+            "2019/08/02 * Groceries and supplies
+                assets:checking     $-90
+                expenses:groceries  weight 2
+                expenses:household  weight 1",
+        )
+        .unwrap();
+        assert!(entry.is_balanced());
+
+        // the balancer is split 2:1 between the blank postings
+        let amounts = entry
+            .get_postings()
+            .iter()
+            .map(|p| entry.get_posting_amount(p).unwrap())
+            .collect::<Vec<Amount>>();
+        assert_eq!(
+            amounts.iter().map(|a| a.mag).collect::<Vec<f64>>(),
+            vec![-90.0, 60.0, 30.0]
+        );
+        assert!(amounts.iter().all(|a| a.symbol == Some(String::from("$"))));
+
+        // weights are written back out
+        let reparsed = parse(&entry.as_parsable("%Y/%m/%d", PostingFormat::Classic)).unwrap();
+        assert_eq!(reparsed.get_postings()[1].get_weight(), Some(2.0));
+
+        // several blanks need weights, and only blanks can have them
+        assert!(parse(
+            "2019/08/02 * Groceries and supplies
+                assets:checking     -90
+                expenses:groceries  weight 2
+                expenses:household"
+        )
+        .is_err());
+        assert!(parse(
+            "2019/08/02 * Groceries
+                assets:checking     -90
+                expenses:groceries  90 weight 2"
+        )
+        .is_err());
+        assert!(parse(
+            "2019/08/02 * Groceries
+                assets:checking     -90
+                expenses:groceries  weight -1"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn all_weighted_blanks_test() {
+        let groceries = String::from("expenses:groceries");
+        let household = String::from("expenses:household");
+        let accounts: HashSet<&String> = [&groceries, &household].iter().cloned().collect();
+
+        // with nothing to balance, the blanks can't share anything
+        assert!(Entry::parse(
+            "2019/08/02 * Groceries and supplies
+                expenses:groceries  weight 1
+                expenses:household  weight 2",
+            "%Y/%m/%d",
+            '.',
+            &accounts
+        )
+        .is_err());
+
+        // and an entry built that way anyway has an error instead of a blank amount
+        let postings = ["expenses:groceries weight 1", "expenses:household weight 2"]
+            .iter()
+            .map(|s| Posting::from(ClassicPosting::parse(s, '.', &accounts).unwrap()))
+            .collect::<Vec<Posting>>();
+        let entry = Entry::new(
+            chrono::NaiveDate::from_ymd(2019, 8, 2),
+            EntryStatus::Cleared,
+            String::from("Groceries and supplies"),
+            None,
+            postings,
+            None,
+        );
+        assert!(entry.get_blank_amount().is_err());
+        assert!(entry.get_posting_amount(&entry.get_postings()[0]).is_err());
+        assert!(!entry.is_balanced());
+    }
+
+    #[test]
+    fn balance_with_test() {
+        let mut imbalanced = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -50
+                expenses:groceries  45",
+        );
+        assert!(imbalanced.balance_with("expenses:unknown"));
+        assert!(imbalanced.is_balanced());
+
+        let balancer = imbalanced.postings.last().unwrap();
+        assert_eq!(balancer.get_account(), "expenses:unknown");
+        assert_eq!(imbalanced.get_blank_amount().unwrap().unwrap().mag, 5.0);
+
+        // balanced entries are left alone
+        let mut balanced = parse_with_accounts(ENTRY_STR);
+        assert!(!balanced.balance_with("expenses:unknown"));
+        assert_eq!(balanced.postings.len(), 2);
+    }
+
+    #[test]
+    fn is_balanced_test() {
+        assert!(parse_with_accounts(ENTRY_STR).is_balanced());
+
+        let blank = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -50.10
+                expenses:groceries",
+        );
+        assert!(blank.is_balanced());
+
+        let floats = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -0.3
+                expenses:groceries  0.1
+                expenses:groceries  0.2",
+        );
+        assert!(floats.is_balanced());
+
+        let imbalanced = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -50
+                expenses:groceries  45",
+        );
+        assert!(!imbalanced.is_balanced());
+
+        // mixed currencies balance to the precision of the native currency, so a quarter of a
+        // cent is rounded off, but half of one rounds up to a whole cent
+        let quarter_cent = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -2.81
+                expenses:groceries  1.25 FOOD @ 2.25",
+        );
+        assert!(quarter_cent.is_balanced());
+
+        let half_cent = parse_with_accounts(
+            "2019/08/02 * Groceries
+                assets:checking    -3.38
+                expenses:groceries  1.5 FOOD @ 2.25",
+        );
+        assert!(!half_cent.is_balanced());
+    }
+
     #[test]
     fn parse_test() {
         let mut accounts: HashSet<&String> = HashSet::new();
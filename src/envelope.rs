@@ -1,8 +1,8 @@
-use crate::amount::{Amount, AmountPool};
+use crate::amount::{Amount, AmountFormat, AmountPool};
 use crate::entry::Entry;
 use crate::errors::ParseError;
 use crate::errors::ProcessingError;
-use crate::posting::{EnvelopePosting, Posting};
+use crate::posting::EnvelopePosting;
 use crate::utils;
 use chrono::prelude::*;
 use chrono::{Local, NaiveDate};
@@ -11,6 +11,15 @@ use std::collections::HashSet;
 use std::fmt;
 use std::str::FromStr;
 
+/// The account that ledger-level envelopes (see `--merge-envelopes-across-accounts`) belong to.
+/// They're funded by every asset account at once, so their envelope postings are written like
+/// `envelope * rent 100`.
+pub const POOLED_ACCOUNT: &str = "*";
+
+/// The fraction of what an envelope should have saved by now (see `Envelope::funding_health`)
+/// that it needs to have to be on track
+const BEHIND_THRESHOLD: f64 = 0.9;
+
 #[derive(Debug)]
 pub struct Envelope {
     name: String,
@@ -32,6 +41,21 @@ pub struct Envelope {
 
     /// The last date at which this envelope was affected
     last_transaction_date: NaiveDate,
+
+    /// The price of one unit of this envelope's currency, in the currency the envelope is funded
+    /// with. Only needed if the envelope's currency is different from its account's.
+    price: Option<Amount>,
+
+    /// A note about the envelope, for humans. It doesn't affect anything else.
+    note: Option<String>,
+
+    /// How many periods after the next one this envelope saves up for ahead of time, set with
+    /// `buffer_periods`. Without a buffer, only the next due date is saved for.
+    buffer_periods: u32,
+
+    /// The most that can be moved into this envelope in a single fill, set with `fill_cap`, in the
+    /// envelope's currency. Without a cap, the funding method alone decides.
+    fill_cap: Option<Amount>,
 }
 
 impl Ord for Envelope {
@@ -88,6 +112,13 @@ impl EnvelopeType {
             }),
         }
     }
+
+    fn as_str(&self) -> &str {
+        match self {
+            EnvelopeType::Expense => "expense",
+            EnvelopeType::Goal => "goal",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -109,6 +140,32 @@ impl FundingMethod {
             }),
         }
     }
+
+    fn as_str(&self) -> &str {
+        match self {
+            FundingMethod::Manual => "manual",
+            FundingMethod::Aggressive => "aggressive",
+            FundingMethod::Conservative => "conservative",
+        }
+    }
+}
+
+/// How an envelope's savings compare to what it should have saved by now
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FundingHealth {
+    OnTrack,
+    Behind,
+    Overfunded,
+}
+
+impl FundingHealth {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FundingHealth::OnTrack => "on_track",
+            FundingHealth::Behind => "behind",
+            FundingHealth::Overfunded => "overfunded",
+        }
+    }
 }
 
 // tuples including a date is the "starting" date
@@ -116,12 +173,15 @@ impl FundingMethod {
 pub enum Frequency {
     Never,
     Once(NaiveDate),
+    Daily,
     Weekly(chrono::Weekday),
     Biweekly(NaiveDate),
     Monthly(u32),
+    MonthlyLastDay,
     Bimonthly(NaiveDate),
-    // Quarterly(NaiveDate),
-    // Semiannually(NaiveDate),
+    Quarterly(NaiveDate),
+    Semiannually(NaiveDate),
+    EveryNDays(NaiveDate, u32),
     Annually(NaiveDate),
 }
 
@@ -131,6 +191,10 @@ impl Frequency {
         date_format: &str,
         starting_date: Option<NaiveDate>,
     ) -> Result<Self, ParseError> {
+        if s == "semiannually" {
+            return Self::parse("every 6 months", date_format, starting_date);
+        }
+
         if let Some(what) = s.strip_prefix("every other ") {
             // stop if "starting" isn't given, since it's required here
             if starting_date.is_none() {
@@ -144,7 +208,8 @@ impl Frequency {
             // remember: the `starting` clause is already trimmed
             if Self::parse_weekday(what).is_some() {
                 Ok(Self::Biweekly(starting_date.unwrap()))
-            } else if Self::parse_day_of_month(what).is_some() {
+            } else if let Some(d) = Self::parse_day_of_month(what) {
+                Self::check_day_of_month(d, s)?;
                 Ok(Self::Bimonthly(starting_date.unwrap()))
             } else {
                 Err(ParseError {
@@ -154,11 +219,39 @@ impl Frequency {
             }
         } else if let Some(what) = s.strip_prefix("every ") {
             // parse "everys"
-            // remember: the `starting` clause is already trimmed
-            if let Some(w) = Self::parse_weekday(what) {
+            // remember: the `starting` clause is already trimmed. "6 months" is checked first,
+            // since it would otherwise be read as the 6th of every month
+            if what == "day" {
+                Ok(Self::Daily)
+            } else if what == "last day" {
+                Ok(Self::MonthlyLastDay)
+            } else if let Some(n) = what
+                .strip_suffix(" days")
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                match starting_date {
+                    Some(_) if n == 0 => Err(ParseError {
+                        context: Some(s.to_string()),
+                        message: Some("envelopes can't be due every 0 days. the number of days needs to be at least 1".to_string()),
+                    }),
+                    Some(d) => Ok(Self::EveryNDays(d, n)),
+                    None => Err(ParseError {
+                        context: Some(s.to_string()),
+                        message: Some("a `starting` clause is required for `every N days` frequencies so silverfox knows which days to use".to_string()),
+                    }),
+                }
+            } else if what == "6 months" {
+                match starting_date {
+                    Some(d) => Ok(Self::Semiannually(d)),
+                    None => Err(ParseError{
+                        context: Some(s.to_string()),
+                        message: Some("envelopes due every 6 months require a `starting` date so that silverfox knows which months and day the envelope is due".to_string()),
+                    })
+                }
+            } else if let Some(w) = Self::parse_weekday(what) {
                 Ok(Self::Weekly(w))
             } else if let Some(d) = Self::parse_day_of_month(what) {
-                Ok(Self::Monthly(d))
+                Ok(Self::Monthly(Self::check_day_of_month(d, s)?))
             } else if what == "year" {
                 match starting_date {
                     Some(d) => Ok(Self::Annually(d)),
@@ -167,6 +260,14 @@ impl Frequency {
                         message: Some("envelopes due annually require a `starting` date so that silverfox knows which day of the year the envelope is due".to_string()),
                     })
                 }
+            } else if what == "quarter" {
+                match starting_date {
+                    Some(d) => Ok(Self::Quarterly(d)),
+                    None => Err(ParseError{
+                        context: Some(s.to_string()),
+                        message: Some("envelopes due quarterly require a `starting` date so that silverfox knows which months and day the envelope is due".to_string()),
+                    })
+                }
             } else {
                 Err(ParseError {
                     context: Some(s.to_string()),
@@ -210,15 +311,70 @@ impl Frequency {
         }
     }
 
+    /// Returns the day if it could be a day of a month, so that typos like `every 45th` aren't
+    /// silently treated as the last day of the month.
+    fn check_day_of_month(day: u32, s: &str) -> Result<u32, ParseError> {
+        if (1..=31).contains(&day) {
+            Ok(day)
+        } else {
+            Err(ParseError {
+                context: Some(s.to_string()),
+                message: Some(format!(
+                    "{} isn't a day of the month; it needs to be from 1 to 31",
+                    day
+                )),
+            })
+        }
+    }
+
+    /// Returns the Frequency as it would be written in an envelope header, after `due`. Any
+    /// `starting` clause is left out, since that belongs to the Envelope itself.
+    pub fn as_parsable(&self, date_format: &str) -> String {
+        match self {
+            Self::Never => String::from("no date"),
+            Self::Once(d) => d.format(date_format).to_string(),
+            Self::Daily => String::from("every day"),
+            Self::Weekly(w) => format!("every {}", w),
+            Self::Biweekly(d) => format!("every other {}", d.weekday()),
+            Self::Monthly(day) => format!("every {}", Self::ordinal(*day)),
+            Self::MonthlyLastDay => String::from("every last day"),
+            Self::Bimonthly(d) => format!("every other {}", Self::ordinal(d.day())),
+            Self::Quarterly(_) => String::from("every quarter"),
+            Self::Semiannually(_) => String::from("every 6 months"),
+            Self::EveryNDays(_, n) => format!("every {} days", n),
+            Self::Annually(_) => String::from("every year"),
+        }
+    }
+
+    /// Returns the day of the month with its English suffix, like "1st" or "22nd".
+    fn ordinal(day: u32) -> String {
+        let suffix = match (day % 10, day % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+
+        format!("{}{}", day, suffix)
+    }
+
     /// Gets the Frequency's last due date based on the next due date
     pub fn get_last_due_date(&self) -> Option<NaiveDate> {
         // get the next due date and just subtract
         match self.get_next_due_date() {
             Some(next_date) => match self {
+                Self::Daily => Some(next_date.pred()),
                 Self::Weekly(_) => Some(next_date - chrono::Duration::days(7)),
                 Self::Biweekly(_) => Some(next_date - chrono::Duration::days(14)),
                 Self::Monthly(_) => Some(Self::subtract_months(next_date, 1)),
+                Self::MonthlyLastDay => Some(Self::get_last_date_of_month(Self::subtract_months(
+                    next_date, 1,
+                ))),
                 Self::Bimonthly(_) => Some(Self::subtract_months(next_date, 2)),
+                Self::Quarterly(_) => Some(Self::subtract_months(next_date, 3)),
+                Self::Semiannually(_) => Some(Self::subtract_months(next_date, 6)),
+                Self::EveryNDays(_, n) => Some(next_date - chrono::Duration::days(*n as i64)),
                 Self::Annually(d) => Some(d.with_year(d.year() - 1).unwrap()),
                 _ => None,
             },
@@ -230,24 +386,37 @@ impl Frequency {
         }
     }
 
-    fn subtract_months(date: NaiveDate, num: i32) -> NaiveDate {
-        let mut new_month0 = date.month0() as i32 - num;
-        let mut new_year = date.year();
-
-        // this is dumb and pretty inefficient, so we'll have to improve this later. it's just the
-        // easy thing to do for now. TODO
-        while new_month0 < 0 {
-            new_month0 += 12;
-            new_year -= 1;
+    /// Returns the due date that comes just before `due_date`, if this Frequency repeats
+    pub fn get_due_date_before(&self, due_date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Self::Never | Self::Once(_) => None,
+            Self::Daily => Some(due_date.pred()),
+            Self::Weekly(_) => Some(due_date - chrono::Duration::days(7)),
+            Self::Biweekly(_) => Some(due_date - chrono::Duration::days(14)),
+            Self::Monthly(_) => Some(Self::subtract_months(due_date, 1)),
+            Self::MonthlyLastDay => Some(Self::get_last_date_of_month(Self::subtract_months(
+                due_date, 1,
+            ))),
+            Self::Bimonthly(_) => Some(Self::subtract_months(due_date, 2)),
+            Self::Quarterly(_) => Some(Self::subtract_months(due_date, 3)),
+            Self::Semiannually(_) => Some(Self::subtract_months(due_date, 6)),
+            Self::EveryNDays(_, n) => Some(due_date - chrono::Duration::days(*n as i64)),
+            Self::Annually(_) => Some(Self::subtract_months(due_date, 12)),
         }
+    }
 
-        NaiveDate::from_ymd(new_year, new_month0 as u32 + 1, date.day())
+    fn subtract_months(date: NaiveDate, num: i32) -> NaiveDate {
+        utils::subtract_months(date, num)
     }
 
-    // this function is pretty long, so we should probably break it into smaller functions
     /// Calculates and returns the next due date based on this Frequency.
     pub fn get_next_due_date(&self) -> Option<NaiveDate> {
-        let today = Local::today().naive_local();
+        self.get_next_due_date_after(Local::today().naive_local())
+    }
+
+    // this function is pretty long, so we should probably break it into smaller functions
+    /// Calculates and returns the first due date after `today` based on this Frequency.
+    pub fn get_next_due_date_after(&self, today: NaiveDate) -> Option<NaiveDate> {
         match self {
             Self::Never => None,
             Self::Once(date) => {
@@ -257,6 +426,7 @@ impl Frequency {
                     Some(*date)
                 }
             }
+            Self::Daily => Some(today.succ()),
             Self::Weekly(w) => {
                 // get next by weekday; keep adding to this 'next' variable until the weekday
                 // matches
@@ -280,6 +450,14 @@ impl Frequency {
             Self::Monthly(day_of_month) => {
                 Some(Self::next_date_by_day_of_month(today, *day_of_month))
             }
+            Self::MonthlyLastDay => {
+                let last_date_this_month = Self::get_last_date_of_month(today);
+                if last_date_this_month > today {
+                    Some(last_date_this_month)
+                } else {
+                    Some(Self::get_last_date_of_month(today.succ()))
+                }
+            }
             Self::Bimonthly(starting_date) => {
                 if starting_date > &today {
                     Some(*starting_date)
@@ -304,6 +482,21 @@ impl Frequency {
                     Some(date)
                 }
             }
+            Self::Quarterly(starting_date) => {
+                Some(Self::next_date_by_months(*starting_date, 3, today))
+            }
+            Self::Semiannually(starting_date) => {
+                Some(Self::next_date_by_months(*starting_date, 6, today))
+            }
+            Self::EveryNDays(starting_date, n) => {
+                if starting_date > &today {
+                    Some(*starting_date)
+                } else {
+                    let n = *n as i64;
+                    let periods_passed = (today - *starting_date).num_days() / n;
+                    Some(*starting_date + chrono::Duration::days((periods_passed + 1) * n))
+                }
+            }
             Self::Annually(starting_date) => {
                 if starting_date > &today {
                     Some(*starting_date)
@@ -316,9 +509,22 @@ impl Frequency {
 
     /// Returns the last day of the date's month
     fn get_last_date_of_month(date: NaiveDate) -> NaiveDate {
-        NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
-            .unwrap_or_else(|| NaiveDate::from_ymd(date.year() + 1, 1, 1))
-            .pred()
+        utils::last_date_of_month(date)
+    }
+
+    /// Returns the first date after `today` that's a whole number of `months` periods from
+    /// `starting_date` (which is returned itself if it's after `today`). Each due date is counted
+    /// from `starting_date`, so a day that doesn't exist in some month doesn't change the day of
+    /// the months after it.
+    fn next_date_by_months(starting_date: NaiveDate, months: i32, today: NaiveDate) -> NaiveDate {
+        let mut periods = 0;
+        let mut next = starting_date;
+        while next <= today {
+            periods += 1;
+            next = Self::subtract_months(starting_date, -months * periods);
+        }
+
+        next
     }
 
     fn next_date_by_day_of_month(today: NaiveDate, day: u32) -> NaiveDate {
@@ -350,6 +556,23 @@ impl Frequency {
                 .unwrap_or(last_date_next_month)
         }
     }
+
+    /// Returns how many times this Frequency comes due in an average month, or None if it doesn't
+    /// repeat
+    pub fn periods_per_month(&self) -> Option<f64> {
+        match self {
+            Self::Never | Self::Once(_) => None,
+            Self::Daily => Some(utils::DAYS_PER_MONTH),
+            Self::Weekly(_) => Some(52.0 / 12.0),
+            Self::Biweekly(_) => Some(26.0 / 12.0),
+            Self::Monthly(_) | Self::MonthlyLastDay => Some(1.0),
+            Self::Bimonthly(_) => Some(0.5),
+            Self::Quarterly(_) => Some(1.0 / 3.0),
+            Self::Semiannually(_) => Some(1.0 / 6.0),
+            Self::EveryNDays(_, n) => Some(utils::DAYS_PER_MONTH / *n as f64),
+            Self::Annually(_) => Some(1.0 / 12.0),
+        }
+    }
 }
 
 impl Envelope {
@@ -388,6 +611,27 @@ impl Envelope {
         &self.name
     }
 
+    /// Renames this Envelope's account and any accounts it's filled from (see
+    /// `utils::renamed_account`)
+    pub fn rename_account(&mut self, old: &str, new: &str) {
+        if let Some(a) = utils::renamed_account(&self.account, old, new) {
+            self.account = a;
+        }
+
+        self.auto_accounts = self
+            .auto_accounts
+            .drain()
+            .map(|a| utils::renamed_account(&a, old, new).unwrap_or(a))
+            .collect();
+    }
+
+    /// Empties the envelope, as if no entries had been processed yet
+    pub fn reset(&mut self) {
+        self.next_amount = Amount::zero();
+        self.now_amount = Amount::zero();
+        self.last_transaction_date = NaiveDate::from_ymd(0, 1, 1);
+    }
+
     /// Returns the starting struct of an Envelope. The string passed in can include ledger
     /// comments.
     fn from_header(
@@ -443,6 +687,10 @@ impl Envelope {
             account: String::from(account_name),
             starting_date,
             last_transaction_date: NaiveDate::from_ymd(0, 1, 1),
+            price: None,
+            note: None,
+            buffer_periods: 0,
+            fill_cap: None,
         };
         Ok(envelope)
     }
@@ -453,6 +701,9 @@ impl Envelope {
         account_name: &str,
         decimal_symbol: char,
     ) -> Result<(), ParseError> {
+        // the currency is applied after everything else, so that it can come before `amount`
+        let mut currency = None;
+
         for line in body.lines() {
             let trimmed_line = utils::remove_comments(line).trim();
             let line_split = trimmed_line.split_whitespace().collect::<Vec<&str>>();
@@ -504,6 +755,43 @@ impl Envelope {
                             Err(e) => return Err(e),
                         }
                     }
+                    "currency" => {
+                        // the currency of the envelope, if it isn't given in `amount`
+                        currency = Some(value.trim().to_string());
+                    }
+                    "price" => {
+                        // how much one unit of the envelope's currency costs in the account's
+                        self.price = Some(Amount::parse(value, decimal_symbol)?);
+                    }
+                    "note" => {
+                        self.note = Some(value.trim().to_string());
+                    }
+                    "buffer_periods" => match value.trim().parse::<u32>() {
+                        Ok(n) => self.buffer_periods = n,
+                        Err(_) => {
+                            return Err(ParseError {
+                                message: Some(format!(
+                                    "`{}` isn't a number of periods silverfox can save ahead for",
+                                    value.trim()
+                                )),
+                                context: None,
+                            })
+                        }
+                    },
+                    "fill_cap" => {
+                        let cap = Amount::parse(value, decimal_symbol)?;
+                        if cap.mag < 0.0 {
+                            return Err(ParseError {
+                                message: Some(format!(
+                                    "the fill cap of an envelope (`{}` in {}) can't be negative",
+                                    self.name, account_name
+                                )),
+                                context: None,
+                            });
+                        }
+
+                        self.fill_cap = Some(cap);
+                    }
                     _ => {
                         return Err(ParseError {
                             message: Some(format!(
@@ -517,6 +805,12 @@ impl Envelope {
             }
         }
 
+        if let Some(c) = currency {
+            self.amount.symbol = Some(c);
+            self.next_amount.symbol = self.amount.symbol.clone();
+            self.now_amount.symbol = self.amount.symbol.clone();
+        }
+
         Ok(())
     }
 
@@ -638,7 +932,7 @@ impl Envelope {
         for posting in entry.get_envelope_postings() {
             // process each envelope posting in the entry
 
-            if let Posting::Envelope(envelope_posting) = posting {
+            if let Some(envelope_posting) = posting.as_envelope() {
                 // this posting can only apply if the accounts match
                 if envelope_posting.get_account_name() == &self.account
                     && &self.name == envelope_posting.get_envelope_name()
@@ -666,7 +960,7 @@ impl Envelope {
 
         // count
         for posting in entry.get_postings() {
-            if *posting.get_account() == self.account {
+            if self.draws_from(posting.get_account()) {
                 self_account_count += 1;
             } else if self.auto_accounts.contains(posting.get_account()) {
                 auto_account_count += 1;
@@ -691,19 +985,12 @@ impl Envelope {
 
         // calculate sums for envelope
         for posting in entry.get_postings() {
-            let mut amount_to_add = match posting.get_amount() {
-                Some(a) => a.clone(),
-                None => match entry.get_blank_amount() {
-                    Ok(o) => {
-                        if let Some(b) = o {
-                            b
-                        } else {
-                            unreachable!()
-                        }
-                    }
-                    Err(e) => return Err(e),
-                },
-            };
+            let mut amount_to_add = entry.get_posting_amount(posting)?;
+
+            // amounts in the currency the envelope is funded with can be converted with its price
+            if amount_to_add.symbol != self.amount.symbol {
+                amount_to_add = self.to_envelope_currency(&amount_to_add);
+            }
 
             // if symbols don't match, try converting to native currency
             if amount_to_add.symbol != self.amount.symbol {
@@ -741,7 +1028,7 @@ amount")
                 }
             }
 
-            if posting.get_account() == &self.account {
+            if self.draws_from(posting.get_account()) {
                 self_account_postings_sum += amount_to_add;
             } else if self.auto_accounts.contains(posting.get_account()) {
                 auto_postings_sum += amount_to_add;
@@ -776,6 +1063,16 @@ amount")
         Ok(())
     }
 
+    /// Returns true if money in `account` is money for this envelope. Ledger-level envelopes draw
+    /// from every asset account; any other envelope only draws from its own account.
+    fn draws_from(&self, account: &str) -> bool {
+        if self.account == POOLED_ACCOUNT {
+            account.starts_with("asset")
+        } else {
+            account == self.account
+        }
+    }
+
     fn apply_amount(&mut self, amount: &Amount, date: NaiveDate) {
         if amount.mag < 0.0 {
             // take from an envelope. always take from the 'now' envelope
@@ -808,7 +1105,16 @@ amount")
         self.now_amount.mag + self.next_amount.mag
     }
 
-    fn get_filling_amount(&self, account_available_amount: &Amount) -> Amount {
+    /// Returns true if this is a one-time goal that has all of its amount saved up. Achieved goals
+    /// keep their money, but aren't filled any more. Taking money out of one makes it unachieved
+    /// again, until it's back up to its amount.
+    pub fn is_achieved(&self) -> bool {
+        matches!(self.envelope_type, EnvelopeType::Goal)
+            && matches!(self.freq, Frequency::Once(_))
+            && self.get_total_amount_mag() >= self.amount.mag
+    }
+
+    fn get_filling_amount(&self, account_available_amount: &Amount, today: NaiveDate) -> Amount {
         assert_eq!(account_available_amount.symbol, self.amount.symbol);
 
         // some convenience variables
@@ -817,17 +1123,16 @@ amount")
             mag: 0.0,
             symbol: symbol.clone(),
         };
-        let next_due_date = if let Some(d) = self.get_next_due_date() {
+        let next_due_date = if let Some(d) = self.get_next_due_date_after(today) {
             d
         } else {
             // no due date, no amount
             return zero_amount;
         };
 
-        let today = Local::today().naive_utc();
         let remaining_amount = self.get_remaining_next_amount();
 
-        if self.last_transaction_date == today {
+        let amount = if self.last_transaction_date == today || self.is_achieved() {
             zero_amount
         } else {
             match self.funding {
@@ -837,7 +1142,7 @@ amount")
                 }
                 FundingMethod::Aggressive => {
                     let mag = self
-                        .amount
+                        .get_target_amount()
                         .mag
                         .min(account_available_amount.mag) // makes sure the account value stays positive :)
                         .min(remaining_amount.mag) // prevents envelope overflow
@@ -852,7 +1157,9 @@ amount")
                 FundingMethod::Conservative => {
                     // get days remaining, and remaining amount
                     let date_diff = next_due_date.signed_duration_since(today);
-                    let days_remaining = date_diff.num_days();
+                    // envelopes due every day are due tomorrow, so there's always at least a
+                    // day left
+                    let days_remaining = date_diff.num_days().max(1);
                     let mag = (remaining_amount.mag / days_remaining as f64)
                         .min(account_available_amount.mag) // makes sure the account value stays positive
                         .min(remaining_amount.mag) // prevents envelope overflow
@@ -866,19 +1173,75 @@ amount")
                     }
                 }
             }
+        };
+
+        // no matter the funding method, a single fill never moves more than the cap
+        match &self.fill_cap {
+            Some(cap) => Amount {
+                mag: amount.mag.min(cap.mag),
+                symbol: amount.symbol,
+            },
+            None => amount,
         }
     }
 
-    /// Returns a posting with this Envelope's fill amount for the day. `account` is passed so that
-    /// the program can determine how much money we have available.
-    pub fn get_filling_posting(&self, account_available_value: &AmountPool) -> EnvelopePosting {
-        let amount = self.get_filling_amount(&account_available_value.only(&self.amount.symbol));
+    /// Returns a posting with this Envelope's fill amount for `today`. `account_available_value`
+    /// is passed so that the program can determine how much money we have available.
+    pub fn get_filling_posting(
+        &self,
+        account_available_value: &AmountPool,
+        today: NaiveDate,
+    ) -> EnvelopePosting {
+        let funding_symbol = match &self.price {
+            Some(p) => &p.symbol,
+            None => &self.amount.symbol,
+        };
+        let available = self.to_envelope_currency(&account_available_value.only(funding_symbol));
+        let amount = self.get_filling_amount(&available, today);
 
         EnvelopePosting::new(self.account.clone(), amount, self.name.clone())
     }
 
+    /// Converts an amount in the currency this envelope is funded with (see `price`) to the
+    /// envelope's currency. Amounts in any other currency are returned as they are.
+    fn to_envelope_currency(&self, amount: &Amount) -> Amount {
+        match &self.price {
+            Some(p) if p.symbol == amount.symbol && p.mag != 0.0 => Amount {
+                mag: amount.mag / p.mag,
+                symbol: self.amount.symbol.clone(),
+            },
+            _ => amount.clone(),
+        }
+    }
+
+    /// Converts an amount in this envelope's currency to the currency the envelope is funded with
+    /// (see `price`). Amounts in any other currency are returned as they are.
+    pub fn to_funding_currency(&self, amount: &Amount) -> Amount {
+        match &self.price {
+            Some(p) if amount.symbol == self.amount.symbol => Amount {
+                mag: amount.mag * p.mag,
+                symbol: p.symbol.clone(),
+            },
+            _ => amount.clone(),
+        }
+    }
+
+    /// Returns how much this envelope saves up for its next due date, including any periods it
+    /// saves for ahead of time (see `buffer_periods`)
+    fn get_target_amount(&self) -> Amount {
+        Amount {
+            mag: self.amount.mag * (1 + self.buffer_periods) as f64,
+            symbol: self.amount.symbol.clone(),
+        }
+    }
+
     fn get_remaining_next_amount(&self) -> Amount {
-        self.amount.clone() - self.next_amount.clone()
+        self.get_target_amount() - self.next_amount.clone()
+    }
+
+    /// Returns the amount this envelope needs by each due date
+    pub fn get_amount(&self) -> &Amount {
+        &self.amount
     }
 
     pub fn get_next_amount(&self) -> &Amount {
@@ -890,13 +1253,17 @@ amount")
     }
 
     fn get_next_due_date(&self) -> Option<NaiveDate> {
+        self.get_next_due_date_after(Local::today().naive_local())
+    }
+
+    fn get_next_due_date_after(&self, today: NaiveDate) -> Option<NaiveDate> {
         let starting_date = if let Some(d) = self.starting_date {
             d
         } else {
-            return self.freq.get_next_due_date();
+            return self.freq.get_next_due_date_after(today);
         };
 
-        let freq_next_date = if let Some(d) = self.freq.get_next_due_date() {
+        let freq_next_date = if let Some(d) = self.freq.get_next_due_date_after(today) {
             d
         } else {
             return Some(starting_date);
@@ -905,25 +1272,380 @@ amount")
         Some(starting_date.max(freq_next_date))
     }
 
+    /// Returns how much needs to go into this envelope each day, starting on `as_of`, for it to
+    /// be full by its next due date, along with that due date. Aggressive envelopes are filled
+    /// as soon as possible, so the whole remaining amount is needed right away.
+    ///
+    /// If the envelope is already full, the amount is zero. If it's past due, the whole remaining
+    /// amount is needed, and no date is returned. Envelopes without a due date have no plan.
+    pub fn contribution_plan(&self, as_of: NaiveDate) -> Option<(Amount, Option<NaiveDate>)> {
+        if let Frequency::Never = self.freq {
+            return None;
+        }
+
+        let remaining = self.get_remaining_next_amount();
+        if remaining.mag <= 0.0 {
+            let zero = Amount {
+                mag: 0.0,
+                symbol: self.amount.symbol.clone(),
+            };
+            return Some((zero, self.get_next_due_date_after(as_of)));
+        }
+
+        let due_date = match self.get_next_due_date_after(as_of) {
+            Some(d) => d,
+            None => return Some((remaining, None)),
+        };
+
+        match self.funding {
+            FundingMethod::Aggressive => Some((remaining, Some(due_date))),
+            FundingMethod::Conservative | FundingMethod::Manual => {
+                let days_remaining = due_date.signed_duration_since(as_of).num_days().max(1);
+                let per_day = Amount {
+                    mag: remaining.mag / days_remaining as f64,
+                    symbol: remaining.symbol,
+                };
+
+                Some((per_day, Some(due_date)))
+            }
+        }
+    }
+
+    /// Returns true if this envelope has no due date (`no date`). Untracked envelopes are never
+    /// filled automatically, so they only change with manual envelope postings.
+    pub fn is_untracked(&self) -> bool {
+        self.freq == Frequency::Never
+    }
+
+    /// Returns the date this envelope is due next, as of `as_of`. One-time envelopes keep their
+    /// date even after it has passed. Envelopes without a due date return None.
+    pub fn get_due_date(&self, as_of: NaiveDate) -> Option<NaiveDate> {
+        match self.freq {
+            Frequency::Once(d) => Some(d),
+            _ => self.get_next_due_date_after(as_of),
+        }
+    }
+
+    /// Returns the number of days from `as_of` until this envelope is due next. One-time envelopes
+    /// whose date has passed return a negative number (they're overdue). Envelopes without a due
+    /// date return None.
+    pub fn days_until_due(&self, as_of: NaiveDate) -> Option<i64> {
+        let due_date = self.get_due_date(as_of)?;
+
+        Some(due_date.signed_duration_since(as_of).num_days())
+    }
+
+    /// Returns true if this envelope won't be full by its next due date, as of `as_of`. Overdue
+    /// envelopes that aren't full are always underfunded. Otherwise, the envelope's progress since
+    /// its last due date (or its starting date) is projected forward at the same pace to see
+    /// whether it'll reach its amount in time. Envelopes without a due date are never underfunded.
+    pub fn is_underfunded_for_due(&self, as_of: NaiveDate) -> bool {
+        let remaining = self.get_remaining_next_amount();
+        if remaining.mag <= 0.0 {
+            return false;
+        }
+
+        let days_until_due = match self.days_until_due(as_of) {
+            Some(d) if d < 0 => return true,
+            Some(d) => d,
+            None => return false,
+        };
+
+        let period_start = self
+            .get_next_due_date_after(as_of)
+            .and_then(|d| self.freq.get_due_date_before(d))
+            .or(self.starting_date);
+        let days_elapsed = match period_start {
+            Some(d) => as_of.signed_duration_since(d).num_days(),
+            None => return false,
+        };
+
+        // nothing can be projected at the very start of a period
+        if days_elapsed <= 0 {
+            return false;
+        }
+
+        let pace = self.next_amount.mag / days_elapsed as f64;
+        let projected = self.next_amount.mag + pace * days_until_due as f64;
+
+        projected < self.amount.mag
+    }
+
+    /// Returns how this envelope's savings compare to what it should have saved by `as_of`, if it
+    /// were filled at an even pace from its last due date (or its starting date) to its next one.
+    /// Envelopes with less than `BEHIND_THRESHOLD` of that are behind, and envelopes with more than
+    /// they save up for (buffers included) are overfunded. Envelopes without a period to measure,
+    /// like those without a due date, are on track unless they're overfunded.
+    pub fn funding_health(&self, as_of: NaiveDate) -> FundingHealth {
+        if self.next_amount.mag > self.get_target_amount().mag {
+            return FundingHealth::Overfunded;
+        }
+
+        match self.expected_amount(as_of) {
+            Some(expected) if self.next_amount.mag < expected * BEHIND_THRESHOLD => {
+                FundingHealth::Behind
+            }
+            _ => FundingHealth::OnTrack,
+        }
+    }
+
+    /// Returns how much this envelope should have saved for its next due date by `as_of`, if it's
+    /// filled at an even pace over its period. Overdue envelopes should have all of their amount.
+    /// Returns None if the envelope doesn't have a due date or a start to its period.
+    fn expected_amount(&self, as_of: NaiveDate) -> Option<f64> {
+        let days_until_due = self.days_until_due(as_of)?;
+        if days_until_due < 0 {
+            return Some(self.amount.mag);
+        }
+
+        let period_start = self
+            .get_next_due_date_after(as_of)
+            .and_then(|d| self.freq.get_due_date_before(d))
+            .or(self.starting_date)?;
+        let days_elapsed = as_of.signed_duration_since(period_start).num_days();
+        if days_elapsed <= 0 {
+            return Some(0.0);
+        }
+
+        Some(self.amount.mag * days_elapsed as f64 / (days_elapsed + days_until_due) as f64)
+    }
+
+    /// Returns how much of its amount this envelope has saved for its next due date, as a percent
+    pub fn percent_funded(&self) -> f64 {
+        if self.amount.mag == 0.0 {
+            return 100.0;
+        }
+
+        self.next_amount.mag * 100.0 / self.amount.mag
+    }
+
+    /// Returns this envelope's state as of `as_of` as a JSON object
+    pub fn as_json(&self, as_of: NaiveDate) -> String {
+        let symbol = match &self.amount.symbol {
+            Some(s) => utils::json_string(s),
+            None => String::from("null"),
+        };
+        let due_date = match self.get_due_date(as_of) {
+            Some(d) => utils::json_string(&d.format("%Y-%m-%d").to_string()),
+            None => String::from("null"),
+        };
+        let days_until_due = match self.days_until_due(as_of) {
+            Some(d) => d.to_string(),
+            None => String::from("null"),
+        };
+        let note = match &self.note {
+            Some(n) => utils::json_string(n),
+            None => String::from("null"),
+        };
+
+        format!(
+            "{{\"account\":{},\"name\":{},\"type\":{},\"amount\":{},\"currency\":{},\"now\":{},\"next\":{},\"due_date\":{},\"days_until_due\":{},\"underfunded\":{},\"percent_funded\":{},\"health\":{},\"note\":{}}}",
+            utils::json_string(&self.account),
+            utils::json_string(&self.name),
+            utils::json_string(self.envelope_type.as_str()),
+            self.amount.mag,
+            symbol,
+            self.now_amount.mag,
+            self.next_amount.mag,
+            due_date,
+            days_until_due,
+            self.is_underfunded_for_due(as_of),
+            self.percent_funded(),
+            utils::json_string(self.funding_health(as_of).as_str()),
+            note
+        )
+    }
+
     pub fn get_freq(&self) -> &Frequency {
         &self.freq
     }
+
+    /// Returns the account this envelope belongs to, or `POOLED_ACCOUNT` if it's pooled
+    pub fn get_account(&self) -> &str {
+        &self.account
+    }
+
+    pub fn get_starting_date(&self) -> Option<NaiveDate> {
+        self.starting_date
+    }
+
+    /// Returns the period this envelope is budgeted over when it's exported, and the amount it
+    /// needs in each of those periods. Frequencies that other programs don't have a period for
+    /// (like every other week) are spread over a month instead. Envelopes that don't repeat have
+    /// no budget.
+    pub fn budget_period(&self) -> Option<(&'static str, Amount)> {
+        let (period, periods_per_month) = match self.freq {
+            Frequency::Never | Frequency::Once(_) => return None,
+            Frequency::Daily => ("daily", 1.0),
+            Frequency::Weekly(_) => ("weekly", 1.0),
+            Frequency::Monthly(_) | Frequency::MonthlyLastDay => ("monthly", 1.0),
+            Frequency::Quarterly(_) => ("quarterly", 1.0),
+            Frequency::Annually(_) => ("yearly", 1.0),
+            _ => ("monthly", self.freq.periods_per_month()?),
+        };
+
+        Some((
+            period,
+            Amount {
+                mag: self.amount.mag * periods_per_month,
+                symbol: self.amount.symbol.clone(),
+            },
+        ))
+    }
+
+    /// Returns the account this envelope's money is spent from: its first `for` account, or a
+    /// subaccount of its own account named after it if it doesn't have any
+    pub fn budget_account(&self) -> String {
+        match self.auto_accounts.iter().min() {
+            Some(a) => a.clone(),
+            None if self.account == POOLED_ACCOUNT => format!("assets:{}", self.name),
+            None => format!("{}:{}", self.account, self.name),
+        }
+    }
+
+    /// Returns a String that can be written under an account definition and parsed later on,
+    /// giving the same envelope. Balances aren't included; those come from entries.
+    pub fn as_parsable(&self, date_format: &str) -> String {
+        let _plain = AmountFormat::plain_scope();
+
+        let mut s = match self.freq {
+            Frequency::Never => format!("{} {} no date", self.envelope_type.as_str(), self.name),
+            _ => format!(
+                "{} {} due {}",
+                self.envelope_type.as_str(),
+                self.name,
+                self.freq.as_parsable(date_format)
+            ),
+        };
+
+        if let Some(d) = self.starting_date {
+            s.push_str(&format!(" starting {}", d.format(date_format)));
+        }
+
+        s.push_str(&format!("\n    amount {}", self.amount));
+
+        // sort auto accounts so that output is the same every time
+        let mut auto_accounts = self.auto_accounts.iter().collect::<Vec<&String>>();
+        auto_accounts.sort();
+        for account in auto_accounts {
+            s.push_str(&format!("\n    for {}", account));
+        }
+
+        s.push_str(&format!("\n    funding {}", self.funding.as_str()));
+
+        if let Some(p) = &self.price {
+            s.push_str(&format!("\n    price {}", p));
+        }
+
+        if let Some(n) = &self.note {
+            s.push_str(&format!("\n    note {}", n));
+        }
+
+        if self.buffer_periods > 0 {
+            s.push_str(&format!("\n    buffer_periods {}", self.buffer_periods));
+        }
+
+        if let Some(c) = &self.fill_cap {
+            s.push_str(&format!("\n    fill_cap {}", c));
+        }
+
+        s
+    }
 }
 
+/// The width of the `now`/`next` column in an envelope's display
+const PRELUDE_WIDTH: usize = 20;
+
+/// The width of the text progress column in an envelope's display
+const PROGRESS_TEXT_WIDTH: usize = 30;
+
+/// The width of the progress bar in an envelope's compact display
+const COMPACT_BAR_WIDTH: usize = 10;
+
 impl fmt::Display for Envelope {
+    /// Displays the envelope with a progress bar for both its `now` and `next` amounts. The
+    /// alternate flag (`{:#}`) displays the envelope on a single line instead.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            self.fmt_compact(f)
+        } else {
+            self.fmt_detailed(f, None)
+        }
+    }
+}
+
+/// Displays an envelope like its own Display does, but with its due date relative to a day (like
+/// "due in 3 days") instead of as a date. See `Envelope::relative_to`.
+pub struct RelativeEnvelope<'a> {
+    envelope: &'a Envelope,
+    as_of: NaiveDate,
+}
+
+impl fmt::Display for RelativeEnvelope<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            self.envelope.fmt_compact(f)
+        } else {
+            self.envelope.fmt_detailed(f, Some(self.as_of))
+        }
+    }
+}
+
+impl Envelope {
+    /// Returns this envelope for display with its due date relative to `as_of`
+    pub fn relative_to(&self, as_of: NaiveDate) -> RelativeEnvelope<'_> {
+        RelativeEnvelope {
+            envelope: self,
+            as_of,
+        }
+    }
+
+    /// Returns the label for the `next` progress bar. With `as_of`, the label is just the due date
+    /// relative to it ("due in 3 days", "due today", or "overdue by 2 days"), so that it fits in
+    /// the prelude column. Otherwise, it's `next` with the due date. Untracked envelopes are
+    /// labeled as such either way.
+    fn next_prelude(&self, as_of: Option<NaiveDate>) -> String {
+        if self.is_untracked() {
+            return "untracked / manual".to_string();
+        }
+
+        match as_of {
+            Some(as_of) => match self.days_until_due(as_of) {
+                Some(0) => "due today".to_string(),
+                Some(1) => "due in 1 day".to_string(),
+                Some(-1) => "overdue by 1 day".to_string(),
+                Some(days) if days < 0 => format!("overdue by {} days", -days),
+                Some(days) => format!("due in {} days", days),
+                None => "next".to_string(),
+            },
+            None => match self.get_next_due_date() {
+                Some(d) => format!("next (on {})", d),
+                None => "next".to_string(),
+            },
+        }
+    }
+
+    /// Writes the envelope with a progress bar for both its `now` and `next` amounts. The `next`
+    /// bar is followed by how much of the amount is saved, and whether the envelope is behind or
+    /// overfunded (see `funding_health`). If `as_of` is given, the due date is written relative to
+    /// it (see `next_prelude`), and funding health is judged on it instead of today.
+    fn fmt_detailed(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        as_of: Option<NaiveDate>,
+    ) -> fmt::Result {
         let progress_bar_width = 40;
 
+        // names can't run past the start of the progress bars
+        let name_width = PRELUDE_WIDTH + PROGRESS_TEXT_WIDTH + 4;
+        let name = utils::truncate_with_ellipsis(&self.name, name_width);
+
         // get next stuff
         let next_display = Amount {
             mag: self.next_amount.mag + self.now_amount.mag.min(0.0), // if now amount is below zero, subtract overflow from the next amount
             symbol: self.next_amount.symbol.clone(),
         };
-        let next_prelude = if let Some(d) = self.get_next_due_date() {
-            format!("next (on {})", d)
-        } else {
-            "next".to_string()
-        };
+        let next_prelude = self.next_prelude(as_of);
         let next_text = self.make_text_progress(&next_display);
         let next_bar = self.make_bar(&next_display, 40);
 
@@ -935,12 +1657,65 @@ impl fmt::Display for Envelope {
         let now_text = self.make_text_progress(&now_display);
         let now_bar = self.make_bar(&now_display, progress_bar_width);
 
-        writeln!(f, "    {}", self.name)?;
-        writeln!(f, "      {:20} {:>30} {}", "now", now_text, now_bar)?;
+        let next_prelude = utils::truncate_with_ellipsis(&next_prelude, PRELUDE_WIDTH);
+
+        let health = self.funding_health(as_of.unwrap_or_else(|| Local::today().naive_local()));
+        let next_status = match health {
+            FundingHealth::OnTrack => format!("{:.0}%", self.percent_funded()),
+            _ => format!("{:.0}% {}", self.percent_funded(), health.as_str()),
+        };
+
+        writeln!(f, "    {}", name)?;
+        if let Some(n) = &self.note {
+            writeln!(f, "      {}", n)?;
+        }
+        writeln!(
+            f,
+            "      {:pw$} {:>tw$} {}",
+            "now",
+            now_text,
+            now_bar,
+            pw = PRELUDE_WIDTH,
+            tw = PROGRESS_TEXT_WIDTH
+        )?;
+        write!(
+            f,
+            "      {:pw$} {:>tw$} {} {}",
+            next_prelude,
+            next_text,
+            next_bar,
+            next_status,
+            pw = PRELUDE_WIDTH,
+            tw = PROGRESS_TEXT_WIDTH
+        )
+    }
+
+    /// Writes the envelope on one line: its name, its `now`, `next`, and goal amounts, and a
+    /// short progress bar for the `next` amount.
+    fn fmt_compact(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        let name = utils::truncate_with_ellipsis(&self.name, PRELUDE_WIDTH);
+
+        // the same as the detailed display, any overflow from `now` is taken from `next`
+        let now_display = Amount {
+            mag: self.now_amount.mag.max(0.0),
+            symbol: self.now_amount.symbol.clone(),
+        };
+        let next_display = Amount {
+            mag: self.next_amount.mag + self.now_amount.mag.min(0.0),
+            symbol: self.next_amount.symbol.clone(),
+        };
+
+        let text = format!("{} / {} / {}", now_display, next_display, self.amount);
+        let bar = self.make_bar(&next_display, COMPACT_BAR_WIDTH);
+
         write!(
             f,
-            "      {:20} {:>30} {}",
-            next_prelude, next_text, next_bar
+            "    {:nw$}  {:>tw$} {}",
+            name,
+            text,
+            bar,
+            nw = PRELUDE_WIDTH,
+            tw = PROGRESS_TEXT_WIDTH
         )
     }
 }
@@ -959,4 +1734,678 @@ mod tests {
         let subtracted_1 = Frequency::subtract_months(date_1, 3);
         assert_eq!(NaiveDate::from_ymd(2019, 10, 1), subtracted_1);
     }
+
+    #[test]
+    fn last_day_test() {
+        let freq = Frequency::parse("every last day", "%Y/%m/%d", None).unwrap();
+        assert_eq!(freq, Frequency::MonthlyLastDay);
+        assert_eq!(freq.as_parsable("%Y/%m/%d"), "every last day");
+
+        let next_after = |y, m, d| {
+            freq.get_next_due_date_after(NaiveDate::from_ymd(y, m, d))
+                .unwrap()
+        };
+
+        // february, in leap years and otherwise
+        assert_eq!(next_after(2020, 2, 3), NaiveDate::from_ymd(2020, 2, 29));
+        assert_eq!(next_after(2021, 2, 3), NaiveDate::from_ymd(2021, 2, 28));
+        assert_eq!(next_after(2021, 1, 31), NaiveDate::from_ymd(2021, 2, 28));
+
+        // 30- and 31-day months, and the end of the year
+        assert_eq!(next_after(2020, 3, 31), NaiveDate::from_ymd(2020, 4, 30));
+        assert_eq!(next_after(2020, 4, 30), NaiveDate::from_ymd(2020, 5, 31));
+        assert_eq!(next_after(2020, 12, 31), NaiveDate::from_ymd(2021, 1, 31));
+
+        // the due date before is the end of the month before, not the same day of it
+        assert_eq!(
+            freq.get_due_date_before(NaiveDate::from_ymd(2020, 4, 30)),
+            Some(NaiveDate::from_ymd(2020, 3, 31))
+        );
+        assert_eq!(
+            freq.get_due_date_before(NaiveDate::from_ymd(2020, 3, 31)),
+            Some(NaiveDate::from_ymd(2020, 2, 29))
+        );
+    }
+
+    #[test]
+    fn buffer_periods_test() {
+        let parse = |s: &str| Envelope::parse(s, "assets:checking", '.', "%Y/%m/%d").unwrap();
+        let mut unbuffered =
+            parse("expense rent due every 15th\n    amount 100\n    funding aggressive");
+        let mut buffered = parse(
+            "expense rent due every 15th\n    amount 100\n    funding aggressive\n    buffer_periods 2",
+        );
+
+        // both envelopes already have enough for the next due date
+        unbuffered.next_amount.mag = 100.0;
+        buffered.next_amount.mag = 100.0;
+
+        let available = AmountPool::from(Amount {
+            mag: 1000.0,
+            symbol: None,
+        });
+        let today = NaiveDate::from_ymd(2020, 1, 1);
+
+        // so the unbuffered one stops, but the buffered one saves for two more periods
+        assert_eq!(
+            unbuffered
+                .get_filling_posting(&available, today)
+                .get_amount()
+                .mag,
+            0.0
+        );
+        assert_eq!(
+            buffered
+                .get_filling_posting(&available, today)
+                .get_amount()
+                .mag,
+            200.0
+        );
+
+        let reparsed = parse(&buffered.as_parsable("%Y/%m/%d"));
+        assert_eq!(reparsed.buffer_periods, 2);
+
+        assert!(Envelope::parse(
+            "expense rent due every 15th\n    amount 100\n    buffer_periods -1",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn achieved_goal_test() {
+        let mut goal = Envelope::parse(
+            "goal camera by 2100/01/01\n    amount 100\n    funding aggressive",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        let amount = |mag: f64| Amount { mag, symbol: None };
+        let available = amount(1000.0);
+        let today = NaiveDate::from_ymd(2020, 1, 10);
+
+        assert!(!goal.is_achieved());
+        assert_eq!(goal.get_filling_amount(&available, today).mag, 100.0);
+
+        // reaching the goal stops the filling, but keeps the money
+        goal.apply_amount(&amount(100.0), NaiveDate::from_ymd(2020, 1, 1));
+        assert!(goal.is_achieved());
+        assert_eq!(goal.get_filling_amount(&available, today).mag, 0.0);
+        assert_eq!(goal.get_total_amount_mag(), 100.0);
+
+        // withdrawing from it makes it unachieved
+        goal.apply_amount(&amount(-30.0), NaiveDate::from_ymd(2020, 1, 2));
+        assert!(!goal.is_achieved());
+
+        // and it's achieved again once it's back up to its amount
+        goal.apply_amount(&amount(30.0), NaiveDate::from_ymd(2020, 1, 3));
+        assert!(goal.is_achieved());
+
+        // only one-time goals can be achieved
+        let mut yearly = Envelope::parse(
+            "goal trip due every year starting 2100/01/01\n    amount 100",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        yearly.apply_amount(&amount(100.0), NaiveDate::from_ymd(2020, 1, 1));
+        assert!(!yearly.is_achieved());
+    }
+
+    #[test]
+    fn fill_cap_test() {
+        let parse = |s: &str| Envelope::parse(s, "assets:checking", '.', "%Y/%m/%d").unwrap();
+        let uncapped =
+            parse("expense rent due every 15th\n    amount 1000\n    funding aggressive");
+        let capped = parse(
+            "expense rent due every 15th\n    amount 1000\n    funding aggressive\n    fill_cap 100",
+        );
+
+        // plenty is available, and the envelopes are empty
+        let available = AmountPool::from(Amount {
+            mag: 5000.0,
+            symbol: None,
+        });
+        let today = NaiveDate::from_ymd(2020, 1, 1);
+
+        assert_eq!(
+            uncapped
+                .get_filling_posting(&available, today)
+                .get_amount()
+                .mag,
+            1000.0
+        );
+        assert_eq!(
+            capped
+                .get_filling_posting(&available, today)
+                .get_amount()
+                .mag,
+            100.0
+        );
+
+        // less than the cap still moves as much as is available
+        let scarce = AmountPool::from(Amount {
+            mag: 40.0,
+            symbol: None,
+        });
+        assert_eq!(
+            capped.get_filling_posting(&scarce, today).get_amount().mag,
+            40.0
+        );
+
+        let reparsed = parse(&capped.as_parsable("%Y/%m/%d"));
+        assert_eq!(reparsed.fill_cap.map(|c| c.mag), Some(100.0));
+
+        assert!(Envelope::parse(
+            "expense rent due every 15th\n    amount 100\n    fill_cap -5",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn filling_posting_test() {
+        let mut envelope = Envelope::parse(
+            "expense rent due every 5th\n    amount 100\n    funding conservative",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        let available = AmountPool::from(Amount {
+            mag: 1000.0,
+            symbol: None,
+        });
+        let fill_on = |envelope: &Envelope, y, m, d| {
+            envelope
+                .get_filling_posting(&available, NaiveDate::from_ymd(y, m, d))
+                .get_amount()
+                .mag
+        };
+
+        assert_eq!(
+            envelope.get_next_due_date_after(NaiveDate::from_ymd(2020, 1, 1)),
+            Some(NaiveDate::from_ymd(2020, 1, 5))
+        );
+        assert_eq!(
+            envelope.get_next_due_date_after(NaiveDate::from_ymd(2020, 1, 5)),
+            Some(NaiveDate::from_ymd(2020, 2, 5))
+        );
+
+        // four days until the 5th, so a quarter of the amount is needed today
+        assert_eq!(fill_on(&envelope, 2020, 1, 1), 25.0);
+
+        // nothing more is moved on a day the envelope has already been filled
+        envelope.last_transaction_date = NaiveDate::from_ymd(2020, 1, 1);
+        assert_eq!(fill_on(&envelope, 2020, 1, 1), 0.0);
+    }
+
+    #[test]
+    fn every_n_days_test() {
+        let starting = NaiveDate::from_ymd(2024, 1, 1);
+        let freq = Frequency::parse("every 10 days", "%Y/%m/%d", Some(starting)).unwrap();
+        assert_eq!(freq, Frequency::EveryNDays(starting, 10));
+
+        let next_after = |y, m, d| freq.get_next_due_date_after(NaiveDate::from_ymd(y, m, d));
+        assert_eq!(next_after(2023, 12, 25), Some(starting));
+        assert_eq!(
+            next_after(2024, 1, 1),
+            Some(NaiveDate::from_ymd(2024, 1, 11))
+        );
+        assert_eq!(
+            next_after(2024, 1, 10),
+            Some(NaiveDate::from_ymd(2024, 1, 11))
+        );
+        assert_eq!(
+            next_after(2024, 1, 11),
+            Some(NaiveDate::from_ymd(2024, 1, 21))
+        );
+        assert_eq!(
+            next_after(2024, 2, 29),
+            Some(NaiveDate::from_ymd(2024, 3, 1))
+        );
+        assert_eq!(
+            freq.get_due_date_before(NaiveDate::from_ymd(2024, 1, 21)),
+            Some(NaiveDate::from_ymd(2024, 1, 11))
+        );
+        assert_eq!(freq.as_parsable("%Y/%m/%d"), "every 10 days");
+
+        // intervals need a starting date and at least one day
+        assert!(Frequency::parse("every 10 days", "%Y/%m/%d", None).is_err());
+        assert!(Frequency::parse("every 0 days", "%Y/%m/%d", Some(starting)).is_err());
+    }
+
+    #[test]
+    fn daily_test() {
+        let today = NaiveDate::from_ymd(2020, 2, 28);
+        let freq = Frequency::parse("every day", "%Y/%m/%d", None).unwrap();
+        assert_eq!(freq, Frequency::Daily);
+        assert_eq!(
+            freq.get_next_due_date_after(today),
+            Some(NaiveDate::from_ymd(2020, 2, 29))
+        );
+        assert_eq!(freq.get_due_date_before(today.succ()), Some(today));
+        assert_eq!(freq.as_parsable("%Y/%m/%d"), "every day");
+
+        // the whole remaining amount is needed by tomorrow
+        let mut envelope = Envelope::parse(
+            "expense supplies due every day\n    amount 40\n    funding conservative",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        envelope.next_amount.mag = 15.0;
+        let (per_day, due_date) = envelope.contribution_plan(today).unwrap();
+        assert_eq!(per_day.mag, 25.0);
+        assert_eq!(due_date, Some(today.succ()));
+    }
+
+    #[test]
+    fn semiannually_test() {
+        let date_0 = NaiveDate::from_ymd(2019, 8, 2);
+        let freq_0 = Frequency::parse("every 6 months", "%Y/%m/%d", Some(date_0)).unwrap();
+        assert_eq!(freq_0, Frequency::Semiannually(date_0));
+        assert_eq!(
+            freq_0.get_next_due_date_after(date_0),
+            Some(NaiveDate::from_ymd(2020, 2, 2))
+        );
+        assert_eq!(
+            freq_0.get_due_date_before(NaiveDate::from_ymd(2020, 2, 2)),
+            Some(date_0)
+        );
+
+        let date_1 = NaiveDate::from_ymd(2020, 4, 30);
+        let freq_1 = Frequency::parse("semiannually", "%Y/%m/%d", Some(date_1)).unwrap();
+        assert_eq!(freq_1, Frequency::Semiannually(date_1));
+        assert_eq!(
+            freq_1.get_next_due_date_after(NaiveDate::from_ymd(2020, 5, 1)),
+            Some(NaiveDate::from_ymd(2020, 10, 30))
+        );
+        assert_eq!(
+            freq_1.get_next_due_date_after(NaiveDate::from_ymd(2020, 12, 1)),
+            Some(NaiveDate::from_ymd(2021, 4, 30))
+        );
+
+        assert!(Frequency::parse("semiannually", "%Y/%m/%d", None).is_err());
+        assert_eq!(freq_1.as_parsable("%Y/%m/%d"), "every 6 months");
+    }
+
+    #[test]
+    fn day_of_month_range_test() {
+        assert!(Frequency::parse("every 45th", "%Y/%m/%d", None).is_err());
+        assert!(Frequency::parse("every 0th", "%Y/%m/%d", None).is_err());
+        assert!(Frequency::parse(
+            "every other 32nd",
+            "%Y/%m/%d",
+            Some(NaiveDate::from_ymd(2020, 1, 1))
+        )
+        .is_err());
+
+        match Frequency::parse("every 31st", "%Y/%m/%d", None) {
+            Ok(Frequency::Monthly(31)) => (),
+            other => panic!("expected a monthly frequency on the 31st, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quarterly_test() {
+        assert!(Frequency::parse("every quarter", "%Y/%m/%d", None).is_err());
+
+        let starting = NaiveDate::from_ymd(2020, 1, 31);
+        let freq = Frequency::parse("every quarter", "%Y/%m/%d", Some(starting)).unwrap();
+        assert_eq!(freq, Frequency::Quarterly(starting));
+
+        let next_after = |y, m, d| {
+            freq.get_next_due_date_after(NaiveDate::from_ymd(y, m, d))
+                .unwrap()
+        };
+        assert_eq!(next_after(2019, 12, 1), starting);
+        assert_eq!(next_after(2020, 1, 31), NaiveDate::from_ymd(2020, 4, 30));
+        // the short month before doesn't change the day of the month after it
+        assert_eq!(next_after(2020, 4, 30), NaiveDate::from_ymd(2020, 7, 31));
+        assert_eq!(next_after(2020, 12, 1), NaiveDate::from_ymd(2021, 1, 31));
+        assert_eq!(
+            freq.get_due_date_before(NaiveDate::from_ymd(2020, 7, 31)),
+            Some(NaiveDate::from_ymd(2020, 4, 30))
+        );
+
+        // round trip through an envelope header
+        let mut envelope = Envelope::parse(
+            "expense insurance due every quarter starting 2020/01/15\n    amount 910\n    funding conservative",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        let parsable = envelope.as_parsable("%Y/%m/%d");
+        assert!(parsable.starts_with("expense insurance due every quarter starting 2020/01/15"));
+        let reparsed = Envelope::parse(&parsable, "assets:checking", '.', "%Y/%m/%d").unwrap();
+        assert_eq!(reparsed.get_freq(), envelope.get_freq());
+
+        // conservative funding is spread over the 91 days until the next premium
+        envelope.next_amount.mag = 0.0;
+        let (per_day, due_date) = envelope
+            .contribution_plan(NaiveDate::from_ymd(2020, 1, 15))
+            .unwrap();
+        assert_eq!(due_date, Some(NaiveDate::from_ymd(2020, 4, 15)));
+        assert_eq!(per_day.mag, 10.0);
+    }
+
+    #[test]
+    fn contribution_plan_test() {
+        let as_of = NaiveDate::from_ymd(2020, 1, 1);
+        let mut goal = Envelope::parse(
+            "goal vacation due 2020/01/11\n    amount 5000\n    funding conservative",
+            "assets:savings",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        goal.next_amount.mag = 1000.0;
+
+        // 4000 left over 10 days
+        let (amount, due_date) = goal.contribution_plan(as_of).unwrap();
+        assert_eq!(amount.mag, 400.0);
+        assert_eq!(due_date, Some(NaiveDate::from_ymd(2020, 1, 11)));
+
+        // aggressive envelopes want everything now
+        goal.funding = FundingMethod::Aggressive;
+        assert_eq!(goal.contribution_plan(as_of).unwrap().0.mag, 4000.0);
+
+        // past due goals need the whole remaining amount
+        let (amount, due_date) = goal
+            .contribution_plan(NaiveDate::from_ymd(2020, 2, 1))
+            .unwrap();
+        assert_eq!(amount.mag, 4000.0);
+        assert_eq!(due_date, None);
+
+        // met goals don't need anything
+        goal.next_amount.mag = 5000.0;
+        assert_eq!(goal.contribution_plan(as_of).unwrap().0.mag, 0.0);
+    }
+
+    #[test]
+    fn underfunded_test() {
+        let as_of = NaiveDate::from_ymd(2020, 1, 10);
+        let mut envelope = Envelope::parse(
+            "expense rent due every 15th\n    amount 300\n    funding conservative",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        assert_eq!(envelope.days_until_due(as_of), Some(5));
+
+        // 260 saved in the 26 days since the 15th of last month is 10 a day, which is enough
+        envelope.next_amount.mag = 260.0;
+        assert!(!envelope.is_underfunded_for_due(as_of));
+
+        // 100 saved in the same time isn't
+        envelope.next_amount.mag = 100.0;
+        assert!(envelope.is_underfunded_for_due(as_of));
+
+        // full envelopes are never underfunded
+        envelope.next_amount.mag = 300.0;
+        assert!(!envelope.is_underfunded_for_due(as_of));
+
+        // one-time goals can be overdue
+        let mut goal = Envelope::parse(
+            "goal laptop due 2020/01/01\n    amount 1000",
+            "assets:savings",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        assert_eq!(goal.days_until_due(as_of), Some(-9));
+        assert!(goal.is_underfunded_for_due(as_of));
+        goal.next_amount.mag = 1000.0;
+        assert!(!goal.is_underfunded_for_due(as_of));
+
+        // envelopes without due dates have neither
+        let envelope = Envelope::parse(
+            "goal rainy_day no date\n    amount 1000",
+            "assets:savings",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        assert_eq!(envelope.days_until_due(as_of), None);
+        assert!(!envelope.is_underfunded_for_due(as_of));
+        assert!(envelope
+            .as_json(as_of)
+            .contains("\"days_until_due\":null,\"underfunded\":false"));
+    }
+
+    #[test]
+    fn funding_health_test() {
+        let as_of = NaiveDate::from_ymd(2020, 1, 10);
+        let mut envelope = Envelope::parse(
+            "expense rent due every 15th\n    amount 310\n    funding conservative",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+
+        // 26 of the 31 days since the 15th of last month have passed, so 260 is expected
+        envelope.next_amount.mag = 260.0;
+        assert_eq!(envelope.funding_health(as_of), FundingHealth::OnTrack);
+
+        // a little short is still on track, but much less isn't
+        envelope.next_amount.mag = 240.0;
+        assert_eq!(envelope.funding_health(as_of), FundingHealth::OnTrack);
+        envelope.next_amount.mag = 200.0;
+        assert_eq!(envelope.funding_health(as_of), FundingHealth::Behind);
+
+        // right after a due date, nothing is expected yet
+        envelope.next_amount.mag = 0.0;
+        assert_eq!(
+            envelope.funding_health(NaiveDate::from_ymd(2020, 1, 15)),
+            FundingHealth::OnTrack
+        );
+
+        // more than the amount is overfunded, unless it's saved for a buffer
+        envelope.next_amount.mag = 400.0;
+        assert_eq!(envelope.funding_health(as_of), FundingHealth::Overfunded);
+        envelope.buffer_periods = 1;
+        assert_eq!(envelope.funding_health(as_of), FundingHealth::OnTrack);
+
+        // overdue goals should be full
+        let mut goal = Envelope::parse(
+            "goal laptop due 2020/01/01\n    amount 1000",
+            "assets:savings",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        goal.next_amount.mag = 950.0;
+        assert_eq!(goal.funding_health(as_of), FundingHealth::OnTrack);
+        goal.next_amount.mag = 500.0;
+        assert_eq!(goal.funding_health(as_of), FundingHealth::Behind);
+
+        // health is displayed after the percent saved, and exported
+        let displayed = goal.relative_to(as_of).to_string();
+        assert!(displayed.lines().last().unwrap().ends_with("| 50% behind"));
+        assert!(goal
+            .as_json(as_of)
+            .contains("\"percent_funded\":50,\"health\":\"behind\""));
+    }
+
+    #[test]
+    fn untracked_test() {
+        let mut accounts: HashSet<&String> = HashSet::new();
+        let savings = String::from("assets:savings");
+        accounts.insert(&savings);
+
+        let mut envelope = Envelope::parse(
+            "goal rainy_day no date\n    amount 1000\n    funding aggressive",
+            "assets:savings",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        assert!(envelope.is_untracked());
+
+        // untracked envelopes are never filled automatically...
+        let available = AmountPool::from(Amount {
+            mag: 5000.0,
+            symbol: None,
+        });
+        let today = NaiveDate::from_ymd(2020, 1, 10);
+        assert_eq!(
+            envelope
+                .get_filling_posting(&available, today)
+                .get_amount()
+                .mag,
+            0.0
+        );
+
+        // ...but they can be funded manually
+        let entry = Entry::parse(
+            "2020/01/10 * Saving up\n    envelope assets:savings rainy_day 250",
+            "%Y/%m/%d",
+            '.',
+            &accounts,
+        )
+        .unwrap();
+        envelope.process_entry(&entry).unwrap();
+        assert_eq!(envelope.get_next_amount().mag, 250.0);
+
+        let displayed = envelope.to_string();
+        assert!(displayed.contains("untracked / manual"));
+        assert!(displayed.contains("250"));
+        assert!(envelope
+            .relative_to(today)
+            .to_string()
+            .contains("untracked / manual"));
+
+        // untracked envelopes sort after every envelope with a due date
+        let rent = Envelope::parse(
+            "expense rent due every 15th\n    amount 300",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        let mut envelopes = [&envelope, &rent];
+        envelopes.sort();
+        assert_eq!(envelopes[0].get_name(), "rent");
+    }
+
+    #[test]
+    fn relative_due_date_test() {
+        let envelope = Envelope::parse(
+            "expense rent due every 15th\n    amount 300",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        let goal = Envelope::parse(
+            "goal laptop due 2020/01/01\n    amount 1000",
+            "assets:savings",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        let prelude = |e: &Envelope, day| e.next_prelude(Some(NaiveDate::from_ymd(2020, 1, day)));
+
+        // future
+        assert_eq!(prelude(&envelope, 12), "due in 3 days");
+        assert_eq!(prelude(&envelope, 14), "due in 1 day");
+
+        // today
+        assert_eq!(prelude(&goal, 1), "due today");
+
+        // past
+        assert_eq!(prelude(&goal, 2), "overdue by 1 day");
+        assert_eq!(prelude(&goal, 10), "overdue by 9 days");
+
+        let displayed = envelope
+            .relative_to(NaiveDate::from_ymd(2020, 1, 12))
+            .to_string();
+        assert!(displayed.contains("due in 3 days"));
+        assert!(!envelope.to_string().contains("due in"));
+    }
+
+    #[test]
+    fn note_test() {
+        let chunk = "expense insurance due every 5th
+    amount 100
+    note Car insurance — Geico policy #123";
+        let envelope = Envelope::parse(chunk, "assets:checking", '.', "%Y/%m/%d").unwrap();
+        assert_eq!(
+            envelope.note.as_deref(),
+            Some("Car insurance — Geico policy #123")
+        );
+
+        let reparsed = Envelope::parse(
+            &envelope.as_parsable("%Y/%m/%d"),
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        assert_eq!(reparsed.note, envelope.note);
+
+        assert!(envelope
+            .to_string()
+            .starts_with("    insurance\n      Car insurance — Geico policy #123\n"));
+        assert!(envelope
+            .as_json(NaiveDate::from_ymd(2020, 1, 1))
+            .ends_with(",\"note\":\"Car insurance — Geico policy #123\"}"));
+    }
+
+    #[test]
+    fn compact_display_test() {
+        let mut envelope = Envelope::parse(
+            "expense rent due every 5th\n    amount 100",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        envelope.now_amount.mag = 20.0;
+        envelope.next_amount.mag = 50.0;
+
+        assert_eq!(
+            format!("{:#}", envelope),
+            "    rent                                 20 /  50 /  100 |═════     |"
+        );
+    }
+
+    #[test]
+    fn long_name_display_test() {
+        let long_name = "a".repeat(80);
+        let chunk = format!("expense {} due every 5th\n    amount 100", long_name);
+        let envelope = Envelope::parse(&chunk, "assets:checking", '.', "%Y/%m/%d").unwrap();
+
+        let display = envelope.to_string();
+        let lines: Vec<&str> = display.lines().collect();
+
+        // the name is cut off with an ellipsis where the progress bars begin
+        let bar_column = lines[1].find('|').unwrap();
+        assert_eq!(lines[0].chars().count(), bar_column);
+        assert!(lines[0].ends_with('…'));
+
+        // both rows stay aligned
+        assert_eq!(lines[2].find('|').unwrap(), bar_column);
+
+        // short names aren't touched
+        let envelope = Envelope::parse(
+            "expense rent due every 5th\n    amount 100",
+            "assets:checking",
+            '.',
+            "%Y/%m/%d",
+        )
+        .unwrap();
+        assert!(envelope.to_string().starts_with("    rent\n"));
+    }
 }
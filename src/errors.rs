@@ -1,3 +1,4 @@
+use crate::utils;
 use std::error::Error;
 use std::fmt;
 use std::path::{Path, PathBuf};
@@ -64,6 +65,187 @@ impl SilverfoxError {
     pub fn file_error<P: AsRef<Path>>(path: P, error: std::io::Error) -> Self {
         Self::File(path.as_ref().to_path_buf(), error)
     }
+
+    /// Returns a short name for the kind of error this is
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SilverfoxError::Basic(_) => "basic",
+            SilverfoxError::Parse(_) => "parse",
+            SilverfoxError::Validation(_) => "validation",
+            SilverfoxError::Processing(_) => "processing",
+            SilverfoxError::File(_, _) => "file",
+            SilverfoxError::Csv(_) => "csv",
+        }
+    }
+
+    /// Returns the context of the error (usually the chunk of the journal it came from), if it
+    /// has one
+    pub fn get_context(&self) -> Option<&str> {
+        match self {
+            SilverfoxError::Parse(p) => p.context.as_deref(),
+            SilverfoxError::Validation(v) => v.context.as_deref(),
+            SilverfoxError::Processing(p) => p.context.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Sets the context of the error to `context`, unless it already has one. Only parse,
+    /// validation, and processing errors have contexts.
+    pub fn or_context(mut self, context: &str) -> Self {
+        let existing = match &mut self {
+            SilverfoxError::Parse(p) => &mut p.context,
+            SilverfoxError::Validation(v) => &mut v.context,
+            SilverfoxError::Processing(p) => &mut p.context,
+            _ => return self,
+        };
+
+        if existing.is_none() {
+            *existing = Some(context.to_string());
+        }
+
+        self
+    }
+
+    /// Returns the error as a diagnostic that points to where it happened in `source`, the text of
+    /// the journal at `path`. `context_lines` lines of the journal are shown before and after the
+    /// problem line, and the problem is underlined with carets. If the message mentions something
+    /// in backticks (like an account name), that's what is underlined; otherwise, the whole first
+    /// line of the error's context is.
+    ///
+    /// Returns None if the error has no context, or if its context can't be found in `source`.
+    pub fn as_diagnostic(&self, source: &str, path: &Path, context_lines: usize) -> Option<String> {
+        let source_lines = source.lines().collect::<Vec<&str>>();
+        let (line_index, column, width) = self.locate(&source_lines)?;
+
+        let first_shown = line_index.saturating_sub(context_lines);
+        let last_shown = (line_index + context_lines).min(source_lines.len() - 1);
+        let gutter_width = (last_shown + 1).to_string().len();
+
+        let mut s = format!(
+            "error: {}\n{:w$}--> {}:{}\n{:w$} |\n",
+            self.diagnostic_message(),
+            "",
+            path.display(),
+            line_index + 1,
+            "",
+            w = gutter_width
+        );
+        for (i, line) in source_lines
+            .iter()
+            .enumerate()
+            .take(last_shown + 1)
+            .skip(first_shown)
+        {
+            let numbered = format!("{:>w$} | {}", i + 1, line, w = gutter_width);
+            s.push_str(numbered.trim_end());
+            s.push('\n');
+
+            if i == line_index {
+                s.push_str(&format!(
+                    "{:w$} | {}{}\n",
+                    "",
+                    " ".repeat(column),
+                    "^".repeat(width.max(1)),
+                    w = gutter_width
+                ));
+            }
+        }
+
+        Some(s)
+    }
+
+    /// Returns the line number (starting at 1) that the error points to in `source`, the text of
+    /// the journal it came from (see `as_diagnostic`). Returns None if the error has no context,
+    /// or if its context can't be found in `source`.
+    pub fn line_in(&self, source: &str) -> Option<usize> {
+        let source_lines = source.lines().collect::<Vec<&str>>();
+        self.locate(&source_lines)
+            .map(|(line_index, _, _)| line_index + 1)
+    }
+
+    /// Returns where the error points to in `source_lines` (see `as_diagnostic`), as the index of
+    /// the line, the column that the problem starts at, and how many characters wide it is
+    fn locate(&self, source_lines: &[&str]) -> Option<(usize, usize, usize)> {
+        let context = self.get_context()?;
+
+        // find the context in the source, one line at a time. blank lines in the source are
+        // skipped, since chunks don't keep them
+        let mut wanted = context.lines().map(str::trim).filter(|l| !l.is_empty());
+        let first = wanted.next()?;
+        let start = source_lines.iter().position(|l| l.trim() == first)?;
+        let mut end = start;
+        for line in wanted {
+            match source_lines[end + 1..]
+                .iter()
+                .position(|l| l.trim() == line)
+            {
+                Some(i) => end += i + 1,
+                None => break,
+            }
+        }
+
+        // point at the first mention of something in backticks, or at the whole first line
+        let message = self.diagnostic_message();
+        let mentioned = message.split('`').nth(1).filter(|m| !m.is_empty());
+        let location = mentioned
+            .and_then(|m| {
+                (start..=end).find_map(|i| {
+                    source_lines[i]
+                        .find(m)
+                        .map(|c| (i, source_lines[i][..c].chars().count(), m.chars().count()))
+                })
+            })
+            .unwrap_or_else(|| {
+                let line = source_lines[start];
+                let indent = line.len() - line.trim_start().len();
+                (start, indent, line.trim().chars().count())
+            });
+
+        Some(location)
+    }
+
+    /// Returns the message shown in the error's diagnostic (see `as_diagnostic`)
+    fn diagnostic_message(&self) -> String {
+        match self {
+            SilverfoxError::Parse(p) => p.message.clone(),
+            SilverfoxError::Validation(v) => v.message.clone(),
+            SilverfoxError::Processing(p) => p.message.clone(),
+            _ => None,
+        }
+        .unwrap_or_else(|| format!("silverfox ran into a {} error here", self.kind()))
+    }
+
+    /// Returns the error as a JSON object with `kind`, `message`, `context`, and `line` fields,
+    /// for editors and other tools that read silverfox's errors. If `source` (the text of the
+    /// journal) is given, `line` is the line of the journal the error points to (see `line_in`).
+    /// Fields that aren't known are `null`.
+    pub fn as_json(&self, source: Option<&str>) -> String {
+        let (message, context, line) = match self {
+            SilverfoxError::Basic(s) => (Some(s.clone()), None, None),
+            SilverfoxError::Parse(p) => (p.message.clone(), p.context.clone(), None),
+            SilverfoxError::Validation(v) => (v.message.clone(), v.context.clone(), None),
+            SilverfoxError::Processing(p) => (p.message.clone(), p.context.clone(), None),
+            SilverfoxError::File(p, e) => {
+                (Some(e.to_string()), Some(p.display().to_string()), None)
+            }
+            SilverfoxError::Csv(c) => (Some(c.to_string()), None, c.position().map(|p| p.line())),
+        };
+        let line = line.or_else(|| self.line_in(source?).map(|l| l as u64));
+
+        let json_or_null = |s: Option<String>| match s {
+            Some(s) => utils::json_string(&s),
+            None => String::from("null"),
+        };
+
+        format!(
+            "{{\"kind\":{},\"message\":{},\"context\":{},\"line\":{}}}",
+            utils::json_string(self.kind()),
+            json_or_null(message),
+            json_or_null(context),
+            line.map(|l| l.to_string())
+                .unwrap_or_else(|| String::from("null"))
+        )
+    }
 }
 
 /// ParseError is thrown during the parsing phase of ledger construction. If silverfox can't parse
@@ -216,3 +398,82 @@ impl fmt::Display for ProcessingError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_test() {
+        let source = "account assets:checking
+account expenses:food
+
+2020/01/01 * Groceries
+    assets:cheking    -50
+    expenses:food      50
+
+2020/01/02 * Groceries
+    assets:checking   -20
+    expenses:food      20
+";
+        let chunk = "2020/01/01 * Groceries\n    assets:cheking    -50\n    expenses:food      50";
+        let err = SilverfoxError::from(
+            ValidationError::default()
+                .set_message("the account `assets:cheking` is not defined in your journal"),
+        )
+        .or_context(chunk);
+
+        let diagnostic = err
+            .as_diagnostic(source, Path::new("journal.sfox"), 2)
+            .unwrap();
+        let expected = "error: the account `assets:cheking` is not defined in your journal
+ --> journal.sfox:5
+  |
+3 |
+4 | 2020/01/01 * Groceries
+5 |     assets:cheking    -50
+  |     ^^^^^^^^^^^^^^
+6 |     expenses:food      50
+7 |
+";
+        assert_eq!(diagnostic, expected);
+
+        // without anything in backticks, the first line of the context is underlined
+        let err = SilverfoxError::from(ParseError {
+            context: Some(String::from("2020/01/02 * Groceries")),
+            message: Some(String::from("something's wrong")),
+        });
+        let diagnostic = err
+            .as_diagnostic(source, Path::new("journal.sfox"), 0)
+            .unwrap();
+        assert!(diagnostic.ends_with("8 | 2020/01/02 * Groceries\n  | ^^^^^^^^^^^^^^^^^^^^^^\n"));
+
+        // errors that can't be found in the source have no diagnostic
+        assert!(SilverfoxError::Basic(String::from("oops"))
+            .as_diagnostic(source, Path::new("journal.sfox"), 2)
+            .is_none());
+    }
+
+    #[test]
+    fn as_json_test() {
+        let err = SilverfoxError::from(ParseError {
+            context: Some(String::from("2020/01/01 ! \"Groceries\"")),
+            message: Some(String::from("couldn't parse status")),
+        });
+
+        assert_eq!(
+            err.as_json(None),
+            r#"{"kind":"parse","message":"couldn't parse status","context":"2020/01/01 ! \"Groceries\"","line":null}"#
+        );
+
+        // with the journal, the line the error points to is known
+        let source = "account expenses:groceries\n\n2020/01/01 ! \"Groceries\"\n";
+        assert!(err.as_json(Some(source)).ends_with(r#""line":3}"#));
+
+        let err = SilverfoxError::Basic(String::from("line one\nline two"));
+        assert_eq!(
+            err.as_json(None),
+            r#"{"kind":"basic","message":"line one\nline two","context":null,"line":null}"#
+        );
+    }
+}
@@ -1,28 +1,167 @@
-use crate::errors::SilverfoxError;
-use crate::ledger::Ledger;
+use crate::account::AccountMatcher;
+use crate::amount::{AmountFormat, RoundingMode};
+use crate::color::ColorChoice;
+use crate::entry::EntryStatus;
+use crate::errors::{SilverfoxError, ValidationError};
+use crate::importer::CsvImporter;
+use crate::ledger::{
+    BudgetFormat, ImportOptions, JournalFormat, Ledger, LedgerOptions, Period, TableStyle,
+    DEFAULT_MAX_DATE_GAP_DAYS,
+};
+use crate::posting::PostingFormat;
+use crate::utils;
+use chrono::{Local, NaiveDate};
 use std::convert::TryFrom;
 use std::env;
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 pub struct CommandFlags {
     pub file_path: Option<PathBuf>,
     pub subcommand: Subcommand,
     pub no_move: bool,
 
-    pub csv_file: Option<PathBuf>,
+    /// Whether the entry that fills envelopes should only be displayed, not added to the journal
+    pub dry_fill: bool,
+
+    /// Whether csv data to import should be read from stdin instead of a file
+    pub stdin: bool,
+
+    /// Whether pending entries should be left out of envelope processing
+    pub confirmed_only: bool,
+
+    /// Whether envelopes can be defined outside of accounts, funded by all asset accounts
+    pub merge_envelopes: bool,
+
+    /// Whether the journal can include the output of commands
+    pub allow_exec: bool,
+
+    /// Whether the journal can include URLs
+    pub allow_network: bool,
+
+    /// If set (with `--current`), entries dated after this day are left out
+    pub as_of: Option<NaiveDate>,
+
+    /// Whether envelopes should be displayed on one line each
+    pub compact: bool,
+
+    /// Whether envelope due dates should be displayed relative to today, like "due in 3 days"
+    pub relative_dates: bool,
+
+    /// Whether balances should be valued in the native currency with the journal's prices
+    pub market: bool,
+
+    /// How reports are written
+    pub format: ReportFormat,
+
+    /// Whether reports should end with a line of total assets, liabilities, and net worth
+    pub summary: bool,
+
+    /// Whether to show how much each envelope needs to be filled on time, instead of balances
+    pub plan: bool,
+
+    /// Whether envelopes from every account should be displayed in one list, by due date, and
+    /// whether balances should be displayed with full account names instead of as a tree
+    pub flat: bool,
+
+    /// How amounts are rounded when they're displayed and when entries and balance assertions
+    /// are checked
+    pub rounding: RoundingMode,
+
+    /// The locale preset amounts are displayed with, instead of the journal's `locale`
+    pub locale: Option<AmountFormat>,
+
+    /// If set (with `--context`), errors in the journal are shown with this many lines of the
+    /// journal around them
+    pub context_lines: Option<usize>,
+
+    /// How many days an entry can be from the others before `validate` warns about its date
+    pub max_date_gap_days: i64,
+
+    /// Whether warnings about the journal should be treated as errors
+    pub strict: bool,
+
+    /// Whether imports should print which subrules each csv record matched
+    pub verbose: bool,
+
+    /// Whether imports should keep records that are already in the journal
+    pub allow_duplicates: bool,
+
+    /// Whether imported entries should only be displayed, not added to the journal. Envelopes
+    /// aren't filled either, so the journal isn't touched at all.
+    pub dry_run: bool,
+
+    /// A regular expression that reports match account names against, instead of the substring
+    /// given as an argument
+    pub account_regex: Option<String>,
+
+    /// The tag (or metadata key) that entries in the register need, with the value it needs if
+    /// one was given like `--tag key=value`
+    pub tag_match: Option<(String, Option<String>)>,
+
+    pub csv_files: Vec<PathBuf>,
     pub rules_file: Option<PathBuf>,
 
+    /// If set, the character that separates fields in imported csv data, instead of the one in
+    /// the rules
+    pub csv_delimiter: Option<u8>,
+
+    /// qif files to import, and the account they were exported from
+    pub qif_files: Vec<PathBuf>,
+    pub qif_account: Option<String>,
+
     pub other_accounts: bool,
     pub begin_date: Option<chrono::NaiveDate>,
     pub end_date: Option<chrono::NaiveDate>,
+    pub through_date: Option<chrono::NaiveDate>,
+    pub step: Period,
+
+    /// The format of the journal file, if it shouldn't be guessed from the file's extension
+    pub file_format: Option<JournalFormat>,
+
+    /// How errors should be written
+    pub error_format: ErrorFormat,
+
+    /// Whether output should be colored
+    pub color: ColorChoice,
+
+    /// How postings are laid out when silverfox writes entries to the journal
+    pub posting_format: PostingFormat,
+
+    /// The date of an entry made with `new`, if it isn't today
+    pub date: Option<NaiveDate>,
+
+    /// The status of an entry made with `new`
+    pub status: EntryStatus,
+
+    /// The description of an entry made with `new`
+    pub description: Option<String>,
+
+    /// The payee of an entry made with `new`
+    pub payee: Option<String>,
+
+    /// The postings of an entry made with `new`, each written like a posting in the journal
+    pub postings: Vec<String>,
+
+    /// Any arguments that aren't flags, in the order they were given
+    pub arguments: Vec<String>,
 }
 
 impl CommandFlags {
     pub fn parse_from_env() -> Result<Self, SilverfoxError> {
-        let mut args = env::args();
+        Self::parse_from_args(env::args().skip(1), Local::today().naive_local())
+    }
+
+    /// Parses flags from `args`, which shouldn't include the name of the program. `today` is used
+    /// for any flags relative to the current date.
+    fn parse_from_args<I>(args: I, today: NaiveDate) -> Result<Self, SilverfoxError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
 
         // parse subcommand
-        let subcommand = match args.nth(1) {
+        let subcommand = match args.next() {
             Some(a) => Subcommand::try_from(a.as_str())?,
             None => {
                 display_help();
@@ -34,32 +173,240 @@ impl CommandFlags {
             file_path: None,
             subcommand,
             no_move: false,
-            csv_file: None,
+            dry_fill: false,
+            stdin: false,
+            confirmed_only: false,
+            merge_envelopes: false,
+            allow_exec: false,
+            allow_network: false,
+            as_of: None,
+            compact: false,
+            relative_dates: false,
+            market: false,
+            summary: false,
+            format: ReportFormat::Text,
+            plan: false,
+            flat: false,
+            rounding: RoundingMode::default(),
+            locale: None,
+            context_lines: None,
+            max_date_gap_days: DEFAULT_MAX_DATE_GAP_DAYS,
+            strict: false,
+            verbose: false,
+            allow_duplicates: false,
+            dry_run: false,
+            account_regex: None,
+            tag_match: None,
+            csv_files: Vec::new(),
             rules_file: None,
+            csv_delimiter: None,
+            qif_files: Vec::new(),
+            qif_account: None,
             other_accounts: false,
             begin_date: None,
             end_date: None,
+            through_date: None,
+            step: Period::Monthly,
+            file_format: None,
+            error_format: ErrorFormat::Prose,
+            color: ColorChoice::default(),
+            posting_format: PostingFormat::default(),
+            date: None,
+            status: EntryStatus::Pending,
+            description: None,
+            payee: None,
+            postings: Vec::new(),
+            arguments: Vec::new(),
         };
 
         while let Some(arg) = args.next() {
+            // long flags can have their values attached, like `--color=always`
+            let (arg, inline_value) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => {
+                    (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+                }
+                _ => (arg, None),
+            };
+
             // match boolean flags first
             match arg.as_str() {
                 "--no-move" | "-n" => {
                     flags.no_move = true;
                 }
+                "--dry-fill" => {
+                    flags.dry_fill = true;
+                }
+                "--stdin" => {
+                    flags.stdin = true;
+                }
+                "--confirmed-only" => {
+                    flags.confirmed_only = true;
+                }
+                "--merge-envelopes-across-accounts" => {
+                    flags.merge_envelopes = true;
+                }
+                "--allow-exec" => {
+                    flags.allow_exec = true;
+                }
+                "--allow-network" => {
+                    flags.allow_network = true;
+                }
+                "--current" => {
+                    flags.as_of = Some(today);
+                }
+                "--compact" => {
+                    flags.compact = true;
+                }
+                "--relative-dates" => {
+                    flags.relative_dates = true;
+                }
+                "--market" | "-V" => {
+                    flags.market = true;
+                }
+                "--summary" | "-s" => {
+                    flags.summary = true;
+                }
+                "--json" => {
+                    flags.format = ReportFormat::Json;
+                }
+                "--tabular" => {
+                    flags.format = ReportFormat::Tsv;
+                }
+                "--table" => {
+                    flags.format = ReportFormat::Table;
+                }
+                "--plan" => {
+                    flags.plan = true;
+                }
+                "--flat" | "--flat-envelopes" => {
+                    flags.flat = true;
+                }
+                "--tree" => {
+                    flags.flat = false;
+                }
+                "--strict" => {
+                    flags.strict = true;
+                }
+                "--verbose" => {
+                    flags.verbose = true;
+                }
+                "--allow-duplicates" => {
+                    flags.allow_duplicates = true;
+                }
+                "--dry-run" => {
+                    flags.dry_run = true;
+                }
+                a if !a.starts_with('-') => {
+                    flags.arguments.push(arg);
+                }
                 _ => {
                     // then flags that require arguments
-                    let arg_value = parse_argument_value(args.next(), &arg)?;
+                    let arg_value = match inline_value {
+                        Some(v) => v,
+                        None => parse_argument_value(args.next(), &arg)?,
+                    };
                     match arg.as_str() {
                         "-f" | "--file" => {
                             flags.file_path = Some(PathBuf::from(arg_value));
                         }
                         "--csv-file" | "--csv" => {
-                            flags.csv_file = Some(PathBuf::from(arg_value));
+                            flags.csv_files.push(PathBuf::from(arg_value));
                         }
                         "--rules-file" | "--rules" => {
                             flags.rules_file = Some(PathBuf::from(arg_value));
                         }
+                        "--csv-delimiter" => {
+                            flags.csv_delimiter = Some(CsvImporter::parse_delimiter(&arg_value)?);
+                        }
+                        "--qif" => {
+                            flags.qif_files.push(PathBuf::from(arg_value));
+                        }
+                        "--qif-account" => {
+                            flags.qif_account = Some(arg_value);
+                        }
+                        "-b" | "--begin" | "--since" => {
+                            flags.begin_date = Some(parse_date_value(&arg_value, &arg)?);
+                        }
+                        "-e" | "--end" => {
+                            flags.end_date = Some(parse_date_value(&arg_value, &arg)?);
+                        }
+                        "--last" => {
+                            flags.begin_date = Some(parse_relative_date(&arg_value, today)?);
+                        }
+                        "--period" => {
+                            let (begin, end) = parse_date_range(&arg_value, today)?;
+                            flags.begin_date = Some(begin);
+                            flags.end_date = Some(end);
+                        }
+                        "--file-format" | "--compat" => {
+                            flags.file_format = Some(JournalFormat::parse(&arg_value)?);
+                        }
+                        "--error-format" => {
+                            flags.error_format = ErrorFormat::parse(&arg_value)?;
+                        }
+                        "--color" => {
+                            flags.color = ColorChoice::parse(&arg_value)?;
+                        }
+                        "--format" => {
+                            flags.format = ReportFormat::parse(&arg_value)?;
+                        }
+                        "--account-regex" => {
+                            flags.account_regex = Some(arg_value);
+                        }
+                        "--tag" => {
+                            flags.tag_match = Some(match arg_value.split_once('=') {
+                                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                                None => (arg_value, None),
+                            });
+                        }
+                        "--context" => match arg_value.parse::<usize>() {
+                            Ok(n) => flags.context_lines = Some(n),
+                            Err(_) => {
+                                return Err(SilverfoxError::Basic(format!(
+                                    "`{}` isn't a number of lines that silverfox can show around errors",
+                                    arg_value
+                                )))
+                            }
+                        },
+                        "--max-date-gap" => match arg_value.parse::<i64>() {
+                            Ok(n) if n > 0 => flags.max_date_gap_days = n,
+                            _ => {
+                                return Err(SilverfoxError::Basic(format!(
+                                    "`{}` isn't a number of days that silverfox can use for a date gap",
+                                    arg_value
+                                )))
+                            }
+                        },
+                        "--rounding" => {
+                            flags.rounding = RoundingMode::parse(&arg_value)?;
+                        }
+                        "--locale" => {
+                            flags.locale = Some(AmountFormat::from_locale(&arg_value)?);
+                        }
+                        "--posting-format" => {
+                            flags.posting_format = PostingFormat::parse(&arg_value)?;
+                        }
+                        "--step" => {
+                            flags.step = Period::parse(&arg_value)?;
+                        }
+                        "--through" => {
+                            flags.through_date = Some(parse_date_value(&arg_value, &arg)?);
+                        }
+                        "--date" => {
+                            flags.date = Some(parse_date_value(&arg_value, &arg)?);
+                        }
+                        "--status" => {
+                            flags.status = arg_value.parse::<EntryStatus>()?;
+                        }
+                        "--desc" | "--description" => {
+                            flags.description = Some(arg_value);
+                        }
+                        "--payee" => {
+                            flags.payee = Some(arg_value);
+                        }
+                        "--posting" => {
+                            flags.postings.push(arg_value);
+                        }
                         _ => {
                             return Err(SilverfoxError::Basic(
                                 format!(
@@ -77,6 +424,11 @@ impl CommandFlags {
     }
 
     pub fn execute(&self) -> Result<(), SilverfoxError> {
+        self.rounding.set_default();
+        if let Some(l) = self.locale {
+            l.set_default();
+        }
+
         let file_path = if let Some(f) = &self.file_path {
             f.to_owned()
         } else if let Some(e) = get_file_from_env() {
@@ -87,56 +439,517 @@ impl CommandFlags {
     - set the environment variable $SILVERFOX_FILE or $LEDGER_FILE to a path to a file")));
         };
 
-        let mut ledger = Ledger::from_file(&file_path)?;
+        // validation reports every problem in the journal, so it can't stop at the first error
+        // like the rest of the subcommands do
+        if let Subcommand::Validate = self.subcommand {
+            return validate(
+                &file_path,
+                &self.ledger_options(),
+                self.error_format,
+                self.context_lines,
+                self.max_date_gap_days,
+                self.strict,
+            );
+        }
 
-        if !self.no_move {
-            if let Err(e) = ledger.fill_envelopes() {
-                return Err(e);
+        // the journal has to be read before the ledger is, since reading the ledger changes the
+        // working directory
+        let source = self
+            .context_lines
+            .and_then(|_| std::fs::read_to_string(&file_path).ok());
+        let mut ledger = Ledger::from_file(&file_path, &self.ledger_options()).map_err(|e| {
+            match diagnose(
+                &e,
+                &source,
+                &file_path,
+                self.context_lines,
+                self.error_format,
+            ) {
+                Some(d) => SilverfoxError::Basic(d.trim_end().to_string()),
+                None => e,
+            }
+        })?;
+
+        if self.locale.is_none() {
+            if let Some(l) = ledger.get_amount_format() {
+                l.set_default();
             }
         }
 
+        // filling envelopes and most subcommands write to the journal, so with --strict, the
+        // journal is checked before anything can be written to it
+        self.report_warnings(&ledger, Local::today().naive_local(), io::stderr())?;
+
+        // a dry fill only shows the entry, so everything after it sees the envelopes unfilled
+        if self.dry_fill {
+            match ledger.dry_fill_envelopes(Local::today().naive_local()) {
+                Some(entry) => println!(
+                    "silverfox would add this entry to fill your envelopes:\n\n{}",
+                    entry
+                ),
+                None => println!("your envelopes don't need filling right now\n"),
+            }
+        } else if !self.no_move && !self.dry_run {
+            ledger.fill_envelopes(Local::today().naive_local())?;
+        }
+
         match self.subcommand {
-            Subcommand::Balance => ledger.display_flat_balance()?,
-            Subcommand::Envelopes => ledger.display_envelopes(),
-            Subcommand::Register => ledger.display_register(self.begin_date, self.end_date, None),
+            Subcommand::Account => {
+                let account_name = match self.arguments.first() {
+                    Some(a) => a,
+                    None => return Err(SilverfoxError::Basic(String::from("silverfox needs to know which account to show, like so: `silverfox account assets:checking`"))),
+                };
+                let as_of = self.as_of.unwrap_or_else(|| Local::today().naive_local());
+
+                ledger.display_account_detail(account_name, as_of, self.color)?
+            }
+            Subcommand::Assertions => ledger.display_assertions_report(self.color)?,
+            Subcommand::Balance if self.format == ReportFormat::Tsv => {
+                ledger.display_tsv_balance()?
+            }
+            Subcommand::Balance if self.flat => ledger.display_flat_balance(
+                self.account_matcher()?,
+                self.market_date(),
+                self.color,
+            )?,
+            Subcommand::Balance => ledger.display_tree_balance(
+                self.account_matcher()?,
+                self.market_date(),
+                self.color,
+            )?,
+            Subcommand::Envelopes if self.format == ReportFormat::Json => {
+                ledger.display_envelopes_json(Local::today().naive_local())?
+            }
+            Subcommand::Envelopes if self.plan => {
+                ledger.display_contribution_plans(Local::today().naive_local())
+            }
+            Subcommand::Envelopes if self.flat => {
+                ledger.display_flat_envelopes(Local::today().naive_local())?
+            }
+            Subcommand::Envelopes => ledger.display_envelopes(
+                self.compact,
+                Some(Local::today().naive_local()).filter(|_| self.relative_dates),
+                self.color,
+            ),
+            Subcommand::Export => match self.format {
+                ReportFormat::Text | ReportFormat::Ledger => {
+                    print!("{}", ledger.export_ledger_format())
+                }
+                _ => {
+                    return Err(SilverfoxError::Basic(String::from(
+                        "journals can only be exported with `--format ledger`",
+                    )))
+                }
+            },
+            Subcommand::ExportBudget => match self.format.budget_format() {
+                Some(format) => ledger.display_budget_export(
+                    format,
+                    self.as_of.unwrap_or_else(|| Local::today().naive_local()),
+                )?,
+                None => return Err(SilverfoxError::Basic(String::from(
+                    "budgets can only be exported with `--format beancount` or `--format ledger`",
+                ))),
+            },
+            Subcommand::Register if self.format == ReportFormat::Json => ledger
+                .display_register_json(
+                    self.begin_date,
+                    self.end_date,
+                    self.account_matcher()?,
+                    self.tag_match.clone(),
+                )?,
+            Subcommand::Register if self.format == ReportFormat::JsonLines => ledger
+                .display_register_json_lines(
+                    self.begin_date,
+                    self.end_date,
+                    self.account_matcher()?,
+                    self.tag_match.clone(),
+                )?,
+            Subcommand::Register => ledger.display_register(
+                self.begin_date,
+                self.end_date,
+                self.account_matcher()?,
+                self.tag_match.clone(),
+                self.format.table_style(),
+            )?,
+            Subcommand::Import if self.stdin => {
+                let rules_file = match self.rules_file.clone().or_else(CsvImporter::get_env_rules_path) {
+                    Some(r) => r,
+                    None => return Err(SilverfoxError::Basic(String::from("if you're importing csv data from stdin, you need to specify a rules file with the --rules flag (or $SILVERFOX_RULES)"))),
+                };
+
+                let mut csv_str = String::new();
+                if let Err(e) = io::stdin().read_to_string(&mut csv_str) {
+                    return Err(SilverfoxError::Basic(format!("silverfox couldn't read csv data from stdin: {}", e)));
+                }
+
+                let skipped = ledger.import_csv_str(
+                    &csv_str,
+                    &rules_file,
+                    self.csv_delimiter,
+                    &self.import_options(),
+                )?;
+                print_skipped_duplicates(skipped);
+                return Ok(());
+            }
+            Subcommand::Import if !self.qif_files.is_empty() => {
+                let account = match &self.qif_account {
+                    Some(a) => a,
+                    None => return Err(SilverfoxError::Basic(String::from("silverfox needs to know which account your qif files are for, like so: `silverfox import --qif bank.qif --qif-account assets:checking`"))),
+                };
+
+                let skipped =
+                    ledger.import_qif(&self.qif_files, account, &self.import_options())?;
+                print_skipped_duplicates(skipped);
+                return Ok(());
+            }
             Subcommand::Import => {
-                match &self.csv_file {
-                    Some(c) => {
-                        return ledger.import_csv(c, self.rules_file.as_ref())
-                    },
-                    None => {
-                        return Err(SilverfoxError::Basic(String::from("if you're importing a csv file, you need to specify the csv file with the --csv flag")))
-                    },
+                if self.csv_files.is_empty() {
+                    return Err(SilverfoxError::Basic(String::from("if you're importing a csv file, you need to specify the csv file with the --csv flag (or a qif file with --qif)")));
+                }
+
+                let skipped = ledger.import_csv(
+                    &self.csv_files,
+                    self.rules_file.as_ref(),
+                    self.csv_delimiter,
+                    &self.import_options(),
+                )?;
+                print_skipped_duplicates(skipped);
+                return Ok(());
+            }
+            Subcommand::New => {
+                let description = match &self.description {
+                    Some(d) => d,
+                    None => return Err(SilverfoxError::Basic(String::from("silverfox needs a description for the new entry, like so: `silverfox new --desc Groceries --posting \"expenses:food 20\" --posting assets:checking`"))),
+                };
+                let date = self.date.unwrap_or_else(|| Local::today().naive_local());
+
+                ledger.new_entry_and_save(
+                    date,
+                    self.status,
+                    description,
+                    self.payee.as_deref(),
+                    &self.postings,
+                )?;
+                println!("added `{}` to your journal", description);
+            }
+            Subcommand::Allocate => {
+                let (amount, account, envelope) = self.allocation()?;
+                let date = self.date.unwrap_or_else(|| Local::today().naive_local());
+
+                if let Some(warning) =
+                    ledger.allocate_and_save(date, &amount, &account, &envelope)?
+                {
+                    eprintln!("warning: {}", warning);
                 }
+                println!("allocated {} to `{}` in `{}`", amount, envelope, account);
+            }
+            Subcommand::Reconcile => {
+                let account_name = match self.arguments.first() {
+                    Some(a) => a,
+                    None => return Err(SilverfoxError::Basic(String::from("silverfox needs to know which account to reconcile, like so: `silverfox reconcile assets:checking --through 2020/01/31`"))),
+                };
+                let through = self.through_date.unwrap_or_else(|| Local::today().naive_local());
+
+                let count = ledger.reconcile_and_save(account_name, through)?;
+                println!("reconciled {} entries in `{}`", count, account_name);
+            }
+            Subcommand::Rename => {
+                let (old, new) = match (self.arguments.first(), self.arguments.get(1)) {
+                    (Some(o), Some(n)) => (o, n),
+                    _ => return Err(SilverfoxError::Basic(String::from("silverfox needs the account to rename and its new name, like so: `silverfox rename expenses:food expenses:groceries`"))),
+                };
+
+                let count = ledger.rename_account_and_save(old, new)?;
+                println!("renamed `{}` to `{}` in {} entries", old, new, count);
+            }
+            Subcommand::Reverse => {
+                let description = match self.arguments.first() {
+                    Some(d) => d,
+                    None => return Err(SilverfoxError::Basic(String::from("silverfox needs part of the description of the entry to reverse, like so: `silverfox reverse Groceries`"))),
+                };
+
+                let reversed =
+                    ledger.reverse_entry_and_save(description, Local::today().naive_local())?;
+                println!("added a reversal of `{}`", reversed);
+            }
+            Subcommand::Summary => ledger.display_summary()?,
+            Subcommand::Snapshot => {
+                ledger.display_balance_snapshots(self.begin_date, self.end_date, self.step)?
             }
             // Subcommand::Register => ledger.display_register(self.period, self.begin_date, self.end_date),
             _ => return Err(SilverfoxError::Basic(format!("the `{}` subcommand is recognized by silverfox, but not supported yet. sorry :(", self.subcommand))),
         }
 
+        if self.summary && self.subcommand.is_report() {
+            println!("{}", ledger.summary_line()?);
+        }
+
         Ok(())
     }
+
+    /// Writes the ledger's warnings (see `Ledger::get_warnings`) to `writer`. With `--strict`,
+    /// returns an error if there are any instead, after printing each of them as an error.
+    fn report_warnings<W: Write>(
+        &self,
+        ledger: &Ledger,
+        today: NaiveDate,
+        writer: W,
+    ) -> Result<(), SilverfoxError> {
+        let warnings = ledger.get_warnings(today, self.max_date_gap_days);
+        let errors = check_warnings(warnings, self.strict, writer);
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        for e in &errors {
+            self.error_format.print(e, None);
+        }
+
+        Err(SilverfoxError::Basic(format!(
+            "silverfox found {} warning(s) in your journal, which are errors with --strict",
+            errors.len()
+        )))
+    }
+
+    /// Returns the matcher for the accounts a report should be about. `--account-regex` is used if
+    /// it's given; otherwise, the first argument is matched as a plain substring of account names.
+    fn account_matcher(&self) -> Result<Option<AccountMatcher>, SilverfoxError> {
+        match &self.account_regex {
+            Some(pattern) => AccountMatcher::regex(pattern).map(Some),
+            None => Ok(self
+                .arguments
+                .first()
+                .cloned()
+                .map(AccountMatcher::Substring)),
+        }
+    }
+
+    /// Returns the amount, account, and envelope of an allocation, from arguments like `50 to
+    /// assets:checking:groceries`. The envelope is the last part of the account name.
+    fn allocation(&self) -> Result<(String, String, String), SilverfoxError> {
+        let usage = || {
+            SilverfoxError::Basic(String::from("silverfox needs an amount and the envelope to allocate it to, like so: `silverfox allocate 50 to assets:checking:groceries`"))
+        };
+
+        let arguments = self.arguments.join(" ");
+        let (amount, target) = arguments.rsplit_once(" to ").ok_or_else(usage)?;
+        let (account, envelope) = target.trim().rsplit_once(':').ok_or_else(usage)?;
+        if amount.trim().is_empty() || account.is_empty() || envelope.is_empty() {
+            return Err(usage());
+        }
+
+        Ok((
+            amount.trim().to_string(),
+            account.to_string(),
+            envelope.to_string(),
+        ))
+    }
+
+    /// Returns the day that holdings are valued on with `--market`: the `--current` day, or today
+    fn market_date(&self) -> Option<NaiveDate> {
+        if self.market {
+            Some(self.as_of.unwrap_or_else(|| Local::today().naive_local()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the options used to read the journal
+    fn ledger_options(&self) -> LedgerOptions {
+        LedgerOptions {
+            format: self.file_format,
+            confirmed_only: self.confirmed_only,
+            merge_envelopes: self.merge_envelopes,
+            allow_exec: self.allow_exec,
+            allow_network: self.allow_network,
+            skip_balance_assertions: matches!(self.subcommand, Subcommand::Assertions),
+            posting_format: self.posting_format,
+            as_of: self.as_of,
+        }
+    }
+
+    /// Returns the options used to import csv and qif files
+    fn import_options(&self) -> ImportOptions {
+        ImportOptions {
+            verbose: self.verbose,
+            allow_duplicates: self.allow_duplicates,
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+/// How reports are written to stdout. Not every report supports every format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    /// Columns for reading in a terminal
+    Text,
+
+    /// Tab-separated values, for pasting into spreadsheets
+    Tsv,
+
+    /// JSON, for other programs
+    Json,
+
+    /// JSON lines, with one object per line, for streaming to other programs
+    JsonLines,
+
+    /// A table with box-drawing borders
+    Table,
+
+    /// A table with ASCII borders, for terminals that can't show box-drawing characters
+    AsciiTable,
+
+    /// beancount directives, for exporting budgets
+    Beancount,
+
+    /// ledger-cli syntax, for exporting budgets and journals
+    Ledger,
+}
+
+impl ReportFormat {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "text" => Ok(Self::Text),
+            "tsv" => Ok(Self::Tsv),
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::JsonLines),
+            "table" => Ok(Self::Table),
+            "ascii-table" => Ok(Self::AsciiTable),
+            "beancount" => Ok(Self::Beancount),
+            "ledger" => Ok(Self::Ledger),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't an output format silverfox knows. try `text`, `tsv`, `json`, `jsonl`, `table`, `ascii-table`, `beancount`, or `ledger`",
+                s
+            ))),
+        }
+    }
+
+    /// Returns the border style for table formats, or None if the format isn't a table
+    pub fn table_style(self) -> Option<TableStyle> {
+        match self {
+            Self::Table => Some(TableStyle::Unicode),
+            Self::AsciiTable => Some(TableStyle::Ascii),
+            _ => None,
+        }
+    }
+
+    /// Returns the budget format for `export-budget`, which is beancount unless another one is
+    /// asked for. Formats that aren't for budgets return None.
+    pub fn budget_format(self) -> Option<BudgetFormat> {
+        match self {
+            Self::Text | Self::Beancount => Some(BudgetFormat::Beancount),
+            Self::Ledger => Some(BudgetFormat::Ledger),
+            _ => None,
+        }
+    }
+}
+
+/// How errors are written to stderr
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// Friendly, human-readable explanations
+    Prose,
+
+    /// One JSON object per error (see `SilverfoxError::as_json`)
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "prose" | "text" => Ok(Self::Prose),
+            "json" => Ok(Self::Json),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't an error format silverfox knows. try `prose` or `json`",
+                s
+            ))),
+        }
+    }
+
+    /// Finds the error format in the command line arguments. This is separate from the rest of
+    /// the flags so that errors in parsing the other flags can be written in the right format,
+    /// too.
+    pub fn from_env() -> Self {
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = if arg == "--error-format" {
+                args.next()
+            } else {
+                arg.strip_prefix("--error-format=").map(String::from)
+            };
+
+            if let Some(f) = value.and_then(|v| Self::parse(&v).ok()) {
+                return f;
+            }
+        }
+
+        Self::Prose
+    }
+
+    /// Prints `error` to stderr. `source` is the text of the journal the error came from, if
+    /// it's known, so that JSON errors can say which line they're on.
+    pub fn print(self, error: &SilverfoxError, source: Option<&str>) {
+        match self {
+            Self::Prose => eprintln!("{}", error),
+            Self::Json => eprintln!("{}", error.as_json(source)),
+        }
+    }
 }
 
 pub enum Subcommand {
     Summary,
+    Account,
+    Allocate,
+    Assertions,
     Balance,
     Envelopes,
+    Export,
+    ExportBudget,
     Register,
     Import,
     New,
+    Reconcile,
+    Rename,
+    Reverse,
+    Snapshot,
+    Validate,
 }
 
 impl Subcommand {
     pub fn display(&self) -> String {
         String::from(match self {
             Self::Summary => "summary",
+            Self::Account => "account",
+            Self::Allocate => "allocate",
+            Self::Assertions => "assertions",
             Self::Balance => "balance",
             Self::Envelopes => "envelopes",
+            Self::Export => "export",
+            Self::ExportBudget => "export-budget",
             Self::Register => "register",
             Self::Import => "import",
             Self::New => "new",
+            Self::Reconcile => "reconcile",
+            Self::Rename => "rename",
+            Self::Reverse => "reverse",
+            Self::Snapshot => "snapshot",
+            Self::Validate => "validate",
         })
     }
+
+    /// Returns true if this subcommand displays a report about the journal, rather than changing it
+    pub fn is_report(&self) -> bool {
+        matches!(
+            self,
+            Self::Account
+                | Self::Assertions
+                | Self::Balance
+                | Self::Envelopes
+                | Self::Register
+                | Self::Snapshot
+                | Self::Summary
+        )
+    }
 }
 
 impl std::fmt::Display for Subcommand {
@@ -149,14 +962,29 @@ impl TryFrom<&str> for Subcommand {
     type Error = SilverfoxError;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
+        // subcommands that share a first letter with another subcommand have to be spelled out
+        match s {
+            "allocate" => return Ok(Self::Allocate),
+            "assertions" => return Ok(Self::Assertions),
+            "reconcile" => return Ok(Self::Reconcile),
+            "rename" => return Ok(Self::Rename),
+            "reverse" => return Ok(Self::Reverse),
+            "snapshot" => return Ok(Self::Snapshot),
+            "export" => return Ok(Self::Export),
+            "export-budget" => return Ok(Self::ExportBudget),
+            _ => (),
+        }
+
         if let Some(c) = s.chars().next() {
             match c {
                 's' => Ok(Self::Summary),
+                'a' => Ok(Self::Account),
                 'b' => Ok(Self::Balance),
                 'e' => Ok(Self::Envelopes),
                 'r' => Ok(Self::Register),
                 'i' => Ok(Self::Import),
                 'n' => Ok(Self::New),
+                'v' => Ok(Self::Validate),
                 _ =>
                     Err(SilverfoxError::Basic(format!("`{}` is not a recognized subcommand. subcommands need to be the first argument made to silverfox. did you misplace your subcommand?", s)))
             }
@@ -169,11 +997,24 @@ impl TryFrom<&str> for Subcommand {
 fn display_help() {
     println!("hello! i'm silverfox!");
     println!("you can use one of the subcommands to get information about your journal:");
-    println!("    (b)alance      display all accounts and their respective values");
-    println!("    (e)nvelopes    view your envelopes and how much is saved up in each");
-    println!("    (r)egister     list all transactions");
-    println!("    (n)ew          add a new transaction to your journal");
-    println!("    (i)mport       parse entries from a csv file and add them to your journal");
+    println!("    (s)ummary      see an overview of your net worth, envelopes, and journal");
+    println!("    (a)ccount      show an account's balance, envelopes, and latest entries");
+    println!("    (b)alance      display all accounts (or only those matching an argument) and their respective values as a tree, or as a list with --flat. --market values commodities with your journal's prices");
+    println!("    (e)nvelopes    view your envelopes and how much is saved up in each. --relative-dates shows due dates like \"due in 3 days\"");
+    println!("    (r)egister     list all transactions, or only those in a range with --begin, --end, or --period (like last-month), or only those with a --tag (like reviewed or category=groceries). --format jsonl writes one JSON object per line");
+    println!("    (n)ew          add a new transaction to your journal with --desc and a --posting for each posting");
+    println!("    (i)mport       parse entries from a csv file (or from stdin with --stdin) and add them to your journal. --verbose shows which subrules each record matched. qif files can be imported with --qif and --qif-account. records already in your journal are skipped unless --allow-duplicates is given. --dry-run shows what would be imported without changing your journal");
+    println!("    (v)alidate     check your journal and list every problem found in it");
+    println!("    allocate       move money into an envelope, like so: `silverfox allocate 50 to assets:checking:groceries`");
+    println!("    assertions     list every balance assertion in your journal and whether it holds, even if some of them fail");
+    println!("    export         write your journal for ledger-cli and hledger, with --format ledger (the default)");
+    println!("    export-budget  write your envelopes as a budget for other programs, with --format beancount (the default) or --format ledger");
+    println!("    reconcile      mark an account's entries as reconciled, up to a date given with --through");
+    println!(
+        "    rename         rename an account (and its subaccounts) everywhere in your journal"
+    );
+    println!("    reverse        add an entry dated today that undoes the latest entry matching a description");
+    println!("    snapshot       output a csv of account balances at the end of every --step (month by default)");
     // println!();
     // println!("you can get more information about each subcommand with the --help flag, like so:");
     // println!("    silverfox b --help")
@@ -186,6 +1027,173 @@ fn parse_argument_value(arg: Option<String>, name: &str) -> Result<String, Silve
     }
 }
 
+/// Parses the journal at `file_path` leniently, printing every error found. Returns an error
+/// summarizing how many problems were found, if any.
+fn validate(
+    file_path: &Path,
+    options: &LedgerOptions,
+    error_format: ErrorFormat,
+    context_lines: Option<usize>,
+    max_date_gap_days: i64,
+    strict: bool,
+) -> Result<(), SilverfoxError> {
+    // json errors say which line of the journal they're on, so they need the journal, too
+    let source = (context_lines.is_some() || error_format == ErrorFormat::Json)
+        .then(|| std::fs::read_to_string(file_path).ok())
+        .flatten();
+    let (ledger, mut errors) = Ledger::from_file_lenient(file_path, options)?;
+
+    for entry in ledger.unbalanced_entries() {
+        errors.push(SilverfoxError::from(
+            ValidationError::default()
+                .set_message("this entry's postings don't add up to zero")
+                .set_context(&entry.as_full_string()),
+        ));
+    }
+
+    let warnings = ledger.get_warnings(Local::today().naive_local(), max_date_gap_days);
+    errors.append(&mut check_warnings(warnings, strict, io::stderr()));
+
+    if errors.is_empty() {
+        println!("your journal looks good!");
+        return Ok(());
+    }
+
+    for e in &errors {
+        match diagnose(e, &source, file_path, context_lines, error_format) {
+            Some(d) => eprint!("{}", d),
+            None => error_format.print(e, source.as_deref()),
+        }
+
+        // prose errors span multiple lines, so they need some space between them
+        if error_format == ErrorFormat::Prose {
+            eprintln!();
+        }
+    }
+
+    Err(SilverfoxError::Basic(format!(
+        "silverfox found {} problem(s) in your journal",
+        errors.len()
+    )))
+}
+
+/// Warnings are only advice, so normally they're written to `writer` and not counted as problems.
+/// In strict mode, each warning is returned as an error instead.
+fn check_warnings<W: Write>(
+    warnings: Vec<String>,
+    strict: bool,
+    mut writer: W,
+) -> Vec<SilverfoxError> {
+    if strict {
+        warnings
+            .iter()
+            .map(|w| SilverfoxError::from(ValidationError::default().set_message(w)))
+            .collect()
+    } else {
+        // being only advice, a warning that can't be written isn't worth failing over
+        for warning in warnings {
+            writeln!(writer, "warning: {}", warning).ok();
+        }
+
+        Vec::new()
+    }
+}
+
+/// Returns `error` as a diagnostic with `context_lines` lines of `source` around it (see
+/// `SilverfoxError::as_diagnostic`). Returns None if no context lines were asked for, if errors
+/// aren't written as prose, or if the error can't be found in the source.
+fn diagnose(
+    error: &SilverfoxError,
+    source: &Option<String>,
+    file_path: &Path,
+    context_lines: Option<usize>,
+    error_format: ErrorFormat,
+) -> Option<String> {
+    if error_format != ErrorFormat::Prose {
+        return None;
+    }
+
+    error.as_diagnostic(source.as_ref()?, file_path, context_lines?)
+}
+
+/// Parses a date given on the command line. Since the journal hasn't been read yet, silverfox's
+/// default date format is used, but ISO 8601 dates are accepted as well.
+fn parse_date_value(value: &str, name: &str) -> Result<NaiveDate, SilverfoxError> {
+    NaiveDate::parse_from_str(value, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .map_err(|_| SilverfoxError::Basic(format!("silverfox couldn't understand `{}` as a date for `{}`. try a date like 2020/01/31", value, name)))
+}
+
+/// Parses a span of time like `30d`, `2w`, `3m`, or `1y`, and returns the date that far before
+/// `today`.
+fn parse_relative_date(value: &str, today: NaiveDate) -> Result<NaiveDate, SilverfoxError> {
+    let err = || {
+        SilverfoxError::Basic(format!(
+            "silverfox couldn't understand `{}` as a span of time. try a number followed by `d`, `w`, `m`, or `y`, like `30d`",
+            value
+        ))
+    };
+
+    let split_index = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+    let num = value[..split_index].parse::<i64>().map_err(|_| err())?;
+
+    // anything that doesn't fit in an i32 is far outside the range of dates silverfox can handle
+    let num = i32::try_from(num).map_err(|_| err())?;
+    let date = match &value[split_index..] {
+        "d" => today.checked_sub_signed(chrono::Duration::days(i64::from(num))),
+        "w" => num
+            .checked_mul(7)
+            .and_then(|days| today.checked_sub_signed(chrono::Duration::days(i64::from(days)))),
+        "m" => utils::checked_subtract_months(today, num),
+        "y" => num
+            .checked_mul(12)
+            .and_then(|months| utils::checked_subtract_months(today, months)),
+        _ => None,
+    };
+    date.ok_or_else(err)
+}
+
+/// Parses a named range of dates relative to `today`: `this-` or `last-` followed by `day`,
+/// `week`, `month`, or `year` (like `last-month`), or `ytd` for the start of the year through
+/// today. Returns the first and last dates of the range.
+fn parse_date_range(
+    value: &str,
+    today: NaiveDate,
+) -> Result<(NaiveDate, NaiveDate), SilverfoxError> {
+    if value == "ytd" {
+        return Ok((Period::Yearly.start_of(today), today));
+    }
+
+    let err = || {
+        SilverfoxError::Basic(format!(
+            "silverfox couldn't understand `{}` as a period. try `this-month`, `last-month`, `ytd`, or the like",
+            value
+        ))
+    };
+
+    let (which, period) = value.split_once('-').ok_or_else(err)?;
+    let period = Period::parse(period).map_err(|_| err())?;
+    let date = match which {
+        "this" => today,
+        // the day before this period starts is in the last one, even across years
+        "last" => period.start_of(today).pred(),
+        _ => return Err(err()),
+    };
+
+    Ok((period.start_of(date), period.end_of(date)))
+}
+
+/// Tells the user how many imported records were skipped because they were already in the
+/// journal, if any were
+fn print_skipped_duplicates(skipped: usize) {
+    if skipped > 0 {
+        println!(
+            "skipped {} record(s) that were already in your journal (use --allow-duplicates to import them anyway)",
+            skipped
+        );
+    }
+}
+
 fn get_file_from_env() -> Option<PathBuf> {
     if let Ok(v) = env::var("SILVERFOX_FILE") {
         Some(PathBuf::from(v))
@@ -195,3 +1203,348 @@ fn get_file_from_env() -> Option<PathBuf> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn last_flag_test() {
+        let today = NaiveDate::from_ymd(2020, 3, 15);
+
+        let flags = CommandFlags::parse_from_args(args("register --last 30d"), today).unwrap();
+        assert_eq!(flags.begin_date, Some(NaiveDate::from_ymd(2020, 2, 14)));
+        assert_eq!(flags.end_date, None);
+
+        let flags =
+            CommandFlags::parse_from_args(args("register --last 1m --end 2020/03/01"), today)
+                .unwrap();
+        assert_eq!(flags.begin_date, Some(NaiveDate::from_ymd(2020, 2, 15)));
+        assert_eq!(flags.end_date, Some(NaiveDate::from_ymd(2020, 3, 1)));
+    }
+
+    #[test]
+    fn period_flag_test() {
+        let range = |s: &str, today: NaiveDate| {
+            let flags =
+                CommandFlags::parse_from_args(args(&format!("register --period {}", s)), today)
+                    .unwrap();
+            (flags.begin_date.unwrap(), flags.end_date.unwrap())
+        };
+        let today = NaiveDate::from_ymd(2020, 3, 15);
+
+        assert_eq!(
+            range("this-month", today),
+            (
+                NaiveDate::from_ymd(2020, 3, 1),
+                NaiveDate::from_ymd(2020, 3, 31)
+            )
+        );
+        assert_eq!(
+            range("last-month", today),
+            (
+                NaiveDate::from_ymd(2020, 2, 1),
+                NaiveDate::from_ymd(2020, 2, 29)
+            )
+        );
+        assert_eq!(
+            range("ytd", today),
+            (NaiveDate::from_ymd(2020, 1, 1), today)
+        );
+
+        // last month in january is december of the year before
+        assert_eq!(
+            range("last-month", NaiveDate::from_ymd(2021, 1, 20)),
+            (
+                NaiveDate::from_ymd(2020, 12, 1),
+                NaiveDate::from_ymd(2020, 12, 31)
+            )
+        );
+
+        assert!(
+            CommandFlags::parse_from_args(args("register --period next-month"), today).is_err()
+        );
+    }
+
+    #[test]
+    fn begin_end_flags_test() {
+        let today = NaiveDate::from_ymd(2020, 3, 15);
+
+        let flags =
+            CommandFlags::parse_from_args(args("register -b 2024/01/01 -e 2024-03-31"), today)
+                .unwrap();
+        assert_eq!(flags.begin_date, Some(NaiveDate::from_ymd(2024, 1, 1)));
+        assert_eq!(flags.end_date, Some(NaiveDate::from_ymd(2024, 3, 31)));
+
+        assert!(CommandFlags::parse_from_args(args("register -b 01/01/2024"), today).is_err());
+    }
+
+    #[test]
+    fn color_flag_test() {
+        let today = NaiveDate::from_ymd(2020, 3, 15);
+
+        let flags = CommandFlags::parse_from_args(args("balance"), today).unwrap();
+        assert_eq!(flags.color, ColorChoice::Auto);
+
+        let flags = CommandFlags::parse_from_args(args("balance --color=always"), today).unwrap();
+        assert_eq!(flags.color, ColorChoice::Always);
+
+        let flags = CommandFlags::parse_from_args(args("balance --color never"), today).unwrap();
+        assert_eq!(flags.color, ColorChoice::Never);
+
+        assert!(CommandFlags::parse_from_args(args("balance --color=sometimes"), today).is_err());
+    }
+
+    #[test]
+    fn new_flags_test() {
+        let today = NaiveDate::from_ymd(2020, 3, 15);
+        let args = [
+            "new",
+            "--date",
+            "2020/03/14",
+            "--desc",
+            "Lunch out",
+            "--posting",
+            "expenses:food 12.50",
+            "--posting=assets:checking",
+        ];
+
+        let flags =
+            CommandFlags::parse_from_args(args.iter().map(|s| s.to_string()), today).unwrap();
+        assert_eq!(flags.date, Some(NaiveDate::from_ymd(2020, 3, 14)));
+        assert_eq!(flags.status, EntryStatus::Pending);
+        assert_eq!(flags.description, Some(String::from("Lunch out")));
+        assert_eq!(flags.payee, None);
+        assert_eq!(
+            flags.postings,
+            vec![
+                String::from("expenses:food 12.50"),
+                String::from("assets:checking")
+            ]
+        );
+    }
+
+    #[test]
+    fn is_report_test() {
+        assert!(Subcommand::Balance.is_report());
+        assert!(Subcommand::Summary.is_report());
+        assert!(!Subcommand::New.is_report());
+        assert!(!Subcommand::Import.is_report());
+    }
+
+    #[test]
+    fn parse_relative_date_test() {
+        let today = NaiveDate::from_ymd(2020, 3, 31);
+
+        assert_eq!(
+            parse_relative_date("2w", today).unwrap(),
+            NaiveDate::from_ymd(2020, 3, 17)
+        );
+        assert_eq!(
+            parse_relative_date("1m", today).unwrap(),
+            NaiveDate::from_ymd(2020, 2, 29)
+        );
+        assert_eq!(
+            parse_relative_date("1y", today).unwrap(),
+            NaiveDate::from_ymd(2019, 3, 31)
+        );
+        assert!(parse_relative_date("30", today).is_err());
+        assert!(parse_relative_date("d", today).is_err());
+        assert!(parse_relative_date("100000000d", today).is_err());
+        assert!(parse_relative_date("999999y", today).is_err());
+        assert!(parse_relative_date("5000000000m", today).is_err());
+    }
+
+    #[test]
+    fn strict_test() {
+        let (ledger, errors) = Ledger::parse_lenient(
+            "account assets:checking
+account income:salary
+
+2999/01/01 ? Paycheck
+    assets:checking  100
+    income:salary
+",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let today = NaiveDate::from_ymd(2020, 1, 1);
+        let flags = |s: &str| CommandFlags::parse_from_args(args(s), today).unwrap();
+
+        // an entry far in the future is warned about, but isn't an error
+        assert!(flags("balance")
+            .report_warnings(&ledger, today, io::sink())
+            .is_ok());
+        assert!(check_warnings(
+            ledger.get_warnings(today, DEFAULT_MAX_DATE_GAP_DAYS),
+            false,
+            io::sink()
+        )
+        .is_empty());
+
+        // unless the run is strict, imports included
+        assert!(flags("balance --strict")
+            .report_warnings(&ledger, today, io::sink())
+            .is_err());
+        assert!(flags("import --strict")
+            .report_warnings(&ledger, today, io::sink())
+            .is_err());
+        assert_eq!(
+            check_warnings(
+                ledger.get_warnings(today, DEFAULT_MAX_DATE_GAP_DAYS),
+                true,
+                io::sink()
+            )
+            .len(),
+            1
+        );
+
+        // without any warnings, strict mode is fine
+        let (clean, _) = Ledger::parse_lenient("account assets:checking\n");
+        assert!(flags("balance --strict")
+            .report_warnings(&clean, today, io::sink())
+            .is_ok());
+    }
+
+    #[test]
+    fn report_warnings_test() {
+        let (ledger, errors) = Ledger::parse_lenient(
+            "account assets:checking
+    expense rent due every 1st
+        amount 1500
+
+account income:salary
+
+2020/01/01 * Paycheck
+    assets:checking  800
+    income:salary
+",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let today = NaiveDate::from_ymd(2020, 1, 15);
+        let flags = CommandFlags::parse_from_args(args("balance"), today).unwrap();
+
+        // on a normal run, warnings like an envelope that can't be funded are written out
+        let mut output = Vec::new();
+        assert!(flags.report_warnings(&ledger, today, &mut output).is_ok());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("warning: "), "{}", output);
+        assert!(output.contains("`rent`"), "{}", output);
+    }
+
+    #[test]
+    fn report_date_warnings_test() {
+        let (ledger, errors) = Ledger::parse_lenient(
+            "account assets:checking
+account expenses:food
+
+2020/01/01 * Groceries
+    assets:checking  -20
+    expenses:food
+
+2020/01/10 * Groceries
+    assets:checking  -30
+    expenses:food
+
+2020/06/01 * Groceries
+    assets:checking  -25
+    expenses:food
+",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let today = NaiveDate::from_ymd(2020, 6, 15);
+        let report = |s: &str| {
+            let flags = CommandFlags::parse_from_args(args(s), today).unwrap();
+            let mut output = Vec::new();
+            assert!(flags.report_warnings(&ledger, today, &mut output).is_ok());
+            String::from_utf8(output).unwrap()
+        };
+
+        // dates that look like typos are written out on a normal run, using the run's max gap
+        assert!(report("balance").is_empty());
+        let output = report("balance --max-date-gap 100");
+        assert!(output.starts_with("warning: "), "{}", output);
+        assert!(output.contains("`2020/06/01 Groceries`"), "{}", output);
+    }
+
+    #[test]
+    fn strict_execute_test() {
+        let path = std::env::temp_dir().join("silverfox_strict_execute_test.sfox");
+        let journal = "account assets:checking
+account income:salary
+
+2999/01/01 ? Paycheck
+    assets:checking  100
+    income:salary
+";
+        std::fs::write(&path, journal).unwrap();
+
+        let today = NaiveDate::from_ymd(2020, 1, 1);
+        let run = |s: &str| {
+            CommandFlags::parse_from_args(args(&format!("{} -f {}", s, path.display())), today)
+                .unwrap()
+                .execute()
+        };
+
+        // with --strict, a command that changes the journal fails without writing anything
+        assert!(run("rename income:salary income:wages --strict").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), journal);
+
+        // without it, the warning doesn't stop the command
+        assert!(run("rename income:salary income:wages").is_ok());
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("account income:wages"));
+    }
+
+    #[test]
+    fn tag_flag_test() {
+        let tag_match = |s: &str| {
+            CommandFlags::parse_from_args(args(s), NaiveDate::from_ymd(2020, 3, 15))
+                .unwrap()
+                .tag_match
+        };
+
+        assert_eq!(
+            tag_match("register --tag category=groceries"),
+            Some((String::from("category"), Some(String::from("groceries"))))
+        );
+        assert_eq!(
+            tag_match("register --tag reviewed"),
+            Some((String::from("reviewed"), None))
+        );
+        assert_eq!(tag_match("register"), None);
+    }
+
+    #[test]
+    fn allocation_test() {
+        let allocation = |s: &str| {
+            CommandFlags::parse_from_args(args(s), NaiveDate::from_ymd(2020, 3, 15))
+                .unwrap()
+                .allocation()
+        };
+
+        assert_eq!(
+            allocation("allocate 50 to assets:checking:groceries").unwrap(),
+            (
+                String::from("50"),
+                String::from("assets:checking"),
+                String::from("groceries")
+            )
+        );
+        assert_eq!(
+            allocation("allocate 50 USD to assets:checking:groceries")
+                .unwrap()
+                .0,
+            "50 USD"
+        );
+        assert!(allocation("allocate 50 assets:checking:groceries").is_err());
+        assert!(allocation("allocate 50 to groceries").is_err());
+    }
+}
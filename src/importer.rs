@@ -1,9 +1,12 @@
+use crate::amount::Amount;
 use crate::entry::{Entry, EntryStatus};
 use crate::errors::*;
 use crate::posting::{ClassicPosting, Posting};
 use crate::utils;
+use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::collections::{LinkedList, VecDeque};
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -11,45 +14,104 @@ pub struct CsvImporter {
     rules: Rules,
     records: VecDeque<csv::StringRecord>,
     ledger_account_set: HashSet<String>,
+
+    /// Whether to print which subrules each record matched as it's imported (see
+    /// `Rules::explain_record`)
+    verbose: bool,
 }
 
 impl CsvImporter {
+    /// Creates an importer from a csv file and the rules file next to it. If `delimiter` is given,
+    /// it's used instead of the `delimiter` rule.
     pub fn from_file(
         csv_file: &Path,
         ledger_account_set: HashSet<String>,
+        delimiter: Option<u8>,
     ) -> Result<Self, SilverfoxError> {
         let rules_file = Self::get_sibling_rules_path(csv_file);
 
-        Self::from_file_with_rules(csv_file, &rules_file, ledger_account_set)
+        Self::from_file_with_rules(csv_file, &rules_file, ledger_account_set, delimiter)
+    }
+
+    /// Parses the character that separates fields in csv data. Besides single characters, the
+    /// names `comma`, `semicolon`, `tab`, and `pipe` can be used.
+    pub fn parse_delimiter(s: &str) -> Result<u8, SilverfoxError> {
+        let c = match s.trim() {
+            "comma" => ',',
+            "semicolon" => ';',
+            "tab" | "\\t" => '\t',
+            "pipe" => '|',
+            s if s.chars().count() == 1 => s.chars().next().unwrap(),
+            s => {
+                return Err(SilverfoxError::Basic(format!(
+                    "`{}` can't be used as a csv delimiter. use a single character, or `comma`, `semicolon`, `tab`, or `pipe`",
+                    s
+                )))
+            }
+        };
+
+        if c.is_ascii() {
+            Ok(c as u8)
+        } else {
+            Err(SilverfoxError::Basic(format!(
+                "`{}` can't be used as a csv delimiter because it isn't an ascii character",
+                c
+            )))
+        }
     }
 
     fn get_sibling_rules_path(original: &Path) -> PathBuf {
         PathBuf::from(format!("{}.rules", original.display()))
     }
 
+    /// Returns the rules file set with the `$SILVERFOX_RULES` environment variable, if there is
+    /// one. It's used for csv files that aren't given a rules file explicitly.
+    pub fn get_env_rules_path() -> Option<PathBuf> {
+        std::env::var_os("SILVERFOX_RULES").map(PathBuf::from)
+    }
+
     pub fn from_file_with_rules(
         csv_file: &Path,
         rules_file: &Path,
         ledger_account_set: HashSet<String>,
+        delimiter: Option<u8>,
     ) -> Result<Self, SilverfoxError> {
         let csv_str =
             fs::read_to_string(csv_file).map_err(|e| SilverfoxError::file_error(csv_file, e))?;
         let rules_str = fs::read_to_string(rules_file)
             .map_err(|e| SilverfoxError::file_error(rules_file, e))?;
 
-        Self::from_strs(&csv_str, &rules_str, ledger_account_set)
+        Self::from_strs(&csv_str, &rules_str, ledger_account_set, delimiter)
+    }
+
+    /// Creates an importer from csv data that has already been read (from stdin, for example).
+    /// Rules can't be found next to data that doesn't come from a file, so the rules file always
+    /// needs to be given.
+    pub fn from_str_with_rules(
+        csv_str: &str,
+        rules_file: &Path,
+        ledger_account_set: HashSet<String>,
+        delimiter: Option<u8>,
+    ) -> Result<Self, SilverfoxError> {
+        let rules_str = fs::read_to_string(rules_file)
+            .map_err(|e| SilverfoxError::file_error(rules_file, e))?;
+
+        Self::from_strs(csv_str, &rules_str, ledger_account_set, delimiter)
     }
 
     fn from_strs(
         csv_str: &str,
         rules_str: &str,
         ledger_account_set: HashSet<String>,
+        delimiter: Option<u8>,
     ) -> Result<Self, SilverfoxError> {
+        let rules = Rules::from_str(rules_str)?;
+
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(false)
+            .delimiter(delimiter.unwrap_or(rules.delimiter))
             .from_reader(csv_str.as_bytes());
 
-        let rules = Rules::from_str(rules_str)?;
         let mut records: VecDeque<csv::StringRecord> = VecDeque::new();
         for result in reader.records().skip(rules.skip as usize) {
             match result {
@@ -67,8 +129,14 @@ impl CsvImporter {
             rules,
             records,
             ledger_account_set,
+            verbose: false,
         })
     }
+
+    /// Sets whether to print which subrules each record matched as it's imported
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
 }
 
 impl Iterator for CsvImporter {
@@ -78,6 +146,10 @@ impl Iterator for CsvImporter {
         match self.records.pop_front() {
             None => None,
             Some(r) => {
+                if self.verbose {
+                    println!("{}", self.rules.explain_record(&r));
+                }
+
                 Some(
                     self.rules
                         .get_entry_from_record(&r, &self.ledger_account_set.iter().collect()),
@@ -87,10 +159,212 @@ impl Iterator for CsvImporter {
     }
 }
 
+/// The `!Type` headers of qif transactions that can be imported. Investment transactions have
+/// fields that mean other things, so they can't be.
+const QIF_TYPES: &[&str] = &["Bank", "Cash", "CCard", "Oth A", "Oth L"];
+
+/// Imports transactions from a qif file. Each transaction becomes an entry with a posting to one
+/// account (the account the qif file was exported from), balanced like a csv record with only
+/// one posting (see `balancing_posting`).
+///
+/// Only the date (`D`), amount (`T`), payee (`P`), memo (`M`), and cleared status (`C`) of each
+/// transaction are read.
+pub struct QifImporter {
+    records: VecDeque<Vec<(char, String)>>,
+    account: String,
+    decimal_symbol: char,
+}
+
+impl QifImporter {
+    pub fn from_file(
+        qif_file: &Path,
+        account: &str,
+        decimal_symbol: char,
+    ) -> Result<Self, SilverfoxError> {
+        let qif_str =
+            fs::read_to_string(qif_file).map_err(|e| SilverfoxError::file_error(qif_file, e))?;
+
+        Self::from_str(&qif_str, account, decimal_symbol)
+    }
+
+    /// Creates an importer from qif data that has already been read. Every entry will have a
+    /// posting to `account`.
+    pub fn from_str(
+        qif_str: &str,
+        account: &str,
+        decimal_symbol: char,
+    ) -> Result<Self, SilverfoxError> {
+        let mut records = VecDeque::new();
+        let mut record = Vec::new();
+
+        for line in qif_str.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(header) = line.strip_prefix("!Type:") {
+                if !QIF_TYPES.contains(&header.trim()) {
+                    return Err(SilverfoxError::Basic(format!(
+                        "silverfox can only import bank, cash, and credit card transactions from qif files, not `{}`",
+                        line
+                    )));
+                }
+            } else if line.starts_with('!') {
+                // other headers (like `!Option:AutoSwitch`) don't change how transactions are read
+            } else if line == "^" {
+                if !record.is_empty() {
+                    records.push_back(std::mem::take(&mut record));
+                }
+            } else {
+                let mut chars = line.chars();
+                if let Some(field) = chars.next() {
+                    record.push((field, chars.as_str().trim().to_string()));
+                }
+            }
+        }
+
+        // the last transaction might not be closed with a `^`
+        if !record.is_empty() {
+            records.push_back(record);
+        }
+
+        Ok(Self {
+            records,
+            account: account.to_string(),
+            decimal_symbol,
+        })
+    }
+
+    fn get_entry_from_record(&self, record: &[(char, String)]) -> Result<Entry, SilverfoxError> {
+        let field = |f: char| {
+            record
+                .iter()
+                .find(|(c, _)| *c == f)
+                .map(|(_, v)| v.as_str())
+                .filter(|v| !v.is_empty())
+        };
+        let context = || {
+            record
+                .iter()
+                .map(|(c, v)| format!("{}{}", c, v))
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        let date = match field('D').and_then(parse_qif_date) {
+            Some(d) => d,
+            None => {
+                return Err(SilverfoxError::from(ParseError {
+                    message: Some(String::from(
+                        "this qif transaction doesn't have a date silverfox can read. dates should look like `01/02/2020` or `1/2'20`",
+                    )),
+                    context: Some(context()),
+                }))
+            }
+        };
+
+        // thousands separators are whichever of `.` and `,` isn't the decimal symbol
+        let thousands_separator = if self.decimal_symbol == '.' { ',' } else { '.' };
+        let amount = match field('T').or_else(|| field('U')) {
+            Some(t) => Amount::parse(&t.replace(thousands_separator, ""), self.decimal_symbol)
+                .map_err(|e| SilverfoxError::from(e).or_context(&context()))?,
+            None => {
+                return Err(SilverfoxError::from(ParseError {
+                    message: Some(String::from("this qif transaction doesn't have an amount")),
+                    context: Some(context()),
+                }))
+            }
+        };
+
+        let status = match field('C') {
+            Some("X") | Some("x") | Some("R") => EntryStatus::Reconciled,
+            _ => EntryStatus::Cleared,
+        };
+
+        let memo = field('M').map(String::from);
+        let (description, comment) = match field('P') {
+            Some(p) => (p.to_string(), memo),
+            None => (
+                memo.unwrap_or_else(|| String::from("qif transaction")),
+                None,
+            ),
+        };
+
+        let postings = vec![
+            Posting::from(ClassicPosting::new(
+                &self.account,
+                Some(amount.clone()),
+                None,
+                None,
+            )),
+            balancing_posting(&amount),
+        ];
+
+        Ok(Entry::new(
+            date,
+            status,
+            description,
+            None,
+            postings,
+            comment,
+        ))
+    }
+}
+
+impl Iterator for QifImporter {
+    type Item = Result<Entry, SilverfoxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records
+            .pop_front()
+            .map(|r| self.get_entry_from_record(&r))
+    }
+}
+
+/// Parses a qif date, which is month first, like `01/02/2020`. Two-digit years are in the 1900s
+/// from 70 on, unless they follow an apostrophe (like `1/2'20`), which means the 2000s.
+fn parse_qif_date(s: &str) -> Option<chrono::NaiveDate> {
+    let parts = s
+        .split(|c| "/-.'".contains(c))
+        .map(|p| p.trim().parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .ok()?;
+
+    let (month, day, year) = match parts[..] {
+        [m, d, y] => (m, d, y),
+        _ => return None,
+    };
+
+    let year = match year {
+        y if y >= 100 => y,
+        y if s.contains('\'') || y < 70 => 2000 + y,
+        y => 1900 + y,
+    };
+
+    chrono::NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+/// Returns a blank posting that balances an entry with only one posting, of `amount`. Money that
+/// leaves goes to `expenses:unknown`, and money that comes in is from `income:unknown`.
+fn balancing_posting(amount: &Amount) -> Posting {
+    let account = if amount.mag < 0.0 {
+        "expenses:unknown"
+    } else if amount.mag > 0.0 {
+        "income:unknown"
+    } else {
+        // don't freak out about amounts with zero amounts
+        "unknown"
+    };
+
+    Posting::from(ClassicPosting::new(account, None, None, None))
+}
+
 #[derive(Clone, Debug, PartialEq)]
 struct Rules {
     accounts: HashMap<String, String>,
     amount_strs: HashMap<String, String>,
+
+    /// Amounts of money out and money in, for csv files that split them into two columns. A
+    /// posting without an `amount` rule gets the credit minus the debit.
+    debit_strs: HashMap<String, String>,
+    credit_strs: HashMap<String, String>,
+
     comment: String,
     description: String,
     date_format: String,
@@ -101,6 +375,13 @@ struct Rules {
     skip: i32,
     status: String,
     subrules: Vec<Subrules>,
+
+    /// If true, records that only produce one posting are errors, instead of being balanced with
+    /// an `unknown` account
+    strict: bool,
+
+    /// The character that separates fields in the csv data
+    delimiter: u8,
 }
 
 impl Default for Rules {
@@ -111,6 +392,8 @@ impl Default for Rules {
         Rules {
             accounts,
             amount_strs,
+            debit_strs: Default::default(),
+            credit_strs: Default::default(),
             comment: Default::default(),
             date_format: String::from("%Y/%m/%d"),
             date_str: String::from("%date%"),
@@ -121,6 +404,8 @@ impl Default for Rules {
             skip: 1,
             status: String::from("~"),
             subrules: Default::default(),
+            strict: false,
+            delimiter: b',',
         }
     }
 }
@@ -148,7 +433,7 @@ impl Rules {
         let mut parsing_subrules_rules = false;
 
         for mut line in s.lines() {
-            line = utils::remove_comments(line);
+            line = remove_rule_comments(line);
 
             // if line contains nothing, continue
             if line.trim().is_empty() {
@@ -167,7 +452,7 @@ impl Rules {
                         } else {
                             // a line starting with a non-whitespace character is a pattern to the
                             // Subrules
-                            (*s).patterns.push(String::from(line));
+                            (*s).patterns.push(SubrulePattern::parse(line)?);
                         }
                     }
 
@@ -203,7 +488,7 @@ impl Rules {
                 parsing_subrules = Some(Subrules::from(&*self));
                 if let Some(i) = line.chars().position(|c| c.is_whitespace()) {
                     match parsing_subrules.as_mut() {
-                        Some(s) => (*s).patterns.push(String::from(&line[i + 1..])),
+                        Some(s) => (*s).patterns.push(SubrulePattern::parse(&line[i + 1..])?),
                         None => unreachable!(), // should be unreachable, as parsing_subrules was just initialized as Some
                     }
                 }
@@ -251,6 +536,7 @@ impl Rules {
                 "date_format" => self.date_format = String::from("%Y/%m/%d"),
                 "date" => self.date_str = String::from("%date%"),
                 "decimal_symbol" | "decimal" => self.decimal_symbol = '.',
+                "delimiter" => self.delimiter = b',',
                 "description" => self.description = String::from("%description%"),
                 "fields" => {
                     return Err(SilverfoxError::from(ValidationError {
@@ -273,10 +559,15 @@ impl Rules {
                 "payee" => self.payee = String::new(),
                 "skip" => self.skip = 1,
                 "status" => self.status = String::from("~"),
+                "strict" => self.strict = false,
                 _ => {
                     // attempt parsing an amount index or an account index
                     if let Some(stripped) = rule_name.strip_prefix("amount") {
                         self.amount_strs.remove(&String::from(stripped));
+                    } else if let Some(stripped) = rule_name.strip_prefix("debit") {
+                        self.debit_strs.remove(&String::from(stripped));
+                    } else if let Some(stripped) = rule_name.strip_prefix("credit") {
+                        self.credit_strs.remove(&String::from(stripped));
                     } else if let Some(stripped) = rule_name.strip_prefix("account") {
                         self.accounts.remove(&String::from(stripped));
                     } else {
@@ -308,6 +599,7 @@ impl Rules {
                         self.decimal_symbol = rule_value.chars().next().unwrap();
                     }
                 }
+                "delimiter" => self.delimiter = CsvImporter::parse_delimiter(&rule_value)?,
                 "description" => self.description = rule_value,
                 "fields" => {
                     for field_name in rule_value.split(',') {
@@ -342,10 +634,28 @@ impl Rules {
                 "status" => {
                     self.status = rule_value;
                 }
+                "strict" => {
+                    self.strict = match rule_value.trim() {
+                        "true" | "yes" => true,
+                        "false" | "no" => false,
+                        _ => {
+                            return Err(SilverfoxError::from(ParseError {
+                                message: Some(String::from(
+                                    "the `strict` rule should be `true` or `false`",
+                                )),
+                                context: Some(line.to_string()),
+                            }))
+                        }
+                    }
+                }
                 _ => {
                     // attempt parsing an amount index or an account index
                     if let Some(stripped) = rule_name.strip_prefix("amount") {
                         self.amount_strs.insert(String::from(stripped), rule_value);
+                    } else if let Some(stripped) = rule_name.strip_prefix("debit") {
+                        self.debit_strs.insert(String::from(stripped), rule_value);
+                    } else if let Some(stripped) = rule_name.strip_prefix("credit") {
+                        self.credit_strs.insert(String::from(stripped), rule_value);
                     } else if let Some(stripped) = rule_name.strip_prefix("account") {
                         self.accounts.insert(String::from(stripped), rule_value);
                     } else {
@@ -364,6 +674,50 @@ impl Rules {
         Ok(())
     }
 
+    /// Returns a description of which subrules apply to the record, listing the patterns of each
+    /// subrule that was tested. Subrules are tested in order, and the first one that applies is
+    /// used, so the subrules after it aren't listed.
+    fn explain_record(&self, record: &csv::StringRecord) -> String {
+        let mut s = format!(
+            "record `{}`",
+            record.iter().collect::<Vec<&str>>().join(",")
+        );
+
+        if self.subrules.is_empty() {
+            s.push_str("\n    there are no subrules, so the main rules were used");
+            return s;
+        }
+
+        for (i, subrules) in self.subrules.iter().enumerate() {
+            let patterns = subrules
+                .patterns
+                .iter()
+                .map(|p| format!("`{}`", p))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            match subrules.matching_pattern(record) {
+                Some(p) => {
+                    s.push_str(&format!(
+                        "\n    subrule {} tested {}: matched `{}`",
+                        i + 1,
+                        patterns,
+                        p
+                    ));
+                    return s;
+                }
+                None => s.push_str(&format!(
+                    "\n    subrule {} tested {}: no match",
+                    i + 1,
+                    patterns
+                )),
+            }
+        }
+
+        s.push_str("\n    no subrules matched, so the main rules were used");
+        s
+    }
+
     pub fn get_entry_from_record(
         &mut self,
         record: &csv::StringRecord,
@@ -383,8 +737,9 @@ impl Rules {
                 .insert(String::from(""), String::from("%account%"));
         }
 
-        // if accounts are blank, add default
-        if self.amount_strs.is_empty() {
+        // if amounts are blank, add default
+        if self.amount_strs.is_empty() && self.debit_strs.is_empty() && self.credit_strs.is_empty()
+        {
             self.amount_strs
                 .insert(String::from(""), String::from("%amount%"));
         }
@@ -442,12 +797,16 @@ impl Rules {
         // make postings from account and amount sets
         let mut postings: Vec<Posting> = Vec::new();
         for (index, account_name) in self.accounts.iter() {
-            let raw_value = match self.amount_strs.get(index) {
-                Some(amount_str) => format!("{} {}", account_name, amount_str),
-                None => account_name.clone(),
+            let account = Self::inject_variables(account_name, &variables);
+            let amount = match self.amount_strs.get(index) {
+                Some(amount_str) => Some(Self::inject_variables(amount_str, &variables)),
+                None => self.debit_credit_amount(index, &variables)?,
             };
 
-            let injected = Self::inject_variables(&raw_value, &variables);
+            let injected = match amount {
+                Some(a) => format!("{} {}", account, a),
+                None => account,
+            };
 
             match Posting::parse(injected.as_str(), self.decimal_symbol, account_set) {
                 Ok(p) => postings.push(p),
@@ -462,15 +821,10 @@ impl Rules {
         match postings.len() {
             1 => {
                 let single_posting_amount = postings[0].get_amount();
-                if let Some(amount) = single_posting_amount {
-                    if amount.mag < 0.0 {
-                        postings.push(Posting::from(ClassicPosting::new("expenses:unknown", None, None, None)))
-                    } else if amount.mag > 0.0 {
-                        postings.push(Posting::from(ClassicPosting::new("income:unknown", None, None, None)))
-                    } else {
-                        // don't freak out about amounts with zero amounts
-                        postings.push(Posting::from(ClassicPosting::new("unknown", None, None, None)))
-                    }
+                if self.strict && single_posting_amount.is_some() {
+                    Err(SilverfoxError::from(ValidationError::default().set_context(&record.iter().collect::<Vec<&str>>().join(",")).set_message(&format!("this record only has a posting to `{}`, and the `strict` rule keeps silverfox from balancing it with an unknown account. add a subrule that categorizes it", postings[0].get_account()))))
+                } else if let Some(amount) = single_posting_amount {
+                    postings.push(balancing_posting(amount));
 
                     Ok(Entry::new(date, status, description, payee, postings, comment))
                 } else {
@@ -486,30 +840,170 @@ impl Rules {
         }
     }
 
+    /// Returns the credit minus the debit for the posting at `index`, or None if there are no
+    /// `debit` or `credit` rules for it. Debits are money out and credits are money in, no matter
+    /// how they're signed, and empty cells are zero.
+    fn debit_credit_amount(
+        &self,
+        index: &str,
+        variables: &HashMap<String, String>,
+    ) -> Result<Option<String>, SilverfoxError> {
+        let debit = self.debit_strs.get(index);
+        let credit = self.credit_strs.get(index);
+        if debit.is_none() && credit.is_none() {
+            return Ok(None);
+        }
+
+        let parse = |rule: Option<&String>| -> Result<Option<Amount>, SilverfoxError> {
+            match rule.map(|r| Self::inject_variables(r, variables)) {
+                Some(v) if !v.trim().is_empty() => {
+                    Ok(Some(Amount::parse(&v, self.decimal_symbol)?))
+                }
+                _ => Ok(None),
+            }
+        };
+
+        let amount = match (parse(debit)?, parse(credit)?) {
+            (None, None) => Amount::zero(),
+            (Some(d), None) => Amount {
+                mag: -d.mag.abs(),
+                symbol: d.symbol,
+            },
+            (None, Some(c)) => Amount {
+                mag: c.mag.abs(),
+                symbol: c.symbol,
+            },
+            (Some(d), Some(c)) if d.symbol == c.symbol => Amount {
+                mag: c.mag.abs() - d.mag.abs(),
+                symbol: c.symbol,
+            },
+            (Some(d), Some(c)) => {
+                return Err(SilverfoxError::from(ParseError {
+                    message: Some(String::from(
+                        "this record's debit and credit are in different currencies, so silverfox can't combine them",
+                    )),
+                    context: Some(format!("debit {}, credit {}", d, c)),
+                }))
+            }
+        };
+
+        let mag = amount
+            .rounded_mag()
+            .to_string()
+            .replace('.', &self.decimal_symbol.to_string());
+        Ok(Some(match amount.symbol {
+            Some(s) => format!("{} {}", mag, s),
+            None => mag,
+        }))
+    }
+
+    /// Replaces each `%name%` in `s` with the value of the variable `name`, and each `%%` with a
+    /// literal `%`. This is done in one pass from left to right, so values that have `%` in them
+    /// (or text that looks like a variable) are never replaced again. Anything that isn't a known
+    /// variable is left alone.
     fn inject_variables(s: &str, variables: &HashMap<String, String>) -> String {
-        let mut result = String::from(s);
+        let mut result = String::with_capacity(s.len());
+        let mut rest = s;
+
+        while let Some(start) = rest.find('%') {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+
+            let end = match after.find('%') {
+                Some(e) => e,
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            };
 
-        for (v_name, v_value) in variables.iter() {
-            result = result.replace(format!("%{}%", v_name).as_str(), v_value);
+            let name = &after[..end];
+            if name.is_empty() {
+                // literal %
+                result.push('%');
+                rest = &after[1..];
+            } else if let Some(value) = variables.get(name) {
+                result.push_str(value);
+                rest = &after[end + 1..];
+            } else {
+                // the closing % might start a variable of its own
+                result.push('%');
+                rest = after;
+            }
         }
 
-        result = result.replace("%%", "%"); // literal %
-
+        result.push_str(rest);
         result
     }
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
 struct Subrules {
-    patterns: Vec<String>,
+    patterns: Vec<SubrulePattern>,
     rules: Rules,
 }
 
 impl Subrules {
     fn applies_to(&self, record: &csv::StringRecord) -> bool {
-        let s = record.as_slice().to_lowercase();
+        self.matching_pattern(record).is_some()
+    }
+
+    /// Returns the first pattern that matches the record, if there is one
+    fn matching_pattern(&self, record: &csv::StringRecord) -> Option<&SubrulePattern> {
+        let lowercase = record.as_slice().to_lowercase();
+        let joined = record.iter().collect::<Vec<&str>>().join(",");
 
-        self.patterns.iter().any(|p| s.contains(&p.to_lowercase()))
+        self.patterns.iter().find(|p| match p {
+            SubrulePattern::Substring(s) => lowercase.contains(&s.to_lowercase()),
+            SubrulePattern::Regex(r) => r.is_match(&joined),
+        })
+    }
+}
+
+/// A pattern that decides whether subrules apply to a csv record
+#[derive(Clone, Debug)]
+enum SubrulePattern {
+    /// Matches records that contain the string anywhere, ignoring case
+    Substring(String),
+
+    /// Matches records that match the regular expression, with their fields joined by commas.
+    /// These are written with a `re:` prefix, like `re:^AMZN MKTP`.
+    Regex(Regex),
+}
+
+impl SubrulePattern {
+    fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s.strip_prefix("re:") {
+            Some(pattern) => Regex::new(pattern).map(Self::Regex).map_err(|e| {
+                SilverfoxError::from(ParseError {
+                    message: Some(format!(
+                        "silverfox couldn't understand `{}` as a regular expression: {}",
+                        pattern, e
+                    )),
+                    context: Some(s.to_string()),
+                })
+            }),
+            None => Ok(Self::Substring(s.to_string())),
+        }
+    }
+}
+
+impl PartialEq for SubrulePattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Substring(a), Self::Substring(b)) => a == b,
+            (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SubrulePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Substring(s) => write!(f, "{}", s),
+            Self::Regex(r) => write!(f, "re:{}", r.as_str()),
+        }
     }
 }
 
@@ -522,6 +1016,17 @@ impl From<&Rules> for Subrules {
     }
 }
 
+/// Removes comments from a line of rules (see `utils::remove_comments`). Semicolons are common
+/// csv delimiters, so a semicolon right after `delimiter` isn't a comment.
+fn remove_rule_comments(line: &str) -> &str {
+    let clean = utils::remove_comments(line);
+    if clean.trim() == "delimiter" && line[clean.len()..].starts_with(';') {
+        &line[..clean.len() + 1]
+    } else {
+        clean
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -586,7 +1091,7 @@ test5
         let mut ledger_account_set = HashSet::<String>::new();
         ledger_account_set.insert(String::from("assets:test"));
 
-        let importer = match CsvImporter::from_strs(CSV_STR, RULES_STR, ledger_account_set) {
+        let importer = match CsvImporter::from_strs(CSV_STR, RULES_STR, ledger_account_set, None) {
             Ok(i) => i,
             Err(e) => panic!("{}", e),
         };
@@ -605,6 +1110,35 @@ test5
         )
     }
 
+    #[test]
+    fn from_str_with_rules_test() {
+        let rules_path = std::env::temp_dir().join("silverfox_from_str_with_rules_test.rules");
+        fs::write(&rules_path, RULES_STR).unwrap();
+
+        let mut ledger_account_set = HashSet::<String>::new();
+        ledger_account_set.insert(String::from("assets:test"));
+
+        let importer = match CsvImporter::from_str_with_rules(
+            CSV_STR,
+            &rules_path,
+            ledger_account_set,
+            None,
+        ) {
+            Ok(i) => i,
+            Err(e) => panic!("{}", e),
+        };
+        let entries = importer.collect::<Result<Vec<Entry>, SilverfoxError>>();
+        fs::remove_file(&rules_path).unwrap();
+
+        match entries {
+            Ok(e) => assert_eq!(
+                format!("{:?}", e),
+                format!("{:?}", parse_csv_test_entries())
+            ),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
     fn parse_csv_test_entries() -> Vec<Entry> {
         let mut entries = Vec::new();
 
@@ -781,6 +1315,180 @@ test5
         entries
     }
 
+    #[test]
+    fn strict_test() {
+        let mut ledger_account_set = HashSet::<String>::new();
+        ledger_account_set.insert(String::from("assets:checking"));
+        ledger_account_set.insert(String::from("expenses:food"));
+
+        let csv_str = "date,description,amount
+2020/01/02,Grocery Store,-50
+2020/01/03,Mystery Charge,-20";
+        let rules_str = "fields date, description, amount
+account assets:checking
+strict true
+
+if Grocery
+    account2 expenses:food
+    amount2 50";
+
+        let results = CsvImporter::from_strs(csv_str, rules_str, ledger_account_set.clone(), None)
+            .unwrap()
+            .collect::<Vec<Result<Entry, SilverfoxError>>>();
+
+        // the categorized record is fine, but the uncategorized one is an error
+        assert!(results[0].is_ok());
+        match &results[1] {
+            Err(e) => assert!(e.to_string().contains("Mystery Charge")),
+            Ok(_) => panic!("a single-posting record was imported in strict mode"),
+        }
+
+        // by default, the unknown account balances the record
+        let rules_str = rules_str.replace("strict true", "");
+        let results = CsvImporter::from_strs(csv_str, &rules_str, ledger_account_set, None)
+            .unwrap()
+            .collect::<Vec<Result<Entry, SilverfoxError>>>();
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn explain_record_test() {
+        let rules = Rules::from_str(RULES_STR).unwrap();
+        let records = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(CSV_STR.as_bytes())
+            .records()
+            .collect::<Result<Vec<csv::StringRecord>, csv::Error>>()
+            .unwrap();
+
+        // the record matches the second pattern of the second subrule
+        let explanation = rules.explain_record(&records[3]);
+        let lines = explanation.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines,
+            vec![
+                "record `2020.01.02,Test CSV Entry Four,-7.8,BTC,8000,test2`",
+                "    subrule 1 tested `test0`: no match",
+                "    subrule 2 tested `test1`, `test2`, `test3`: matched `test2`",
+            ]
+        );
+
+        let explanation = rules.explain_record(&records[0]);
+        assert!(explanation.contains("subrule 3 tested `bad decimal`, `test4`, `test5`: no match"));
+        assert!(explanation.ends_with("no subrules matched, so the main rules were used"));
+    }
+
+    #[test]
+    fn regex_subrules_test() {
+        let mut ledger_account_set = HashSet::<String>::new();
+        ledger_account_set.insert(String::from("assets:checking"));
+        ledger_account_set.insert(String::from("expenses:shopping"));
+        ledger_account_set.insert(String::from("expenses:subscriptions"));
+
+        let csv_str = "date,description,amount
+2020/01/02,AMZN MKTP US,-30
+2020/01/03,AMZN PRIME,-15
+2020/01/04,amzn mktp refund,10";
+        let rules_str = "fields date, description, amount
+account assets:checking
+skip 1
+
+if re:,AMZN MKTP
+    account2 expenses:shopping
+
+if amzn prime
+    account2 expenses:subscriptions";
+
+        let entries = CsvImporter::from_strs(csv_str, rules_str, ledger_account_set, None)
+            .unwrap()
+            .collect::<Result<Vec<Entry>, SilverfoxError>>()
+            .unwrap();
+        let has_account =
+            |e: &Entry, account: &str| e.get_postings().iter().any(|p| p.get_account() == account);
+
+        assert!(has_account(&entries[0], "expenses:shopping"));
+        assert!(has_account(&entries[1], "expenses:subscriptions"));
+
+        // regexes are case-sensitive, unlike substrings
+        assert!(has_account(&entries[2], "income:unknown"));
+
+        // invalid regexes are errors when the rules are read
+        let rules_str = rules_str.replace("re:,AMZN MKTP", "re:AMZN (MKTP");
+        match Rules::from_str(&rules_str) {
+            Err(SilverfoxError::Parse(_)) => (),
+            _ => panic!("an invalid regex didn't cause a parse error"),
+        }
+    }
+
+    #[test]
+    fn debit_credit_test() {
+        let mut ledger_account_set = HashSet::<String>::new();
+        ledger_account_set.insert(String::from("assets:checking"));
+
+        let csv_str = "date,description,debit,credit
+2020/01/02,Grocery Store,50.25,
+2020/01/03,Paycheck,,1000
+2020/01/04,Refund and fee,-2,10
+2020/01/05,Nothing,,";
+        let rules_str = "fields date, description, debit, credit
+account assets:checking
+debit %debit%
+credit %credit%";
+
+        let entries = CsvImporter::from_strs(csv_str, rules_str, ledger_account_set, None)
+            .unwrap()
+            .collect::<Result<Vec<Entry>, SilverfoxError>>()
+            .unwrap();
+        let amounts = entries
+            .iter()
+            .map(|e| e.get_postings()[0].get_amount().unwrap().mag)
+            .collect::<Vec<f64>>();
+
+        // debits are money out, whatever their sign, and empty cells are zero
+        assert_eq!(amounts, [-50.25, 1000.0, 8.0, 0.0]);
+    }
+
+    #[test]
+    fn delimiter_test() {
+        let mut ledger_account_set = HashSet::<String>::new();
+        ledger_account_set.insert(String::from("assets:checking"));
+        ledger_account_set.insert(String::from("expenses:food"));
+
+        let csv_str = "date;description;amount
+02.01.2020;Bäckerei;-4,50
+03.01.2020;Supermarkt;-20,10";
+        let rules_str = "fields date, description, amount
+delimiter ;  // the bank uses semicolons
+decimal_symbol ,
+date_format %d.%m.%Y
+account assets:checking
+account2 expenses:food";
+
+        let entries = CsvImporter::from_strs(csv_str, rules_str, ledger_account_set.clone(), None)
+            .unwrap()
+            .collect::<Result<Vec<Entry>, SilverfoxError>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_description(), "Bäckerei");
+        let amount = entries[1]
+            .get_postings()
+            .iter()
+            .find_map(|p| p.get_amount())
+            .unwrap();
+        assert_eq!(amount.mag, -20.1);
+
+        // the delimiter given on the command line wins over the rules
+        let rules_str = rules_str.replace("delimiter ;", "delimiter tab");
+        let entries = CsvImporter::from_strs(csv_str, &rules_str, ledger_account_set, Some(b';'))
+            .unwrap()
+            .collect::<Result<Vec<Entry>, SilverfoxError>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert!(CsvImporter::parse_delimiter("ab").is_err());
+        assert_eq!(CsvImporter::parse_delimiter("tab").unwrap(), b'\t');
+    }
+
     fn parse_rules_test_struct() -> Rules {
         let mut rules: Rules = Default::default();
 
@@ -803,14 +1511,22 @@ test5
         rules.skip = 1;
 
         let mut subrules_0 = Subrules::from(&rules);
-        subrules_0.patterns.push(String::from("test0"));
+        subrules_0
+            .patterns
+            .push(SubrulePattern::Substring(String::from("test0")));
         subrules_0.rules.comment = String::from("single condition test");
         rules.subrules.push(subrules_0);
 
         let mut subrules_1: Subrules = Subrules::from(&rules);
-        subrules_1.patterns.push(String::from("test1"));
-        subrules_1.patterns.push(String::from("test2"));
-        subrules_1.patterns.push(String::from("test3"));
+        subrules_1
+            .patterns
+            .push(SubrulePattern::Substring(String::from("test1")));
+        subrules_1
+            .patterns
+            .push(SubrulePattern::Substring(String::from("test2")));
+        subrules_1
+            .patterns
+            .push(SubrulePattern::Substring(String::from("test3")));
         subrules_1.rules.comment = String::from("multiple condition test");
         subrules_1.rules.payee = String::from("Ferris the Crab");
         rules.subrules.push(subrules_1);
@@ -819,12 +1535,89 @@ test5
         bad_decimal_subrules.rules.decimal_symbol = ',';
         bad_decimal_subrules
             .patterns
-            .push(String::from("bad decimal"));
-        bad_decimal_subrules.patterns.push(String::from("test4"));
-        bad_decimal_subrules.patterns.push(String::from("test5"));
+            .push(SubrulePattern::Substring(String::from("bad decimal")));
+        bad_decimal_subrules
+            .patterns
+            .push(SubrulePattern::Substring(String::from("test4")));
+        bad_decimal_subrules
+            .patterns
+            .push(SubrulePattern::Substring(String::from("test5")));
         bad_decimal_subrules.rules.comment = String::from("comma decimal_symbol test");
         rules.subrules.push(bad_decimal_subrules);
 
         rules
     }
+
+    #[test]
+    fn inject_variables_test() {
+        let mut variables = HashMap::new();
+        variables.insert(
+            String::from("description"),
+            String::from("50% off %amount%"),
+        );
+        variables.insert(String::from("amount"), String::from("12.50"));
+        variables.insert(String::from("rate"), String::from("%%"));
+
+        // substituted values aren't substituted again
+        assert_eq!(
+            Rules::inject_variables("%description% for %amount%", &variables),
+            "50% off %amount% for 12.50"
+        );
+        assert_eq!(Rules::inject_variables("%rate%", &variables), "%%");
+
+        // `%%` is a literal %, and unknown variables are left alone
+        assert_eq!(
+            Rules::inject_variables("%amount%%% of %total% %amount%", &variables),
+            "12.50% of %total% 12.50"
+        );
+        assert_eq!(Rules::inject_variables("100%", &variables), "100%");
+    }
+
+    #[test]
+    fn qif_test() {
+        let qif_str = "!Type:Bank
+D01/02/2020
+T-1,234.56
+PGrocery Store
+Mweekly shopping
+^
+D1/15'21
+T2000.00
+CX
+Mpaycheck
+^
+";
+        let entries = QifImporter::from_str(qif_str, "assets:checking", '.')
+            .unwrap()
+            .collect::<Result<Vec<Entry>, SilverfoxError>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let groceries = &entries[0];
+        assert_eq!(
+            *groceries.get_date(),
+            chrono::NaiveDate::from_ymd(2020, 1, 2)
+        );
+        assert_eq!(*groceries.get_status(), EntryStatus::Cleared);
+        assert_eq!(groceries.get_description(), "Grocery Store");
+        let postings = groceries.get_postings();
+        assert_eq!(postings[0].get_account(), "assets:checking");
+        assert_eq!(postings[0].get_amount().unwrap().mag, -1234.56);
+        assert_eq!(postings[1].get_account(), "expenses:unknown");
+
+        // without a payee, the memo is the description
+        let paycheck = &entries[1];
+        assert_eq!(
+            *paycheck.get_date(),
+            chrono::NaiveDate::from_ymd(2021, 1, 15)
+        );
+        assert_eq!(*paycheck.get_status(), EntryStatus::Reconciled);
+        assert_eq!(paycheck.get_description(), "paycheck");
+        assert_eq!(paycheck.get_postings()[1].get_account(), "income:unknown");
+
+        // investment transactions can't be imported
+        assert!(
+            QifImporter::from_str("!Type:Invst\nD01/02/2020\n^", "assets:checking", '.').is_err()
+        );
+    }
 }
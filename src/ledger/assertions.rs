@@ -0,0 +1,90 @@
+//! Auditing balance assertions. Rather than stopping at the first assertion that fails, every
+//! assertion in the journal is checked against the balance of its account at that point, so the
+//! journal's checkpoints can be reviewed all at once.
+
+use crate::amount::Amount;
+use crate::color::ColorChoice;
+use chrono::NaiveDate;
+use std::io::Write;
+
+/// A balance assertion from the journal and whether it holds
+#[derive(Clone, Debug)]
+pub struct AssertionResult {
+    pub date: NaiveDate,
+    pub description: String,
+    pub account: String,
+
+    /// The balance the entry asserts the account has
+    pub expected: Amount,
+
+    /// The balance the account actually has, in the currency of `expected`
+    pub actual: Amount,
+}
+
+impl AssertionResult {
+    /// Returns true if the account has the balance that was asserted
+    pub fn holds(&self) -> bool {
+        (self.actual.clone() - &self.expected).rounds_to_zero()
+    }
+}
+
+/// Writes one line for each of the `results`, followed by how many of them failed
+pub fn write<W: Write>(
+    mut writer: W,
+    results: &[AssertionResult],
+    date_format: &str,
+    color: ColorChoice,
+) -> std::io::Result<()> {
+    if results.is_empty() {
+        return writeln!(writer, "your journal doesn't have any balance assertions");
+    }
+
+    let dates = results
+        .iter()
+        .map(|r| r.date.format(date_format).to_string())
+        .collect::<Vec<String>>();
+    let expected = results
+        .iter()
+        .map(|r| r.expected.to_string().trim().to_string())
+        .collect::<Vec<String>>();
+    let actual = results
+        .iter()
+        .map(|r| r.actual.to_string().trim().to_string())
+        .collect::<Vec<String>>();
+
+    let date_width = dates.iter().map(|d| d.len()).max().unwrap_or(0);
+    let account_width = results.iter().map(|r| r.account.len()).max().unwrap_or(0);
+    let expected_width = expected.iter().map(|e| e.len()).max().unwrap_or(0);
+    let actual_width = actual.iter().map(|a| a.len()).max().unwrap_or(0);
+
+    for (i, result) in results.iter().enumerate() {
+        let status = if result.holds() {
+            color.green("ok")
+        } else {
+            color.red("failed")
+        };
+
+        writeln!(
+            writer,
+            "{:dw$}  {:aw$}  expected {:>ew$}  has {:>hw$}  {}  ({})",
+            dates[i],
+            result.account,
+            expected[i],
+            actual[i],
+            status,
+            result.description,
+            dw = date_width,
+            aw = account_width,
+            ew = expected_width,
+            hw = actual_width
+        )?;
+    }
+
+    let failed = results.iter().filter(|r| !r.holds()).count();
+    writeln!(
+        writer,
+        "\n{} of {} balance assertions failed",
+        failed,
+        results.len()
+    )
+}
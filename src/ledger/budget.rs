@@ -0,0 +1,191 @@
+//! Exporting envelopes as a budget for other plain-text accounting programs: beancount
+//! `custom "budget"` directives (the kind Fava reads) or ledger `~` periodic transactions.
+//!
+//! Each repeating envelope becomes one budget for its `budget_account` over its
+//! `budget_period`, with how much is saved in it so far. Budgets only have a period and an
+//! amount, so due dates, funding methods, and buffers don't carry over, and envelopes that don't
+//! repeat can't be exported at all (they're written as comments instead).
+
+use crate::amount::{Amount, AmountFormat};
+use crate::envelope::{Envelope, POOLED_ACCOUNT};
+use crate::errors::SilverfoxError;
+use chrono::NaiveDate;
+use std::io::Write;
+
+/// The program an exported budget is written for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BudgetFormat {
+    /// `custom "budget"` directives
+    Beancount,
+
+    /// `~` periodic transactions
+    Ledger,
+}
+
+/// Writes `envelopes` as a budget in `format`. `date` is when budgets start if their envelopes
+/// don't have a starting date, and amounts without a symbol are in `default_currency`.
+pub fn write<W: Write>(
+    mut writer: W,
+    envelopes: &[&Envelope],
+    format: BudgetFormat,
+    date: NaiveDate,
+    default_currency: &str,
+) -> Result<(), SilverfoxError> {
+    let _plain = AmountFormat::plain_scope();
+
+    let mut s = String::new();
+    for envelope in envelopes {
+        let budget = match envelope.budget_period() {
+            Some(b) => b,
+            None => {
+                s.push_str(&format!(
+                    "; `{}` isn't exported because it doesn't repeat\n",
+                    envelope.get_name()
+                ));
+                continue;
+            }
+        };
+
+        let entry = match format {
+            BudgetFormat::Beancount => beancount_budget(envelope, budget, date, default_currency)?,
+            BudgetFormat::Ledger => ledger_budget(envelope, budget, default_currency),
+        };
+        s.push_str(&entry);
+        s.push('\n');
+    }
+
+    write!(writer, "{}", s)
+        .map_err(|e| SilverfoxError::Basic(format!("couldn't export the budget: {}", e)))
+}
+
+fn beancount_budget(
+    envelope: &Envelope,
+    (period, amount): (&str, Amount),
+    date: NaiveDate,
+    default_currency: &str,
+) -> Result<String, SilverfoxError> {
+    let date = envelope.get_starting_date().unwrap_or(date);
+    let currency = beancount_currency(&amount, default_currency)?;
+
+    Ok(format!(
+        "{} custom \"budget\" {} \"{}\" {} {}\n  envelope: \"{}\"\n  saved: {} {}\n",
+        date.format("%Y-%m-%d"),
+        beancount_account(&envelope.budget_account())?,
+        period,
+        amount.rounded_mag(),
+        currency,
+        envelope.get_name().replace('"', "'"),
+        saved(envelope).rounded_mag(),
+        currency
+    ))
+}
+
+fn ledger_budget(
+    envelope: &Envelope,
+    (period, amount): (&str, Amount),
+    default_currency: &str,
+) -> String {
+    let mut header = format!("~ {}{}", &period[..1].to_uppercase(), &period[1..]);
+    if let Some(d) = envelope.get_starting_date() {
+        header.push_str(&format!(" from {}", d.format("%Y/%m/%d")));
+    }
+
+    let funding_account = match envelope.get_account() {
+        POOLED_ACCOUNT => "assets",
+        a => a,
+    };
+
+    format!(
+        "; {}: {} saved\n{}\n    {:35}    {}\n    {}\n",
+        envelope.get_name(),
+        ledger_amount(&saved(envelope), default_currency),
+        header,
+        envelope.budget_account(),
+        ledger_amount(&amount, default_currency),
+        funding_account
+    )
+}
+
+/// Returns how much is saved in `envelope`, for both its `now` and `next` amounts
+fn saved(envelope: &Envelope) -> Amount {
+    Amount {
+        mag: envelope.get_now_amount().mag + envelope.get_next_amount().mag,
+        symbol: envelope.get_amount().symbol.clone(),
+    }
+}
+
+/// Returns `amount` as ledger would write it, in `default_currency` if it doesn't have a symbol
+fn ledger_amount(amount: &Amount, default_currency: &str) -> String {
+    match &amount.symbol {
+        None if !default_currency.is_empty() => Amount {
+            mag: amount.mag,
+            symbol: Some(default_currency.to_string()),
+        }
+        .to_string()
+        .trim()
+        .to_string(),
+        _ => amount.to_string().trim().to_string(),
+    }
+}
+
+/// Returns the beancount commodity `amount` is in. Beancount commodities are names like `USD`, so
+/// symbols like `$` can't be exported.
+fn beancount_currency(amount: &Amount, default_currency: &str) -> Result<String, SilverfoxError> {
+    let currency = amount.symbol.as_deref().unwrap_or(default_currency);
+    if currency.is_empty() {
+        return Err(SilverfoxError::Basic(String::from(
+            "beancount budgets need a currency. add a `currency` directive (like `currency USD`) to your journal",
+        )));
+    }
+
+    let first_ok = currency.starts_with(|c: char| c.is_ascii_uppercase());
+    let last_ok = currency.ends_with(|c: char| c.is_ascii_uppercase() || c.is_ascii_digit());
+    let rest_ok = currency
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "'._-".contains(c));
+    if !first_ok || !last_ok || !rest_ok || currency.len() > 24 {
+        return Err(SilverfoxError::Basic(format!(
+            "`{}` can't be a beancount currency. beancount currencies are names in capital letters, like USD",
+            currency
+        )));
+    }
+
+    Ok(currency.to_string())
+}
+
+/// Returns `account` as a beancount account, like `Expenses:Rent` for `expenses:rent`. Beancount
+/// accounts have to be under one of its five root accounts.
+fn beancount_account(account: &str) -> Result<String, SilverfoxError> {
+    let mut components = account.split(':');
+    let root = match components.next() {
+        Some("asset") | Some("assets") => "Assets",
+        Some("liability") | Some("liabilities") => "Liabilities",
+        Some("equity") => "Equity",
+        Some("income") | Some("revenue") => "Income",
+        Some("expense") | Some("expenses") => "Expenses",
+        _ => {
+            return Err(SilverfoxError::Basic(format!(
+                "`{}` can't be a beancount account, because beancount accounts have to be under assets, liabilities, equity, income, or expenses",
+                account
+            )))
+        }
+    };
+
+    let mut s = String::from(root);
+    for component in components {
+        s.push(':');
+        let mut chars = component.chars();
+        if let Some(c) = chars.next() {
+            s.extend(c.to_uppercase());
+        }
+        s.extend(chars.map(|c| {
+            if c.is_whitespace() || c == '_' {
+                '-'
+            } else {
+                c
+            }
+        }));
+    }
+
+    Ok(s)
+}
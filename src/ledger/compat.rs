@@ -0,0 +1,302 @@
+//! Reading journals written for ledger-cli. Rather than teaching every parser two syntaxes, a
+//! ledger journal is translated into the nearest silverfox journal before it's parsed.
+//!
+//! Constructs that silverfox has no equivalent for are skipped: commodity/payee/tag/alias/apply/
+//! year directives, automated (`=`) and periodic (`~`) transactions. Price records (`P`) are kept,
+//! without their times. Virtual postings are treated as real postings, and spaces in account names are
+//! replaced with underscores, since silverfox account names can't contain whitespace. Comments in
+//! entries are kept, so their metadata and tags are read like silverfox's own.
+
+use crate::errors::SilverfoxError;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The syntax a journal file is written in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JournalFormat {
+    Silverfox,
+    Ledger,
+}
+
+impl JournalFormat {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "silverfox" | "sfox" => Ok(Self::Silverfox),
+            "ledger" => Ok(Self::Ledger),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't a journal format silverfox knows. try `silverfox` or `ledger`",
+                s
+            ))),
+        }
+    }
+
+    /// Returns the format implied by the extension of the file at `path`, if there is one
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("sfox") => Some(Self::Silverfox),
+            Some("ledger") => Some(Self::Ledger),
+            _ => None,
+        }
+    }
+}
+
+/// Directives that ledger understands but silverfox doesn't. These are skipped, along with any
+/// lines indented under them.
+const SKIPPED_DIRECTIVES: &[&str] = &[
+    "D",
+    "N",
+    "Y",
+    "year",
+    "apply",
+    "end",
+    "alias",
+    "bucket",
+    "capture",
+    "check",
+    "commodity",
+    "define",
+    "payee",
+    "tag",
+    "assert",
+    "=",
+    "~",
+];
+
+/// Translates a ledger-cli journal into a silverfox journal. Accounts that are used but not
+/// declared (ledger doesn't require declarations) are declared at the top of the result, unless
+/// they're in `known_accounts`.
+pub fn translate_ledger(s: &str, known_accounts: &HashSet<&String>) -> String {
+    let mut declared: HashSet<String> = known_accounts.iter().map(|a| a.to_string()).collect();
+    let mut used: Vec<String> = Vec::new();
+    let mut body = String::new();
+
+    let mut skipping_block = false;
+    let mut in_comment_block = false;
+    let mut in_entry = false;
+
+    for line in s.lines() {
+        let trimmed = line.trim();
+
+        if in_comment_block {
+            if trimmed.starts_with("end comment") || trimmed.starts_with("end test") {
+                in_comment_block = false;
+            }
+            continue;
+        }
+
+        // whole-line comments
+        if trimmed.is_empty() || line.starts_with(|c| ";#%|*".contains(c)) {
+            continue;
+        }
+
+        let indented = line.starts_with(char::is_whitespace);
+        if indented {
+            if skipping_block || !in_entry {
+                continue;
+            }
+
+            // comments on their own lines can hold metadata and tags
+            if trimmed.starts_with(';') {
+                body.push_str("    ");
+                body.push_str(trimmed);
+                body.push('\n');
+                continue;
+            }
+
+            let posting = translate_posting(trimmed);
+            if let Some(account) = posting.split_whitespace().next() {
+                if !used.iter().any(|a| a == account) {
+                    used.push(account.to_string());
+                }
+            }
+            body.push_str("    ");
+            body.push_str(&posting);
+            body.push('\n');
+            continue;
+        }
+
+        // a new top-level chunk
+        skipping_block = false;
+        in_entry = false;
+        let keyword = trimmed.split_whitespace().next().unwrap_or("");
+
+        if trimmed == "comment" || trimmed == "test" {
+            in_comment_block = true;
+        } else if keyword == "account" {
+            let name = translate_account(trimmed["account".len()..].trim());
+            declared.insert(name.clone());
+            body.push_str(&format!("\naccount {}\n", name));
+
+            // account sub-directives (like `note` or `alias`) aren't supported
+            skipping_block = true;
+        } else if keyword == "include" {
+            body.push_str(&format!("\n{}\n", trimmed));
+        } else if keyword == "P" {
+            body.push_str(&format!("\n{}\n", translate_price(trimmed)));
+        } else if SKIPPED_DIRECTIVES.contains(&keyword) {
+            skipping_block = true;
+        } else if trimmed.starts_with(|c: char| c.is_ascii_digit()) {
+            body.push_str(&format!("\n{}\n", translate_header(trimmed)));
+            in_entry = true;
+        } else {
+            skipping_block = true;
+        }
+    }
+
+    let mut s = String::new();
+    for account in used.iter().filter(|a| !declared.contains(*a)) {
+        s.push_str(&format!("account {}\n", account));
+    }
+    s.push_str(&body);
+
+    s
+}
+
+/// Translates a ledger transaction header, `DATE[=AUX_DATE] [*|!] [(CODE)] PAYEE [; NOTE]`, into
+/// a silverfox entry header. Ledger's cleared transactions (`*`) are cleared in silverfox, too,
+/// and anything else is pending.
+fn translate_header(header: &str) -> String {
+    let header = header.split(';').next().unwrap_or("").trim();
+    let (date, mut rest) = match header.find(char::is_whitespace) {
+        Some(i) => (&header[..i], header[i..].trim_start()),
+        None => (header, ""),
+    };
+
+    // auxiliary dates are dropped, and dates are written the way silverfox expects by default
+    let date = date
+        .split('=')
+        .next()
+        .unwrap_or("")
+        .replace(['-', '.'], "/");
+
+    let status = if let Some(r) = rest.strip_prefix('*') {
+        rest = r.trim_start();
+        '~'
+    } else if let Some(r) = rest.strip_prefix('!') {
+        rest = r.trim_start();
+        '?'
+    } else {
+        '?'
+    };
+
+    if rest.starts_with('(') {
+        if let Some(i) = rest.find(')') {
+            rest = rest[i + 1..].trim_start();
+        }
+    }
+
+    format!("{} {} {}", date, status, rest)
+}
+
+/// Translates a ledger price record, `P DATE [TIME] SYMBOL PRICE [; NOTE]`, into a silverfox
+/// price line. Times are dropped, since silverfox keeps prices by the day.
+fn translate_price(record: &str) -> String {
+    let record = record.split(';').next().unwrap_or("").trim();
+    let mut tokens = record.split_whitespace().skip(1).peekable();
+
+    let date = tokens.next().unwrap_or("").replace(['-', '.'], "/");
+    tokens.next_if(|t| t.contains(':'));
+
+    format!("P {} {}", date, tokens.collect::<Vec<&str>>().join(" "))
+}
+
+/// Translates a ledger posting. Ledger separates accounts from amounts with at least two spaces
+/// (or a tab), because account names can have spaces in them.
+fn translate_posting(posting: &str) -> String {
+    let (posting, comment) = match posting.split_once(';') {
+        Some((p, c)) => (p.trim(), Some(c.trim())),
+        None => (posting.trim(), None),
+    };
+    let comment = match comment {
+        Some(c) if !c.is_empty() => format!("    ; {}", c),
+        _ => String::new(),
+    };
+
+    let split_index = match (posting.find("  "), posting.find('\t')) {
+        (Some(i), Some(j)) => Some(i.min(j)),
+        (i, j) => i.or(j),
+    };
+
+    let (account, amount) = match split_index {
+        Some(i) => (&posting[..i], posting[i..].trim()),
+        None => (posting, ""),
+    };
+
+    // virtual postings are treated like real ones
+    let account = translate_account(account.trim_matches(|c| "()[]".contains(c)));
+
+    if amount.is_empty() {
+        return format!("{}{}", account, comment);
+    }
+
+    // in ledger, `@@` is a total cost and `=` is a balance assertion
+    let amount = amount
+        .split_whitespace()
+        .map(|t| match t {
+            "@@" => "=",
+            "=" => "!",
+            t => t,
+        })
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    format!("{}    {}{}", account, amount, comment)
+}
+
+fn translate_account(account: &str) -> String {
+    account.split_whitespace().collect::<Vec<&str>>().join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEDGER_JOURNAL: &str = "; a journal for ledger-cli
+P 2020/01/01 BTC $7000
+P 2020-01-03 12:00:00 BTC $7100  ; after lunch
+
+account Assets:Checking
+    note the main account
+
+2020-01-02 * (1042) Grocery Store  ; weekly shopping
+    ; :weekly:
+    Expenses:Food    $50.00  ; category: groceries
+    Assets:Checking
+
+2020/01/05 ! Crypto exchange
+    Assets:Crypto Wallet    0.01 BTC @@ $70
+    Assets:Checking    $-70 = $-120
+
+= /Food/
+    (Budget:Food)    -1
+
+comment
+this isn't parsed at all
+end comment
+";
+
+    #[test]
+    fn translate_ledger_test() {
+        let translated = translate_ledger(LEDGER_JOURNAL, &HashSet::new());
+        let expected = "account Expenses:Food
+account Assets:Crypto_Wallet
+
+P 2020/01/01 BTC $7000
+
+P 2020/01/03 BTC $7100
+
+account Assets:Checking
+
+2020/01/02 ~ Grocery Store
+    ; :weekly:
+    Expenses:Food    $50.00    ; category: groceries
+    Assets:Checking
+
+2020/01/05 ? Crypto exchange
+    Assets:Crypto_Wallet    0.01 BTC = $70
+    Assets:Checking    $-70 ! $-120
+";
+
+        assert_eq!(translated, expected);
+    }
+}
@@ -0,0 +1,88 @@
+//! Including definitions from outside the journal's files: the output of a command
+//! (`include !command`) or the contents of a URL (`include https://...`). Both can run or fetch
+//! anything, so silverfox has to be built with the `exec-include` or `network-include` feature for
+//! them to work at all, and they still have to be allowed with `--allow-exec` or `--allow-network`.
+
+use crate::errors::SilverfoxError;
+
+/// Returns true if an `include` of `source` should be read with `read`, instead of as a file
+pub fn is_external(source: &str) -> bool {
+    source.starts_with('!') || source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Returns the output of a command (if `source` starts with `!`) or the contents of a URL
+pub fn read(source: &str, allow_exec: bool, allow_network: bool) -> Result<String, SilverfoxError> {
+    match source.strip_prefix('!') {
+        Some(command) if !allow_exec => Err(SilverfoxError::Basic(format!(
+            "silverfox won't run `{}` to include its output unless you pass --allow-exec",
+            command.trim()
+        ))),
+        Some(command) => command_output(command.trim()),
+        None if !allow_network => Err(SilverfoxError::Basic(format!(
+            "silverfox won't fetch `{}` to include it unless you pass --allow-network",
+            source
+        ))),
+        None => fetch(source),
+    }
+}
+
+#[cfg(feature = "exec-include")]
+fn command_output(command: &str) -> Result<String, SilverfoxError> {
+    run("sh", &["-c", command], command)
+}
+
+#[cfg(not(feature = "exec-include"))]
+fn command_output(command: &str) -> Result<String, SilverfoxError> {
+    Err(SilverfoxError::Basic(format!(
+        "silverfox can't run `{}` to include its output, because it was built without the `exec-include` feature",
+        command
+    )))
+}
+
+/// Fetches the URL with curl, which saves silverfox from needing an HTTP client of its own
+#[cfg(feature = "network-include")]
+fn fetch(url: &str) -> Result<String, SilverfoxError> {
+    run(
+        "curl",
+        &["--fail", "--silent", "--show-error", "--location", url],
+        url,
+    )
+}
+
+#[cfg(not(feature = "network-include"))]
+fn fetch(url: &str) -> Result<String, SilverfoxError> {
+    Err(SilverfoxError::Basic(format!(
+        "silverfox can't fetch `{}` to include it, because it was built without the `network-include` feature",
+        url
+    )))
+}
+
+/// Runs a program and returns what it wrote to stdout. `source` is what the journal included, for
+/// error messages.
+#[cfg(any(feature = "exec-include", feature = "network-include"))]
+fn run(program: &str, args: &[&str], source: &str) -> Result<String, SilverfoxError> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| {
+            SilverfoxError::Basic(format!(
+                "silverfox couldn't run `{}` to include `{}`: {}",
+                program, source, e
+            ))
+        })?;
+
+    if !output.status.success() {
+        return Err(SilverfoxError::Basic(format!(
+            "`{}` couldn't be included because it failed: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| {
+        SilverfoxError::Basic(format!(
+            "`{}` couldn't be included because it isn't valid UTF-8",
+            source
+        ))
+    })
+}
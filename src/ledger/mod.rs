@@ -1,28 +1,162 @@
-use crate::account::Account;
-use crate::amount::{Amount, AmountPool};
-use crate::entry::Entry;
+use crate::account::{Account, AccountMatcher};
+use crate::amount::{Amount, AmountFormat, AmountPool};
+use crate::color::ColorChoice;
+use crate::entry::{Entry, EntryStatus};
+use crate::envelope::{Envelope, POOLED_ACCOUNT};
 use crate::errors::*;
-use crate::importer::CsvImporter;
-use crate::posting::Posting;
+use crate::importer::{CsvImporter, QifImporter};
+use crate::posting::{EnvelopePosting, Posting, PostingFormat};
+use crate::prices::Prices;
 use crate::utils;
-use chrono::{Local, NaiveDate};
-use std::collections::HashMap;
+use chrono::{Datelike, Local, NaiveDate};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::fs;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
+mod assertions;
+mod budget;
+mod compat;
+mod external;
 mod register;
+pub use assertions::AssertionResult;
+pub use budget::BudgetFormat;
+pub use compat::JournalFormat;
+pub use register::TableStyle;
 use register::Register;
 
+/// By default, how many days an entry can be from every other entry (or in the future) before
+/// `validate_dates` warns about it
+pub const DEFAULT_MAX_DATE_GAP_DAYS: i64 = 365;
+
+/// How many of an account's latest entries are shown in `display_account_detail`
+const ACCOUNT_DETAIL_ENTRIES: usize = 10;
+
+/// The account prefixes that denote income, unless the journal has an `income_accounts` directive
+const DEFAULT_INCOME_PREFIXES: &[&str] = &["income"];
+
+/// The account prefixes that denote expenses, unless the journal has an `expense_accounts`
+/// directive
+const DEFAULT_EXPENSE_PREFIXES: &[&str] = &["expense"];
+
 pub struct Ledger {
     file_path: PathBuf,
     entries: Vec<Entry>,
+
+    /// The number given to the next entry that's added (see `Entry::set_seq`)
+    next_seq: usize,
+
+    /// The canonical paths of the files being read right now, from the main journal to the
+    /// innermost include. Included commands and URLs are kept here as they were written. A source
+    /// that's already here can't be included again, since it would be read in a loop forever.
+    include_stack: Vec<PathBuf>,
+
     date_format: String, // default = "%Y/%m/%d"
     accounts: HashMap<String, Account>,
     default_currency: String,
     decimal_symbol: char,
+
+    /// The format of the main journal file. silverfox can only write to silverfox journals.
+    format: JournalFormat,
+
+    /// If true, pending entries don't affect accounts' envelopes or available values
+    confirmed_only: bool,
+
+    /// The account that takes whatever's left over in entries that don't balance, set with the
+    /// `default_balance_account` directive
+    default_balance_account: Option<String>,
+
+    /// Envelope fills smaller than this are skipped, set with the `min_fill` directive. What
+    /// they would have moved is moved in a later fill instead.
+    min_fill: f64,
+
+    /// The locale preset amounts are displayed with, set with the `locale` directive (see
+    /// `AmountFormat::from_locale`)
+    locale: Option<String>,
+
+    /// How postings are laid out when entries are written to the journal
+    posting_format: PostingFormat,
+
+    /// If set, entries dated after this day don't affect totals or envelopes
+    as_of: Option<NaiveDate>,
+
+    /// Whether mistakes with an obvious fix are forgiven while parsing, with a warning instead of
+    /// an error (see `parse_lenient`)
+    forgiving: bool,
+
+    /// Warnings about mistakes that were forgiven while parsing
+    parse_warnings: Vec<String>,
+
+    /// Accounts that start with any of these are income accounts, set with the
+    /// `income_accounts` directive
+    income_prefixes: Vec<String>,
+
+    /// Accounts that start with any of these are expense accounts, set with the
+    /// `expense_accounts` directive
+    expense_prefixes: Vec<String>,
+
+    /// Envelopes defined outside of any account, which are funded by all asset accounts together.
+    /// These are only allowed with `--merge-envelopes-across-accounts`.
+    pooled_envelopes: Option<Account>,
+
+    /// The prices of commodities over time, from `P` lines
+    prices: Prices,
+
+    /// Whether `include !command` can run commands (see `external`)
+    allow_exec: bool,
+
+    /// If true, balance assertions aren't checked while entries are read (see
+    /// `assertions_report`)
+    skip_balance_assertions: bool,
+
+    /// Whether `include` can fetch URLs (see `external`)
+    allow_network: bool,
+}
+
+/// Options for reading a ledger from a file
+#[derive(Clone, Debug, Default)]
+pub struct LedgerOptions {
+    /// The format of the journal file. If None, it's guessed from the file's extension.
+    pub format: Option<JournalFormat>,
+
+    /// Keeps pending (`?`) entries from moving money in and out of envelopes, since they haven't
+    /// really happened yet
+    pub confirmed_only: bool,
+
+    /// How postings are laid out when entries are written back to the journal
+    pub posting_format: PostingFormat,
+
+    /// Leaves out entries dated after this day, like scheduled bills that haven't happened yet
+    pub as_of: Option<NaiveDate>,
+
+    /// Allows envelopes to be defined outside of accounts, funded by all asset accounts together
+    pub merge_envelopes: bool,
+
+    /// Allows `include !command` to run the command and include its output
+    pub allow_exec: bool,
+
+    /// Allows `include` to fetch URLs
+    pub allow_network: bool,
+
+    /// Reads balance assertions without checking them, so a journal can be read even if some of
+    /// them fail
+    pub skip_balance_assertions: bool,
+}
+
+/// Options for importing entries from csv or qif files
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Prints the subrules that each csv record matched as it's imported
+    pub verbose: bool,
+
+    /// Imports records even if they're already in the journal (see `Entry::is_duplicate_of`)
+    pub allow_duplicates: bool,
+
+    /// Prints the entries that would be imported, and only adds them to the ledger in memory.
+    /// Nothing is written to the journal.
+    pub dry_run: bool,
 }
 
 impl Ledger {
@@ -32,68 +166,254 @@ impl Ledger {
             file_path: PathBuf::new(),
             date_format: String::from("%Y/%m/%d"),
             entries: Vec::new(),
+            next_seq: 0,
+            include_stack: Vec::new(),
             accounts: HashMap::new(),
             default_currency: String::new(),
             decimal_symbol: '.',
+            format: JournalFormat::Silverfox,
+            confirmed_only: false,
+            default_balance_account: None,
+            min_fill: 0.0,
+            locale: None,
+            posting_format: PostingFormat::default(),
+            as_of: None,
+            forgiving: false,
+            parse_warnings: Vec::new(),
+            income_prefixes: DEFAULT_INCOME_PREFIXES
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            expense_prefixes: DEFAULT_EXPENSE_PREFIXES
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            pooled_envelopes: None,
+            prices: Prices::new(),
+            allow_exec: false,
+            allow_network: false,
+            skip_balance_assertions: false,
         }
     }
 
     /// Returns a ledger parsed from a file at the `file_path`.
-    pub fn from_file(file_path: &Path) -> Result<Self, SilverfoxError> {
-        let mut ledger = Self::new();
-        ledger.file_path = PathBuf::from(file_path);
+    pub fn from_file(file_path: &Path, options: &LedgerOptions) -> Result<Self, SilverfoxError> {
+        let mut ledger = Self::with_options(file_path, options);
 
-        if let Err(e) = ledger.add_from_file(file_path) {
+        if let Err(e) = ledger.add_from_file_as(file_path, ledger.format) {
             Err(e)
         } else {
             Ok(ledger)
         }
     }
 
-    /// Adds to the ledger from the contents parsed from the file at the `file_path`.
+    /// Returns a blank ledger set up with `options` for reading the file at `file_path`. If no
+    /// format is given, it's guessed from the file's extension, and a `.ledger` file is read as a
+    /// ledger-cli journal.
+    fn with_options(file_path: &Path, options: &LedgerOptions) -> Self {
+        let mut ledger = Self::new();
+
+        // the current directory changes while parsing (see `add_from_file`), so hold on to an
+        // absolute path for writing back to the file later
+        ledger.file_path =
+            fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+        ledger.format = options
+            .format
+            .or_else(|| JournalFormat::from_path(file_path))
+            .unwrap_or(JournalFormat::Silverfox);
+        ledger.confirmed_only = options.confirmed_only;
+        ledger.posting_format = options.posting_format;
+        ledger.as_of = options.as_of;
+        if options.merge_envelopes {
+            ledger.pooled_envelopes = Some(Account::pooled());
+        }
+        ledger.allow_exec = options.allow_exec;
+        ledger.allow_network = options.allow_network;
+        ledger.skip_balance_assertions = options.skip_balance_assertions;
+
+        ledger
+    }
+
+    /// Adds to the ledger from the contents parsed from the file at the `file_path`. Included
+    /// files are read in the format of the main journal, unless their extension says otherwise.
     fn add_from_file(&mut self, file_path: &Path) -> Result<(), SilverfoxError> {
-        let s = fs::read_to_string(file_path)
-            .map_err(|e| SilverfoxError::file_error(&PathBuf::from(file_path), e))?;
+        let format = JournalFormat::from_path(file_path).unwrap_or(self.format);
+        self.add_from_file_as(file_path, format)
+    }
+
+    fn add_from_file_as(
+        &mut self,
+        file_path: &Path,
+        format: JournalFormat,
+    ) -> Result<(), SilverfoxError> {
+        self.parse_file_with(file_path, format, Self::add_from_str)?
+    }
+
+    /// Reads the file at `file_path` and parses its contents with `parse`, returning whatever
+    /// `parse` does. The file is on the include stack while it's parsed (see `include_stack`).
+    fn parse_file_with<T>(
+        &mut self,
+        file_path: &Path,
+        format: JournalFormat,
+        parse: fn(&mut Self, &str) -> T,
+    ) -> Result<T, SilverfoxError> {
+        let canonical_path =
+            fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+        self.check_include_loop(&canonical_path)?;
+
+        let s = self.read_journal(file_path, format)?;
 
         // change directory to parent after reading to string, and before parsing
         let parent_dir = file_path.parent().unwrap();
 
         std::env::set_current_dir(parent_dir).unwrap_or(());
 
-        self.add_from_str(&s)
+        Ok(self.add_included_str(canonical_path, &s, parse))
+    }
+
+    /// Returns an error if `source` is already being read (see `include_stack`), naming every
+    /// source in the loop in the order they include each other
+    fn check_include_loop(&self, source: &Path) -> Result<(), SilverfoxError> {
+        if let Some(i) = self.include_stack.iter().position(|p| p == source) {
+            let cycle = self.include_stack[i..]
+                .iter()
+                .map(PathBuf::as_path)
+                .chain(std::iter::once(source))
+                .map(|p| format!("`{}`", p.display()))
+                .collect::<Vec<String>>()
+                .join(" includes ");
+
+            return Err(SilverfoxError::Basic(format!(
+                "your journal includes itself in a loop, so silverfox would read it forever: {}",
+                cycle
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Parses `s`, the contents of `source`, with `parse`. `source` stays on the include stack
+    /// while `s` is parsed.
+    fn add_included_str<T>(
+        &mut self,
+        source: PathBuf,
+        s: &str,
+        parse: fn(&mut Self, &str) -> T,
+    ) -> T {
+        self.include_stack.push(source);
+        let result = parse(self, s);
+        self.include_stack.pop();
+
+        result
+    }
+
+    /// Reads the file at `file_path`, translating it to silverfox's format if needed.
+    fn read_journal(
+        &self,
+        file_path: &Path,
+        format: JournalFormat,
+    ) -> Result<String, SilverfoxError> {
+        let s = fs::read_to_string(file_path)
+            .map_err(|e| SilverfoxError::file_error(&PathBuf::from(file_path), e))?;
+
+        match format {
+            JournalFormat::Silverfox => Ok(s),
+            JournalFormat::Ledger => Ok(compat::translate_ledger(
+                &s,
+                &self.accounts.keys().collect(),
+            )),
+        }
+    }
+
+    /// Returns an error if silverfox can't write to the main journal file.
+    fn check_writable(&self) -> Result<(), SilverfoxError> {
+        match self.format {
+            JournalFormat::Silverfox => Ok(()),
+            JournalFormat::Ledger => Err(SilverfoxError::Basic(format!(
+                "silverfox can't write to ledger-format journals, so it won't change `{}`. try --no-move if you're only reading your journal",
+                self.file_path.display()
+            ))),
+        }
     }
 
     /// Adds to the ledger from the contents parsed from the string
     fn add_from_str(&mut self, s: &str) -> Result<(), SilverfoxError> {
+        for chunk in Self::split_chunks(s) {
+            self.parse_chunk(&chunk).map_err(|e| e.or_context(&chunk))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a ledger parsed from the string, along with every error that was found while
+    /// parsing it. Unlike `add_from_str`, parsing doesn't stop at the first error; any chunk that
+    /// fails is skipped, and the ledger is built from the chunks that didn't. Mistakes with an
+    /// obvious fix, like a payee missing its closing bracket, are fixed with a warning instead
+    /// (see `get_warnings`).
+    pub fn parse_lenient(s: &str) -> (Self, Vec<SilverfoxError>) {
+        let mut ledger = Self::new();
+        let errors = ledger.add_from_str_lenient(s);
+
+        (ledger, errors)
+    }
+
+    /// Returns a ledger parsed leniently (see `parse_lenient`) from a file at the `file_path`.
+    /// Only an error reading the file itself stops parsing.
+    pub fn from_file_lenient(
+        file_path: &Path,
+        options: &LedgerOptions,
+    ) -> Result<(Self, Vec<SilverfoxError>), SilverfoxError> {
+        let mut ledger = Self::with_options(file_path, options);
+        let errors =
+            ledger.parse_file_with(file_path, ledger.format, Self::add_from_str_lenient)?;
+
+        Ok((ledger, errors))
+    }
+
+    /// Adds to the ledger from the contents parsed from the string, skipping any chunks that
+    /// can't be parsed. Returns the errors from those chunks.
+    fn add_from_str_lenient(&mut self, s: &str) -> Vec<SilverfoxError> {
+        self.forgiving = true;
+        let errors = Self::split_chunks(s)
+            .iter()
+            .filter_map(|chunk| self.parse_chunk(chunk).err().map(|e| e.or_context(chunk)))
+            .collect();
+        self.forgiving = false;
+
+        errors
+    }
+
+    /// Splits the string into chunks (see `parse_chunk`). Comments are kept, since entries can
+    /// have metadata in them, but lines with nothing but a comment only belong to a chunk if
+    /// they're indented.
+    fn split_chunks(s: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+
         // init a chunk
         let mut chunk = String::new();
 
         // split lines
         let lines = s.lines();
         for mut line in lines {
-            line = utils::remove_comments(line.trim_end());
+            line = line.trim_end();
 
             // if the first character of this line is whitespace, it is part of the current chunk.
-            // if there is no first character, nothing happens
-            if let Some(c) = line.chars().next() {
+            // if there is no first character (besides comments), nothing happens
+            if let Some(c) = utils::remove_comments(line).chars().next() {
                 if c.is_whitespace() {
                     chunk.push('\n');
                     chunk.push_str(line);
                 } else {
-                    if let Err(e) = self.parse_chunk(&chunk) {
-                        return Err(e);
-                    }
+                    chunks.push(chunk);
                     chunk = String::from(line);
                 }
             }
         }
 
-        // parse the last chunk
-        if let Err(e) = self.parse_chunk(&chunk) {
-            Err(e)
-        } else {
-            Ok(())
-        }
+        // add the last chunk
+        chunks.push(chunk);
+
+        chunks
     }
 
     /// Parses a single chunk and adds its contents to the ledger. Returns a SilverfoxError if
@@ -106,15 +426,48 @@ impl Ledger {
             return Ok(()); // blank chunks are fine; they don't modify anything, so no error needed
         }
 
-        let mut tokens = chunk.split_whitespace();
+        // only entries have any use for comments
+        let clean_chunk = chunk
+            .lines()
+            .map(utils::remove_comments)
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        let mut tokens = clean_chunk.split_whitespace();
         let keyword = tokens.next();
-        let value = tokens.next();
+        let value = tokens.clone().next();
         match keyword {
             None => Ok(()),
-            Some("account") => self.parse_account(chunk),
+            Some("account") => self.parse_account(&clean_chunk),
+            Some("expense") | Some("goal") => self.parse_pooled_envelope(&clean_chunk),
+            Some("P") => self.parse_price(&clean_chunk),
             Some("currency") => self.set_currency(value),
             Some("date_format") => self.set_date_format(value),
-            Some("include") => self.include(value),
+            Some("include") => {
+                // commands and URLs are read from the chunk as it was written, since comments
+                // were removed from `value` and `//` is part of every URL
+                let source = chunk.lines().next().unwrap_or("")["include".len()..].trim();
+                if external::is_external(source) {
+                    let key = PathBuf::from(source);
+                    self.check_include_loop(&key)?;
+
+                    let contents = external::read(source, self.allow_exec, self.allow_network)?;
+                    self.add_included_str(key, &contents, Self::add_from_str)
+                } else {
+                    self.include(value)
+                }
+            }
+            Some("default_balance_account") => self.set_default_balance_account(value),
+            Some("min_fill") => self.set_min_fill(value),
+            Some("locale") => self.set_locale(value),
+            Some("income_accounts") => {
+                self.income_prefixes = Self::parse_prefixes("income_accounts", tokens)?;
+                Ok(())
+            }
+            Some("expense_accounts") => {
+                self.expense_prefixes = Self::parse_prefixes("expense_accounts", tokens)?;
+                Ok(())
+            }
             _ => self.parse_entry(chunk),
         }
     }
@@ -148,6 +501,88 @@ impl Ledger {
         }
     }
 
+    fn set_default_balance_account(&mut self, account: Option<&str>) -> Result<(), SilverfoxError> {
+        match account {
+            None => Err(SilverfoxError::from(ParseError {
+                context: None,
+                message: Some(
+                    "no account provided, but default_balance_account keyword was found"
+                        .to_string(),
+                ),
+            })),
+            Some(a) => {
+                self.default_balance_account = Some(a.into());
+                Ok(())
+            }
+        }
+    }
+
+    fn set_min_fill(&mut self, amount: Option<&str>) -> Result<(), SilverfoxError> {
+        let amount = match amount {
+            None => {
+                return Err(SilverfoxError::from(ParseError {
+                    context: None,
+                    message: Some("no amount provided, but min_fill keyword was found".to_string()),
+                }))
+            }
+            Some(a) => a,
+        };
+
+        match amount.replace(self.decimal_symbol, ".").parse::<f64>() {
+            Ok(m) if m >= 0.0 => {
+                self.min_fill = m;
+                Ok(())
+            }
+            _ => Err(SilverfoxError::from(ParseError {
+                context: Some(format!("min_fill {}", amount)),
+                message: Some(String::from(
+                    "min_fill needs to be a number that isn't negative, like `min_fill 0.01`",
+                )),
+            })),
+        }
+    }
+
+    fn set_locale(&mut self, locale: Option<&str>) -> Result<(), SilverfoxError> {
+        match locale {
+            None => Err(SilverfoxError::from(ParseError {
+                context: None,
+                message: Some("no locale provided, but locale keyword was found".to_string()),
+            })),
+            Some(l) => {
+                AmountFormat::from_locale(l)?;
+                self.locale = Some(l.into());
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the format amounts should be displayed with, if the journal has a `locale`
+    /// directive
+    pub fn get_amount_format(&self) -> Option<AmountFormat> {
+        self.locale
+            .as_deref()
+            .and_then(|l| AmountFormat::from_locale(l).ok())
+    }
+
+    /// Parses the account prefixes given to a directive like `income_accounts`
+    fn parse_prefixes<'a>(
+        keyword: &str,
+        tokens: impl Iterator<Item = &'a str>,
+    ) -> Result<Vec<String>, SilverfoxError> {
+        let prefixes = tokens.map(String::from).collect::<Vec<String>>();
+        if prefixes.is_empty() {
+            Err(SilverfoxError::from(ParseError {
+                context: None,
+                message: Some(format!(
+                    "no account prefixes provided, but {} keyword was found",
+                    keyword
+                )),
+            }))
+        } else {
+            Ok(prefixes)
+        }
+    }
+
     fn include(&mut self, file: Option<&str>) -> Result<(), SilverfoxError> {
         match file {
             None => Err(SilverfoxError::from(ParseError {
@@ -158,14 +593,83 @@ impl Ledger {
         }
     }
 
-    fn parse_entry(&mut self, chunk: &str) -> Result<(), SilverfoxError> {
-        match Entry::parse(
+    /// Parses a price line (see `Prices::parse_line`). Prices in the journal's `currency` are
+    /// native prices.
+    fn parse_price(&mut self, line: &str) -> Result<(), SilverfoxError> {
+        let (symbol, date, mut price) =
+            Prices::parse_line(line.trim(), &self.date_format, self.decimal_symbol)?;
+
+        if price.symbol.as_deref() == Some(self.default_currency.as_str()) {
+            price.symbol = None;
+        }
+
+        self.prices.add(symbol, date, price);
+        Ok(())
+    }
+
+    /// Parses an envelope that's defined outside of any account (see `pooled_envelopes`)
+    fn parse_pooled_envelope(&mut self, chunk: &str) -> Result<(), SilverfoxError> {
+        let pooled = match &mut self.pooled_envelopes {
+            Some(p) => p,
+            None => {
+                return Err(SilverfoxError::from(ParseError {
+                    message: Some("this envelope isn't in an account. envelopes can only be defined on their own with --merge-envelopes-across-accounts, which funds them from all of your asset accounts together".to_string()),
+                    context: Some(chunk.to_string()),
+                }))
+            }
+        };
+
+        let envelope = Envelope::parse(
             chunk,
-            &self.date_format,
+            POOLED_ACCOUNT,
             self.decimal_symbol,
-            &self.accounts.keys().collect(),
-        ) {
-            Ok(entry) => self.add_entry(entry),
+            &self.date_format,
+        )?;
+
+        pooled.add_envelope(envelope).map_err(SilverfoxError::from)
+    }
+
+    /// Returns what's available in all asset accounts together, above their buffers. This is what
+    /// ledger-level envelopes are funded from.
+    fn get_pooled_value(accounts: &HashMap<String, Account>) -> AmountPool {
+        let mut value = AmountPool::new();
+        for (name, account) in accounts {
+            if name.starts_with("asset") {
+                value += account.get_fillable_value();
+            }
+        }
+
+        value
+    }
+
+    fn parse_entry(&mut self, chunk: &str) -> Result<(), SilverfoxError> {
+        let accounts = self.accounts.keys().collect();
+        let parsed = if self.forgiving {
+            Entry::parse_forgiving(
+                chunk,
+                &self.date_format,
+                self.decimal_symbol,
+                &accounts,
+                &mut self.parse_warnings,
+            )
+        } else {
+            Entry::parse(chunk, &self.date_format, self.decimal_symbol, &accounts)
+        };
+
+        match parsed {
+            Ok(mut entry) => {
+                if let Some(account) = &self.default_balance_account {
+                    if entry.balance_with(account) && !self.accounts.contains_key(account) {
+                        return Err(SilverfoxError::from(
+                            ValidationError::default()
+                                .set_message(&format!("this entry doesn't balance, so silverfox tried to balance it with `{}`, but that account is not defined in your journal", account))
+                                .set_context(chunk),
+                        ));
+                    }
+                }
+
+                self.add_entry(entry)
+            }
             Err(e) => Err(e),
         }
     }
@@ -173,202 +677,3555 @@ impl Ledger {
     /// Adds an entry to the ledger. Note that this does NOT affect the actual saved file.
     ///
     /// This function shall ensure that the ledger's entries are sorted by date after each insertion.
-    fn add_entry(&mut self, entry: Entry) -> Result<(), SilverfoxError> {
-        for (_, account) in self.accounts.iter_mut() {
-            if let Err(e) = account.process_entry(&entry) {
-                return Err(SilverfoxError::from(e));
+    /// Entries on the same date stay in the order they were added in, which is their file order
+    /// when a journal is read.
+    fn add_entry(&mut self, mut entry: Entry) -> Result<(), SilverfoxError> {
+        if self.is_processed(&entry) {
+            for (_, account) in self.accounts.iter_mut() {
+                let result = if self.skip_balance_assertions {
+                    account.process_entry_unchecked(&entry)
+                } else {
+                    account.process_entry(&entry)
+                };
+
+                if let Err(e) = result {
+                    return Err(SilverfoxError::from(e));
+                }
+            }
+
+            if let Some(pooled) = &mut self.pooled_envelopes {
+                pooled.process_entry(&entry)?;
+                pooled.set_pooled_value(Self::get_pooled_value(&self.accounts));
             }
         }
+        entry.set_seq(self.next_seq);
+        self.next_seq += 1;
+
         self.entries.push(entry);
-        self.entries.sort_by(|a, b| a.get_date().cmp(b.get_date()));
+        self.entries.sort_by(Entry::cmp_order);
+        Ok(())
+    }
+
+    /// Folds `other` into this ledger, as if both had been parsed from the same journal.
+    ///
+    /// Accounts defined in both ledgers are merged (see `Account::merge`), and all entries are
+    /// processed again in date order. The `date_format` and decimal symbol of both ledgers must
+    /// match. The `currency`, `default_balance_account`, `income_accounts`, `expense_accounts`,
+    /// `min_fill`, and `locale` directives must match if both ledgers set them; if only one does, its
+    /// value is used. Anything else, like the journal file and
+    /// reading options, is kept from this ledger.
+    pub fn merge(&mut self, other: Ledger) -> Result<(), SilverfoxError> {
+        let conflict = |what: &str, mine: &str, theirs: &str| {
+            SilverfoxError::from(
+                ValidationError::default().set_message(&format!(
+                    "these ledgers can't be merged because they have different {} settings (`{}` and `{}`)",
+                    what, mine, theirs
+                )),
+            )
+        };
+
+        if self.date_format != other.date_format {
+            return Err(conflict(
+                "date_format",
+                &self.date_format,
+                &other.date_format,
+            ));
+        }
+
+        if self.decimal_symbol != other.decimal_symbol {
+            return Err(conflict(
+                "decimal symbol",
+                &self.decimal_symbol.to_string(),
+                &other.decimal_symbol.to_string(),
+            ));
+        }
+
+        if self.default_currency.is_empty() {
+            self.default_currency = other.default_currency;
+        } else if !other.default_currency.is_empty()
+            && self.default_currency != other.default_currency
+        {
+            return Err(conflict(
+                "currency",
+                &self.default_currency,
+                &other.default_currency,
+            ));
+        }
+
+        match (&self.default_balance_account, other.default_balance_account) {
+            (None, theirs) => self.default_balance_account = theirs,
+            (Some(mine), Some(theirs)) if *mine != theirs => {
+                return Err(conflict("default_balance_account", mine, &theirs))
+            }
+            _ => (),
+        }
+
+        match (&self.locale, other.locale) {
+            (None, theirs) => self.locale = theirs,
+            (Some(mine), Some(theirs)) if *mine != theirs => {
+                return Err(conflict("locale", mine, &theirs))
+            }
+            _ => (),
+        }
+
+        if self.min_fill == 0.0 {
+            self.min_fill = other.min_fill;
+        } else if other.min_fill != 0.0 && self.min_fill != other.min_fill {
+            return Err(conflict(
+                "min_fill",
+                &self.min_fill.to_string(),
+                &other.min_fill.to_string(),
+            ));
+        }
+
+        for (what, mine, theirs, default) in [
+            (
+                "income_accounts",
+                &mut self.income_prefixes,
+                other.income_prefixes,
+                DEFAULT_INCOME_PREFIXES,
+            ),
+            (
+                "expense_accounts",
+                &mut self.expense_prefixes,
+                other.expense_prefixes,
+                DEFAULT_EXPENSE_PREFIXES,
+            ),
+        ] {
+            if *mine == default {
+                *mine = theirs;
+            } else if theirs != default && *mine != theirs {
+                return Err(conflict(what, &mine.join(" "), &theirs.join(" ")));
+            }
+        }
+
+        for (name, account) in other.accounts {
+            match self.accounts.get_mut(&name) {
+                Some(a) => a.merge(account),
+                None => {
+                    self.accounts.insert(name, account);
+                }
+            }
+        }
+
+        self.prices.merge(other.prices);
+
+        match (&mut self.pooled_envelopes, other.pooled_envelopes) {
+            (Some(mine), Some(theirs)) => mine.merge(theirs),
+            (None, theirs) => self.pooled_envelopes = theirs,
+            _ => (),
+        }
+
+        // account values and envelopes depend on the order of entries, so everything is
+        // processed again from the start
+        for account in self.accounts.values_mut().chain(&mut self.pooled_envelopes) {
+            account.reset();
+        }
+
+        // on the same date, this ledger's entries come before the other's. they're numbered again
+        // as they're added
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.extend(other.entries);
+        entries.sort_by(|a, b| a.get_date().cmp(b.get_date()));
+        for entry in entries {
+            self.add_entry(entry)?;
+        }
+
         Ok(())
     }
 
+    /// Returns true if the entry affects accounts and envelopes. Pending entries don't when only
+    /// confirmed entries are read, and neither do entries that aren't in effect yet.
+    fn is_processed(&self, entry: &Entry) -> bool {
+        !(self.confirmed_only && *entry.get_status() == EntryStatus::Pending)
+            && self.is_in_effect(entry)
+    }
+
+    /// Returns false if the entry is dated after the ledger's `as_of` date
+    fn is_in_effect(&self, entry: &Entry) -> bool {
+        match self.as_of {
+            Some(d) => entry.get_date() <= &d,
+            None => true,
+        }
+    }
+
     /// Appends the entry to the file of the Ledger, then internally adds the Entry itself to the
     /// Ledger.
     fn append_entry(&mut self, entry: Entry) -> Result<(), SilverfoxError> {
+        self.check_writable()?;
+
         let mut file = match fs::OpenOptions::new().append(true).open(&self.file_path) {
             Ok(f) => f,
             Err(e) => return Err(SilverfoxError::file_error(&self.file_path, e)),
         };
 
-        if let Err(e) = write!(file, "\n{}", entry.as_parsable(&self.date_format)) {
+        if let Err(e) = write!(
+            file,
+            "\n{}",
+            entry.as_parsable(&self.date_format, self.posting_format)
+        ) {
             return Err(SilverfoxError::Basic(format!("{}", e)));
         }
 
         self.add_entry(entry)
     }
 
-    fn parse_account(&mut self, chunk: &str) -> Result<(), SilverfoxError> {
-        let a = Account::parse(chunk, self.decimal_symbol, &self.date_format)?;
-        self.accounts.insert(a.get_name().to_string(), a);
-
-        Ok(())
-    }
+    /// Returns a String with the entire journal (directives, accounts, and entries) that can be
+    /// written to a file and parsed later on, giving the same ledger. Entries are written in date
+    /// order, since that's the order they're kept in.
+    ///
+    /// Note that comments aren't kept, and any included files are flattened into the result.
+    pub fn as_parsable(&self) -> String {
+        let mut s = format!("date_format {}\n", self.date_format);
 
-    pub fn display_flat_balance(&self) -> Result<(), SilverfoxError> {
-        let totals_map = match self.get_totals() {
-            Ok(m) => m,
-            Err(e) => return Err(e),
-        };
+        if !self.default_currency.is_empty() {
+            s.push_str(&format!("currency {}\n", self.default_currency));
+        }
 
-        let mut totals_vec = totals_map.iter().collect::<Vec<(&String, &AmountPool)>>();
-        totals_vec.sort_by(|a, b| a.0.cmp(b.0));
+        if let Some(a) = &self.default_balance_account {
+            s.push_str(&format!("default_balance_account {}\n", a));
+        }
 
-        for pair in totals_vec.iter() {
-            println!("{:35}    {}", pair.0, pair.1);
+        if self.min_fill != 0.0 {
+            s.push_str(&format!(
+                "min_fill {}\n",
+                self.min_fill
+                    .to_string()
+                    .replace('.', &self.decimal_symbol.to_string())
+            ));
         }
 
-        Ok(())
-    }
+        if let Some(l) = &self.locale {
+            s.push_str(&format!("locale {}\n", l));
+        }
 
-    // TODO This can be rewritten, since totals are accounted for within the Account struct
-    fn get_totals(&self) -> Result<HashMap<String, AmountPool>, SilverfoxError> {
-        // map for account names to amount pools
-        let mut totals_map: HashMap<String, AmountPool> = HashMap::new();
+        if self.income_prefixes != DEFAULT_INCOME_PREFIXES {
+            s.push_str(&format!(
+                "income_accounts {}\n",
+                self.income_prefixes.join(" ")
+            ));
+        }
 
-        // read: for each posting in the ledger, add its amount to its account in totals_map
-        for entry in &self.entries {
-            for posting in entry.get_postings() {
-                let posting_amount = posting.get_amount();
-                let posting_account = posting.get_account();
-                // if the account key exists, just add to it. if it doesn't exist, insert a new key
-                // with the amount
-                match totals_map.get_mut(posting_account) {
-                    Some(pool) => {
-                        if let Some(a) = posting_amount {
-                            *pool += a.clone();
-                        } else {
-                            match entry.get_blank_amount() {
-                                Ok(o) => {
-                                    if let Some(b) = o {
-                                        *pool += b;
-                                    }
-                                }
-                                Err(e) => return Err(SilverfoxError::from(e)),
-                            }
-                        }
-                    }
-                    None => {
-                        // if the posting amount exists, set an AmountPool from the amount as the
-                        // key's value. otherwise, use an AmountPool from a zero Amount.
-                        if let Some(a) = posting_amount {
-                            totals_map
-                                .insert(posting_account.to_owned(), AmountPool::from(a.clone()));
-                        } else {
-                            totals_map.insert(
-                                posting_account.to_owned(),
-                                AmountPool::from(Amount::zero()),
-                            );
-                        }
-                    }
-                }
-            }
+        if self.expense_prefixes != DEFAULT_EXPENSE_PREFIXES {
+            s.push_str(&format!(
+                "expense_accounts {}\n",
+                self.expense_prefixes.join(" ")
+            ));
         }
 
-        Ok(totals_map)
-    }
+        let prices = self.prices.as_parsable(&self.date_format);
+        if !prices.is_empty() {
+            s.push('\n');
+            s.push_str(&prices);
+        }
 
-    pub fn display_envelopes(&self) {
         let mut account_keys = self.accounts.keys().collect::<Vec<&String>>();
         account_keys.sort();
         for key in account_keys {
-            let account = &self.accounts[key];
-            account.display_envelopes();
+            s.push('\n');
+            s.push_str(&self.accounts[key].as_parsable(&self.date_format));
         }
-    }
 
-    pub fn fill_envelopes(&mut self) -> Result<(), SilverfoxError> {
-        let mut postings: Vec<Posting> = Vec::new();
-        for account in self.accounts.values() {
-            postings.append(&mut account.get_filling_postings())
+        if let Some(pooled) = &self.pooled_envelopes {
+            for envelope in pooled.get_envelopes() {
+                s.push('\n');
+                s.push_str(&envelope.as_parsable(&self.date_format));
+                s.push('\n');
+            }
         }
 
-        // remove zero-magnitude postings, they're useless
-        postings.retain(|p| {
-            if let Some(a) = p.get_amount() {
-                a.mag != 0.0
-            } else {
-                false
-            }
-        });
+        for entry in &self.entries {
+            s.push('\n');
+            s.push_str(&entry.as_parsable(&self.date_format, self.posting_format));
+        }
 
-        // if no postings exist, forget adding an entry
-        if postings.is_empty() {
-            return Ok(());
+        s
+    }
+
+    /// Overwrites the file of the Ledger with the Ledger's contents. The journal is written to a
+    /// temporary file first, which then replaces the original, so that the journal isn't left
+    /// half-written if something goes wrong.
+    fn write_to_file(&self) -> Result<(), SilverfoxError> {
+        self.check_writable()?;
+
+        let temp_path = PathBuf::from(format!("{}.tmp", self.file_path.display()));
+
+        fs::write(&temp_path, self.as_parsable())
+            .map_err(|e| SilverfoxError::file_error(&temp_path, e))?;
+        fs::rename(&temp_path, &self.file_path)
+            .map_err(|e| SilverfoxError::file_error(&self.file_path, e))
+    }
+
+    /// Marks every entry with a posting to `account_name` on or before `through` as reconciled.
+    /// Returns the number of entries that were changed. This does NOT affect the actual saved
+    /// file; see `reconcile_and_save`.
+    fn reconcile(&mut self, account_name: &str, through: NaiveDate) -> usize {
+        let mut count = 0;
+
+        for entry in self.entries.iter_mut() {
+            if entry.get_date() <= &through
+                && *entry.get_status() != EntryStatus::Reconciled
+                && entry.contains_account_posting(account_name)
+            {
+                entry.set_status(EntryStatus::Reconciled);
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Reconciles `account_name` through the date given, rewriting the journal file if anything
+    /// changed. Returns the number of entries that were reconciled.
+    pub fn reconcile_and_save(
+        &mut self,
+        account_name: &str,
+        through: NaiveDate,
+    ) -> Result<usize, SilverfoxError> {
+        if !self.accounts.contains_key(account_name) {
+            return Err(SilverfoxError::from(ValidationError::default().set_message(
+                &format!("the account `{}` is not defined in your journal", account_name),
+            )));
+        }
+
+        let count = self.reconcile(account_name, through);
+        if count > 0 {
+            self.write_to_file()?;
         }
 
+        Ok(count)
+    }
+
+    /// Appends a reversal (see `Entry::reverse`) of the latest entry whose description contains
+    /// `description` to the journal, dated `date`. Returns the entry that was reversed, as it
+    /// would be displayed.
+    pub fn reverse_entry_and_save(
+        &mut self,
+        description: &str,
+        date: NaiveDate,
+    ) -> Result<String, SilverfoxError> {
+        let entry = match self
+            .entries
+            .iter()
+            .rev()
+            .find(|e| e.get_description().contains(description))
+        {
+            Some(e) => e,
+            None => {
+                return Err(SilverfoxError::from(
+                    ValidationError::default().set_message(&format!(
+                        "silverfox couldn't find an entry with `{}` in its description",
+                        description
+                    )),
+                ))
+            }
+        };
+
+        let reversed = format!(
+            "{} {}",
+            entry.get_date().format(&self.date_format),
+            entry.get_description()
+        );
+        let reversal = entry.reverse(date);
+        self.append_entry(reversal)?;
+
+        Ok(reversed)
+    }
+
+    /// Appends an entry dated `date` that moves `amount` from the unassigned money in `account`
+    /// into its envelope named `envelope_name`. Returns a warning if that's more than the account
+    /// has unassigned; the entry is still written, since envelopes can be overspent on purpose.
+    pub fn allocate_and_save(
+        &mut self,
+        date: NaiveDate,
+        amount: &str,
+        account: &str,
+        envelope_name: &str,
+    ) -> Result<Option<String>, SilverfoxError> {
+        let (entry, warning) = self.allocation_entry(date, amount, account, envelope_name)?;
+        self.append_entry(entry)?;
+
+        Ok(warning)
+    }
+
+    /// Returns the entry for `allocate_and_save` without writing it, along with its warning if
+    /// there is one
+    fn allocation_entry(
+        &self,
+        date: NaiveDate,
+        amount: &str,
+        account: &str,
+        envelope_name: &str,
+    ) -> Result<(Entry, Option<String>), SilverfoxError> {
+        let amount = Amount::parse(amount, self.decimal_symbol)?;
+
+        let account_data = match account {
+            POOLED_ACCOUNT => self.pooled_envelopes.as_ref(),
+            _ => self.accounts.get(account),
+        };
+        let account_data = match account_data {
+            Some(a) => a,
+            None => {
+                return Err(SilverfoxError::from(
+                    ValidationError::default().set_message(&format!(
+                        "silverfox couldn't find an account named `{}`",
+                        account
+                    )),
+                ))
+            }
+        };
+        let envelope = match account_data.get_envelope(envelope_name) {
+            Some(e) => e,
+            None => {
+                return Err(SilverfoxError::from(
+                    ValidationError::default().set_message(&format!(
+                        "`{}` doesn't have an envelope named `{}`",
+                        account, envelope_name
+                    )),
+                ))
+            }
+        };
+
+        let funding = envelope.to_funding_currency(&amount);
+        let unassigned = account_data.get_available_value().only(&funding.symbol);
+        let warning = if funding.mag > unassigned.mag {
+            Some(format!(
+                "allocating {} to `{}` is more than the {} that `{}` has unassigned",
+                amount.to_string().trim_start(),
+                envelope_name,
+                unassigned.to_string().trim_start(),
+                account
+            ))
+        } else {
+            None
+        };
+
+        let posting = EnvelopePosting::new(account.to_string(), amount, envelope_name.to_string());
         let entry = Entry::new(
-            Local::today().naive_utc(),
-            crate::entry::EntryStatus::Cleared,
-            String::from("move to envelopes"),
+            date,
+            EntryStatus::Cleared,
+            format!("allocate to {}", envelope_name),
             None,
+            vec![Posting::from(posting)],
+            None,
+        );
+
+        Ok((entry, warning))
+    }
+
+    /// Writes a new entry to the journal. Each of `postings` is parsed like a posting line in the
+    /// journal, such as `expenses:food 20`. The entry is balanced with the default balance
+    /// account if there is one, and it has to balance before it's written.
+    pub fn new_entry_and_save(
+        &mut self,
+        date: NaiveDate,
+        status: EntryStatus,
+        description: &str,
+        payee: Option<&str>,
+        postings: &[String],
+    ) -> Result<(), SilverfoxError> {
+        if postings.is_empty() {
+            return Err(SilverfoxError::Basic(String::from("silverfox needs postings for the new entry, like so: `--posting \"expenses:food 20\" --posting assets:checking`")));
+        }
+
+        let accounts = self.accounts.keys().collect();
+        let postings = postings
+            .iter()
+            .map(|p| Posting::parse(p, self.decimal_symbol, &accounts))
+            .collect::<Result<Vec<Posting>, SilverfoxError>>()?;
+
+        let mut entry = Entry::new(
+            date,
+            status,
+            description.to_string(),
+            payee.map(String::from),
             postings,
-            Some(String::from("automatically generated by silverfox")),
+            None,
         );
+        let context = entry.as_parsable(&self.date_format, self.posting_format);
+        entry.validate(&context)?;
+
+        if let Some(account) = &self.default_balance_account {
+            if self.accounts.contains_key(account) {
+                entry.balance_with(account);
+            }
+        }
+
+        if !entry.is_balanced() {
+            return Err(SilverfoxError::from(
+                ValidationError::default()
+                    .set_message("this entry's postings don't add up to zero")
+                    .set_context(&context),
+            ));
+        }
 
         self.append_entry(entry)
     }
 
-    pub fn import_csv(
+    /// Renames the account `old` to `new` everywhere in the ledger: in account definitions,
+    /// envelopes, postings, the `default_balance_account`, and the `income_accounts` and
+    /// `expense_accounts` prefixes. Subaccounts of `old` are renamed too. Returns the number of
+    /// entries that were changed. This does NOT affect the actual saved file; see
+    /// `rename_account_and_save`.
+    fn rename_account(&mut self, old: &str, new: &str) -> Result<usize, SilverfoxError> {
+        let renames = self
+            .accounts
+            .keys()
+            .filter_map(|a| utils::renamed_account(a, old, new).map(|n| (a.clone(), n)))
+            .collect::<Vec<(String, String)>>();
+
+        if renames.is_empty() {
+            return Err(SilverfoxError::from(
+                ValidationError::default().set_message(&format!(
+                    "the account `{}` is not defined in your journal",
+                    old
+                )),
+            ));
+        }
+
+        // renaming onto an account that stays around would leave two definitions of it
+        for (_, new_name) in &renames {
+            if self.accounts.contains_key(new_name) && !renames.iter().any(|r| &r.0 == new_name) {
+                return Err(SilverfoxError::from(
+                    ValidationError::default().set_message(&format!(
+                        "the account `{}` is already defined in your journal",
+                        new_name
+                    )),
+                ));
+            }
+        }
+
+        let mut accounts = HashMap::new();
+        for (name, mut account) in self.accounts.drain() {
+            account.rename(old, new);
+            accounts.insert(
+                utils::renamed_account(&name, old, new).unwrap_or(name),
+                account,
+            );
+        }
+        self.accounts = accounts;
+
+        if let Some(account) = &self.default_balance_account {
+            if let Some(renamed) = utils::renamed_account(account, old, new) {
+                self.default_balance_account = Some(renamed);
+            }
+        }
+
+        for prefix in self
+            .income_prefixes
+            .iter_mut()
+            .chain(self.expense_prefixes.iter_mut())
+        {
+            if let Some(renamed) = utils::renamed_account(prefix, old, new) {
+                *prefix = renamed;
+            }
+        }
+
+        let mut count = 0;
+        for entry in self.entries.iter_mut() {
+            if entry.rename_account(old, new) {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Renames `old` to `new` (see `rename_account`) and rewrites the journal file. Returns the
+    /// number of entries that were changed.
+    pub fn rename_account_and_save(
         &mut self,
-        csv_file: &Path,
-        rules_file: Option<&PathBuf>,
+        old: &str,
+        new: &str,
+    ) -> Result<usize, SilverfoxError> {
+        let count = self.rename_account(old, new)?;
+        self.write_to_file()?;
+
+        Ok(count)
+    }
+
+    fn parse_account(&mut self, chunk: &str) -> Result<(), SilverfoxError> {
+        let a = Account::parse(chunk, self.decimal_symbol, &self.date_format)?;
+        self.accounts.insert(a.get_name().to_string(), a);
+
+        Ok(())
+    }
+
+    pub fn display_flat_balance(
+        &self,
+        account_match: Option<AccountMatcher>,
+        market: Option<NaiveDate>,
+        color: ColorChoice,
     ) -> Result<(), SilverfoxError> {
-        let account_set = self.accounts.keys().cloned().collect();
+        self.write_flat_balance(std::io::stdout(), &account_match, market, color)
+    }
+
+    /// Writes the balance of every account (or only those that `account_match` matches) to
+    /// `writer`, with negative amounts in red if `color` allows it. If `market` is given,
+    /// holdings are valued in the native currency on that day (see `get_balance_totals`).
+    fn write_flat_balance<W: Write>(
+        &self,
+        mut writer: W,
+        account_match: &Option<AccountMatcher>,
+        market: Option<NaiveDate>,
+        color: ColorChoice,
+    ) -> Result<(), SilverfoxError> {
+        let totals_map = match self.get_balance_totals(market) {
+            Ok(m) => m,
+            Err(e) => return Err(e),
+        };
+
+        let mut totals_vec = totals_map
+            .iter()
+            .filter(|(name, _)| match account_match {
+                Some(matcher) => matcher.matches(name),
+                None => true,
+            })
+            .collect::<Vec<(&String, &AmountPool)>>();
+        totals_vec.sort_by(|a, b| a.0.cmp(b.0));
+
+        for pair in totals_vec.iter() {
+            if let Err(e) = writeln!(
+                writer,
+                "{:35}    {}",
+                pair.0,
+                Self::balance_amounts(pair.1, color)
+            ) {
+                return Err(SilverfoxError::Basic(format!(
+                    "silverfox couldn't write the balance: {}",
+                    e
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the amounts of a balance line, with negative amounts in red if `color` allows it.
+    /// Pools with more than one amount are written the same way as AmountPool's Display.
+    fn balance_amounts(pool: &AmountPool, color: ColorChoice) -> String {
+        let amounts = pool
+            .iter()
+            .map(|a| {
+                if a.mag < 0.0 {
+                    color.red(&a.to_string())
+                } else {
+                    a.to_string()
+                }
+            })
+            .collect::<Vec<String>>();
+
+        if amounts.len() > 1 {
+            amounts.iter().map(|a| format!("\n\t{}", a)).collect()
+        } else {
+            amounts.join("")
+        }
+    }
+
+    /// Displays the balance of every account as a tree, where each part of an account's name is
+    /// indented under its parent and each parent shows the total of everything under it (see
+    /// `write_tree_balance`)
+    pub fn display_tree_balance(
+        &self,
+        account_match: Option<AccountMatcher>,
+        market: Option<NaiveDate>,
+        color: ColorChoice,
+    ) -> Result<(), SilverfoxError> {
+        self.write_tree_balance(std::io::stdout(), &account_match, market, color)
+    }
+
+    /// Writes the balance of every account as a tree. `assets:checking` and `assets:cash` are
+    /// written as `checking` and `cash` under `assets`, which has the total of both. If
+    /// `account_match` is given, parents only have the totals of the accounts it matches. `market`
+    /// works the same way as in `write_flat_balance`.
+    fn write_tree_balance<W: Write>(
+        &self,
+        mut writer: W,
+        account_match: &Option<AccountMatcher>,
+        market: Option<NaiveDate>,
+        color: ColorChoice,
+    ) -> Result<(), SilverfoxError> {
+        // sort by each part of the name, so that subaccounts always come right after their parents
+        let mut nodes: BTreeMap<Vec<&str>, AmountPool> = BTreeMap::new();
+        let totals = self.get_balance_totals(market)?;
+        let mut totals_vec = totals
+            .iter()
+            .filter(|(name, _)| match account_match {
+                Some(matcher) => matcher.matches(name),
+                None => true,
+            })
+            .collect::<Vec<(&String, &AmountPool)>>();
+        totals_vec.sort_by(|a, b| a.0.cmp(b.0));
 
-        let imp = match rules_file {
-            Some(r) => CsvImporter::from_file_with_rules(csv_file, r, account_set),
-            None => CsvImporter::from_file(csv_file, account_set),
-        }?;
+        for (account, total) in totals_vec {
+            let parts = account.split(':').collect::<Vec<&str>>();
+            for depth in 1..=parts.len() {
+                *nodes.entry(parts[..depth].to_vec()).or_default() += total;
+            }
+        }
+
+        for (parts, total) in nodes {
+            let name = format!("{}{}", "  ".repeat(parts.len() - 1), parts[parts.len() - 1]);
+            if let Err(e) = writeln!(
+                writer,
+                "{:35}    {}",
+                name,
+                Self::balance_amounts(&total, color)
+            ) {
+                return Err(SilverfoxError::Basic(format!(
+                    "silverfox couldn't write the balance: {}",
+                    e
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Displays the balance of every account as tab-separated values (see `write_tsv_balance`)
+    pub fn display_tsv_balance(&self) -> Result<(), SilverfoxError> {
+        self.write_tsv_balance(std::io::stdout())
+    }
+
+    /// Writes the balance of every account as tab-separated rows of account, amount, and currency,
+    /// one row for each currency an account has. The currency is blank for amounts without one.
+    fn write_tsv_balance<W: Write>(&self, mut writer: W) -> Result<(), SilverfoxError> {
+        let totals_map = self.get_totals()?;
+
+        let mut totals_vec = totals_map.iter().collect::<Vec<(&String, &AmountPool)>>();
+        totals_vec.sort_by(|a, b| a.0.cmp(b.0));
+
+        let has_separator = |s: &str| s.contains(['\t', '\n', '\r']);
+
+        for (account, pool) in totals_vec {
+            for amount in pool.iter() {
+                let currency = amount.symbol.as_deref().unwrap_or("");
 
-        for result in imp {
-            match result {
-                Ok(e) => {
-                    self.append_entry(e)?;
+                // these would break the row apart, and there's no standard way to escape them
+                if has_separator(account) || has_separator(currency) {
+                    return Err(SilverfoxError::Basic(format!(
+                        "`{}` can't be written as tab-separated values because it contains a tab or a line break",
+                        account
+                    )));
+                }
+
+                if let Err(e) = writeln!(
+                    writer,
+                    "{}\t{}\t{}",
+                    account,
+                    amount.rounded_mag(),
+                    currency
+                ) {
+                    return Err(SilverfoxError::Basic(format!(
+                        "silverfox couldn't write the balance: {}",
+                        e
+                    )));
                 }
-                Err(e) => return Err(e),
             }
         }
 
         Ok(())
     }
 
-    /// Display a register of all transactions from `begin_date` (inclusive) to `end_date` (also
-    /// inclusive). Also filter out any entries that don't have an account matching
-    /// `account_match`, i.e. `account_match` doesn't appear in any of the postings of an entry.
-    pub fn display_register(
+    /// Returns the total of every account with postings. Accounts keep their own totals as
+    /// entries are added, so those are used instead of going through every posting again.
+    fn get_totals(&self) -> Result<HashMap<String, AmountPool>, SilverfoxError> {
+        // pending entries count towards totals, but accounts don't process them when only
+        // confirmed entries are read, so the totals have to be found the long way
+        if self.confirmed_only {
+            return self.get_totals_of(|e| self.is_in_effect(e));
+        }
+
+        // every posting's account is defined in the journal, so every account that has postings
+        // is here. an account's value is only empty if it has no postings at all
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(_, account)| !account.get_real_value().is_empty())
+            .map(|(name, account)| (name.clone(), account.get_real_value().clone()))
+            .collect())
+    }
+
+    /// Returns the total of all asset accounts and the total of all liability accounts, in that
+    /// order. Accounts are classified by their top-level name (`assets` or `liabilities`, or
+    /// anything that starts the same way, like `asset`).
+    pub fn get_net_worth(&self) -> Result<(AmountPool, AmountPool), SilverfoxError> {
+        let mut assets = AmountPool::new();
+        let mut liabilities = AmountPool::new();
+
+        // sort so that currencies always come out in the same order
+        let mut totals = self.get_totals()?.into_iter().collect::<Vec<_>>();
+        totals.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (account, total) in totals {
+            if account.starts_with("asset") {
+                assets += total;
+            } else if account.starts_with("liabilit") {
+                liabilities += total;
+            }
+        }
+
+        Ok((assets, liabilities))
+    }
+
+    /// Returns true if the account is an income account; that is, if it starts with any of the
+    /// prefixes in the `income_accounts` directive (`income` by default)
+    pub fn is_income_account(&self, account: &str) -> bool {
+        self.income_prefixes
+            .iter()
+            .any(|p| account.starts_with(p.as_str()))
+    }
+
+    /// Returns true if the account is an expense account; that is, if it starts with any of the
+    /// prefixes in the `expense_accounts` directive (`expense` by default)
+    pub fn is_expense_account(&self, account: &str) -> bool {
+        self.expense_prefixes
+            .iter()
+            .any(|p| account.starts_with(p.as_str()))
+    }
+
+    /// Returns the total of all income accounts and the total of all expense accounts, in that
+    /// order (see `is_income_account` and `is_expense_account`).
+    pub fn get_income_and_expenses(&self) -> Result<(AmountPool, AmountPool), SilverfoxError> {
+        let mut income = AmountPool::new();
+        let mut expenses = AmountPool::new();
+
+        // sort so that currencies always come out in the same order
+        let mut totals = self.get_totals()?.into_iter().collect::<Vec<_>>();
+        totals.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (account, total) in totals {
+            if self.is_income_account(&account) {
+                income += total;
+            } else if self.is_expense_account(&account) {
+                expenses += total;
+            }
+        }
+
+        Ok((income, expenses))
+    }
+
+    /// Returns a single line with the total assets, total liabilities, and net worth of the
+    /// ledger (see `get_net_worth`). Each total lists every currency it has, separated by commas.
+    pub fn summary_line(&self) -> Result<String, SilverfoxError> {
+        let (assets, liabilities) = self.get_net_worth()?;
+
+        let mut net = assets.clone();
+        net += &liabilities;
+
+        Ok(format!(
+            "assets: {} | liabilities: {} | net: {}",
+            Self::inline_pool(&assets),
+            Self::inline_pool(&liabilities),
+            Self::inline_pool(&net)
+        ))
+    }
+
+    /// Returns every amount in the pool on one line, separated by commas, or `0` if the pool is
+    /// empty
+    fn inline_pool(pool: &AmountPool) -> String {
+        if pool.is_empty() {
+            String::from("0")
+        } else {
+            pool.iter()
+                .map(|a| a.to_string().trim_start().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+    }
+
+    /// Displays an overview of the whole ledger: net worth, how much is saved in envelopes, the
+    /// dates the journal covers, and how many entries and accounts it has
+    pub fn display_summary(&self) -> Result<(), SilverfoxError> {
+        self.write_summary(std::io::stdout())
+    }
+
+    fn write_summary<W: Write>(&self, mut writer: W) -> Result<(), SilverfoxError> {
+        let (assets, liabilities) = self.get_net_worth()?;
+        let mut net = assets;
+        net += &liabilities;
+
+        // sort so that currencies always come out in the same order
+        let mut account_names = self.accounts.keys().collect::<Vec<_>>();
+        account_names.sort();
+        let mut in_envelopes = AmountPool::new();
+        for envelope in account_names
+            .iter()
+            .flat_map(|name| self.accounts[*name].get_envelopes())
+        {
+            in_envelopes += envelope.get_now_amount();
+            in_envelopes += envelope.get_next_amount();
+        }
+
+        let entries = self
+            .entries
+            .iter()
+            .filter(|e| self.is_in_effect(e))
+            .collect::<Vec<_>>();
+        let dates = match (
+            entries.iter().map(|e| e.get_date()).min(),
+            entries.iter().map(|e| e.get_date()).max(),
+        ) {
+            (Some(first), Some(last)) => format!(
+                "{} to {}",
+                first.format(&self.date_format),
+                last.format(&self.date_format)
+            ),
+            _ => String::from("none"),
+        };
+
+        let write_err =
+            |e: std::io::Error| SilverfoxError::Basic(format!("couldn't display a summary: {}", e));
+        writeln!(writer, "net worth       {}", Self::inline_pool(&net)).map_err(write_err)?;
+        writeln!(
+            writer,
+            "in envelopes    {}",
+            Self::inline_pool(&in_envelopes)
+        )
+        .map_err(write_err)?;
+        writeln!(writer, "dates           {}", dates).map_err(write_err)?;
+        writeln!(writer, "entries         {}", entries.len()).map_err(write_err)?;
+        writeln!(writer, "accounts        {}", self.accounts.len()).map_err(write_err)?;
+
+        Ok(())
+    }
+
+    /// Returns the balance of every account as of the end of `date`. Accounts without any
+    /// postings on or before `date` aren't included.
+    pub fn balance_on(
+        &self,
+        date: NaiveDate,
+    ) -> Result<HashMap<String, AmountPool>, SilverfoxError> {
+        self.get_totals_of(|e| e.get_date() <= &date)
+    }
+
+    /// Returns the total of every account (see `get_totals`). If `market` is given, every amount
+    /// with a native price on that day is valued in the native currency (see `Prices::to_native`).
+    fn get_balance_totals(
+        &self,
+        market: Option<NaiveDate>,
+    ) -> Result<HashMap<String, AmountPool>, SilverfoxError> {
+        let totals = self.get_totals()?;
+
+        Ok(match market {
+            Some(date) => totals
+                .into_iter()
+                .map(|(account, total)| (account, self.prices.to_native(&total, date)))
+                .collect(),
+            None => totals,
+        })
+    }
+
+    /// Returns the totals of every account, considering only the entries for which `predicate`
+    /// returns true.
+    fn get_totals_of<P>(
+        &self,
+        mut predicate: P,
+    ) -> Result<HashMap<String, AmountPool>, SilverfoxError>
+    where
+        P: FnMut(&Entry) -> bool,
+    {
+        // map for account names to amount pools
+        let mut totals_map: HashMap<String, AmountPool> = HashMap::new();
+
+        // read: for each posting in the ledger, add its amount to its account in totals_map.
+        // envelope postings are virtual, so they don't count towards totals
+        for entry in self.entries.iter().filter(|e| predicate(e)) {
+            for posting in entry.get_postings().iter().filter(|p| p.is_classic()) {
+                // use the posting's amount, or infer the blank amount if there isn't one
+                let amount = entry.get_posting_amount(posting)?;
+
+                // if the account key exists, just add to it. if it doesn't exist, insert a new key
+                // with the amount
+                *totals_map
+                    .entry(posting.get_account().to_owned())
+                    .or_default() += amount;
+            }
+        }
+
+        Ok(totals_map)
+    }
+
+    /// Writes a csv with one row per account and one column per date in `dates`. Each cell is the
+    /// balance of the account at the end of that date. Cells are left empty for accounts that
+    /// have no postings yet at that date.
+    pub fn write_balance_snapshots<W: Write>(
+        &self,
+        writer: W,
+        dates: &[NaiveDate],
+    ) -> Result<(), SilverfoxError> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+
+        let mut header = vec![String::from("account")];
+        header.extend(
+            dates
+                .iter()
+                .map(|d| d.format(&self.date_format).to_string()),
+        );
+        csv_writer.write_record(&header)?;
+
+        let snapshots = dates
+            .iter()
+            .map(|d| self.balance_on(*d))
+            .collect::<Result<Vec<HashMap<String, AmountPool>>, SilverfoxError>>()?;
+
+        let mut account_names = self.get_totals()?.keys().cloned().collect::<Vec<String>>();
+        account_names.sort();
+
+        for name in account_names {
+            let mut record = vec![name.clone()];
+            for snapshot in &snapshots {
+                let cell = match snapshot.get(&name) {
+                    Some(pool) => pool
+                        .iter()
+                        .map(|a| format!("{}", a).trim().to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    None => String::new(),
+                };
+                record.push(cell);
+            }
+            csv_writer.write_record(&record)?;
+        }
+
+        csv_writer
+            .flush()
+            .map_err(|e| SilverfoxError::Basic(format!("{}", e)))
+    }
+
+    /// Prints balance snapshots as a csv, with one column for the end of each `step` period from
+    /// `begin_date` to `end_date`. If no `begin_date` is given, the date of the first entry is
+    /// used. If no `end_date` is given, today is used.
+    pub fn display_balance_snapshots(
         &self,
         begin_date: Option<NaiveDate>,
         end_date: Option<NaiveDate>,
-        account_match: Option<String>,
-    ) {
-        Register::display(
-            &self.entries,
-            &self.date_format,
-            begin_date,
-            end_date,
-            account_match,
-        )
-        .unwrap();
+        step: Period,
+    ) -> Result<(), SilverfoxError> {
+        let today = Local::today().naive_local();
+        let begin = begin_date
+            .or_else(|| self.entries.first().map(|e| *e.get_date()))
+            .unwrap_or(today);
+        let end = end_date.unwrap_or(today);
+
+        self.write_balance_snapshots(std::io::stdout(), &step.period_ends(begin, end))
     }
-}
 
-impl Debug for Ledger {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for ent in &self.entries {
-            Debug::fmt(&ent, f)?;
-            writeln!(f)?;
+    /// Displays every account's envelopes, on one line each if `compact` is true, and with due
+    /// dates relative to `relative_to` if it's given
+    pub fn display_envelopes(
+        &self,
+        compact: bool,
+        relative_to: Option<NaiveDate>,
+        color: ColorChoice,
+    ) {
+        let mut account_keys = self.accounts.keys().collect::<Vec<&String>>();
+        account_keys.sort();
+        for key in account_keys {
+            let account = &self.accounts[key];
+            account.display_envelopes(compact, relative_to, color);
         }
 
-        Ok(())
+        if let Some(pooled) = &self.pooled_envelopes {
+            pooled.display_envelopes(compact, relative_to, color);
+        }
     }
-}
 
-#[derive(Clone, Copy, Debug)]
-pub enum Period {
-    Yearly,
-    Monthly,
-    Weekly,
-    Daily, // ???
+    /// Displays every envelope as a JSON array (see `Envelope::as_json`)
+    pub fn display_envelopes_json(&self, as_of: NaiveDate) -> Result<(), SilverfoxError> {
+        self.write_envelopes_json(std::io::stdout(), as_of)
+            .map_err(|e| SilverfoxError::Basic(format!("couldn't display envelopes: {}", e)))
+    }
+
+    fn write_envelopes_json<W: Write>(
+        &self,
+        mut writer: W,
+        as_of: NaiveDate,
+    ) -> std::io::Result<()> {
+        let mut account_keys = self.accounts.keys().collect::<Vec<&String>>();
+        account_keys.sort();
+
+        let objects = account_keys
+            .into_iter()
+            .flat_map(|key| self.accounts[key].get_envelopes())
+            .map(|e| e.as_json(as_of))
+            .collect::<Vec<String>>();
+
+        writeln!(writer, "[{}]", objects.join(",\n"))
+    }
+
+    /// Displays every repeating envelope as a budget for beancount or ledger (see `budget`).
+    /// Envelopes without a starting date are budgeted from `date`.
+    pub fn display_budget_export(
+        &self,
+        format: BudgetFormat,
+        date: NaiveDate,
+    ) -> Result<(), SilverfoxError> {
+        self.write_budget_export(std::io::stdout(), format, date)
+    }
+
+    fn write_budget_export<W: Write>(
+        &self,
+        writer: W,
+        format: BudgetFormat,
+        date: NaiveDate,
+    ) -> Result<(), SilverfoxError> {
+        let mut envelopes = self
+            .accounts
+            .values()
+            .chain(self.pooled_envelopes.iter())
+            .flat_map(|a| a.get_envelopes())
+            .collect::<Vec<&Envelope>>();
+        envelopes
+            .sort_by(|a, b| (a.get_account(), a.get_name()).cmp(&(b.get_account(), b.get_name())));
+
+        budget::write(writer, &envelopes, format, date, &self.default_currency)
+    }
+
+    /// Returns the whole journal written for ledger-cli and hledger (see `Entry::as_ledger`):
+    /// account declarations, then prices, then every entry. Envelope definitions have no
+    /// equivalent in ledger, so they're left out.
+    pub fn export_ledger_format(&self) -> String {
+        let mut accounts = self.accounts.keys().collect::<Vec<&String>>();
+        accounts.sort();
+
+        let mut s = String::new();
+        for account in accounts {
+            s.push_str(&format!("account {}\n", account));
+        }
+
+        let prices = self.prices.as_parsable("%Y-%m-%d");
+        if !prices.is_empty() {
+            s.push('\n');
+            s.push_str(&prices);
+        }
+
+        for entry in &self.entries {
+            s.push('\n');
+            s.push_str(&entry.as_ledger());
+        }
+
+        s
+    }
+
+    /// Returns every balance assertion in the journal and whether it holds, checking each one
+    /// against its account's balance at that point, in date order. Unlike reading the journal,
+    /// this doesn't stop at the first assertion that fails.
+    pub fn assertions_report(&self) -> Vec<AssertionResult> {
+        let mut balances: HashMap<&String, AmountPool> = HashMap::new();
+        let mut results = Vec::new();
+
+        for entry in self.entries.iter().filter(|e| self.is_processed(e)) {
+            for posting in entry.get_postings().iter().filter(|p| !p.is_envelope()) {
+                let balance = balances.entry(posting.get_account()).or_default();
+                if let Ok(amount) = entry.get_posting_amount(posting) {
+                    *balance += amount;
+                }
+
+                if let Some(expected) = posting.get_balance_assertion() {
+                    results.push(AssertionResult {
+                        date: *entry.get_date(),
+                        description: entry.get_description().to_string(),
+                        account: posting.get_account().clone(),
+                        expected: expected.clone(),
+                        actual: Amount {
+                            mag: balance.only(&expected.symbol).mag,
+                            symbol: expected.symbol.clone(),
+                        },
+                    });
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Displays every balance assertion in the journal and whether it holds (see
+    /// `assertions_report`)
+    pub fn display_assertions_report(&self, color: ColorChoice) -> Result<(), SilverfoxError> {
+        assertions::write(
+            std::io::stdout(),
+            &self.assertions_report(),
+            &self.date_format,
+            color,
+        )
+        .map_err(|e| SilverfoxError::Basic(format!("couldn't display balance assertions: {}", e)))
+    }
+
+    /// Displays the envelopes of every account in one list, sorted by when they're due next as of
+    /// `as_of`. Untracked envelopes (see `Envelope::is_untracked`) are left out, since they're
+    /// never due.
+    pub fn display_flat_envelopes(&self, as_of: NaiveDate) -> Result<(), SilverfoxError> {
+        self.write_flat_envelopes(std::io::stdout(), as_of)
+            .map_err(|e| SilverfoxError::Basic(format!("couldn't display envelopes: {}", e)))
+    }
+
+    fn write_flat_envelopes<W: Write>(
+        &self,
+        mut writer: W,
+        as_of: NaiveDate,
+    ) -> std::io::Result<()> {
+        let mut envelopes = self
+            .accounts
+            .iter()
+            .flat_map(|(name, account)| account.get_envelopes().map(move |e| (name, e)))
+            .filter(|(_, e)| !e.is_untracked())
+            .map(|(name, e)| (e.get_due_date(as_of), name, e))
+            .collect::<Vec<_>>();
+
+        // `None` would sort before any date, so envelopes without dates are put last explicitly
+        envelopes.sort_by(|a, b| {
+            (a.0.is_none(), a.0, a.1, a.2.get_name()).cmp(&(
+                b.0.is_none(),
+                b.0,
+                b.1,
+                b.2.get_name(),
+            ))
+        });
+
+        let due_dates = envelopes
+            .iter()
+            .map(|(due_date, _, _)| match due_date {
+                Some(d) => d.format(&self.date_format).to_string(),
+                None => String::from("-"),
+            })
+            .collect::<Vec<String>>();
+        let date_width = due_dates.iter().map(|d| d.len()).max().unwrap_or(0);
+        let account_width = envelopes
+            .iter()
+            .map(|(_, name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+
+        for ((_, account, envelope), due_date) in envelopes.iter().zip(due_dates) {
+            writeln!(
+                writer,
+                "{:dw$}  {:aw$}  {}",
+                due_date,
+                account,
+                format!("{:#}", envelope).trim_start(),
+                dw = date_width,
+                aw = account_width
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Displays how much needs to go into every envelope to fill it by its due date, starting on
+    /// `as_of`
+    pub fn display_contribution_plans(&self, as_of: NaiveDate) {
+        let mut account_keys = self.accounts.keys().collect::<Vec<&String>>();
+        account_keys.sort();
+        for key in account_keys {
+            self.accounts[key].display_contribution_plans(as_of, &self.date_format);
+        }
+    }
+
+    /// Displays everything about one account: its balance as of `as_of`, its envelopes, and its
+    /// latest entries up to `as_of`
+    pub fn display_account_detail(
+        &self,
+        account_name: &str,
+        as_of: NaiveDate,
+        color: ColorChoice,
+    ) -> Result<(), SilverfoxError> {
+        self.write_account_detail(std::io::stdout(), account_name, as_of, color)
+    }
+
+    fn write_account_detail<W: Write>(
+        &self,
+        mut writer: W,
+        account_name: &str,
+        as_of: NaiveDate,
+        color: ColorChoice,
+    ) -> Result<(), SilverfoxError> {
+        let account = match self.accounts.get(account_name) {
+            Some(a) => a,
+            None => {
+                return Err(SilverfoxError::from(
+                    ValidationError::default().set_message(&format!(
+                        "the account `{}` is not defined in your journal",
+                        account_name
+                    )),
+                ))
+            }
+        };
+
+        let write_err = |e: std::io::Error| {
+            SilverfoxError::Basic(format!("couldn't display an account: {}", e))
+        };
+
+        writeln!(writer, "balance").map_err(write_err)?;
+        let balance = self
+            .balance_on(as_of)?
+            .remove(account_name)
+            .unwrap_or_default();
+        if balance.is_empty() {
+            writeln!(writer, "    0").map_err(write_err)?;
+        }
+        for amount in balance.iter() {
+            writeln!(writer, "    {}", amount).map_err(write_err)?;
+        }
+
+        writeln!(writer, "\nenvelopes").map_err(write_err)?;
+        if account.get_envelopes().next().is_none() {
+            writeln!(writer, "    none\n").map_err(write_err)?;
+        } else {
+            account
+                .write_envelopes(&mut writer, false, None, color)
+                .map_err(write_err)?;
+        }
+
+        writeln!(writer, "latest entries").map_err(write_err)?;
+        let matcher = AccountMatcher::regex(&format!("^{}$", regex::escape(account_name)))?;
+        Register::write_latest(
+            writer,
+            &self.entries,
+            &self.date_format,
+            Some(as_of),
+            matcher,
+            ACCOUNT_DETAIL_ENTRIES,
+        )
+    }
+
+    /// Appends an entry that moves money into every envelope that needs it on `today` (see
+    /// `fill_entry`)
+    pub fn fill_envelopes(&mut self, today: NaiveDate) -> Result<(), SilverfoxError> {
+        match self.fill_entry(today) {
+            Some(entry) => self.append_entry(entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the entry `fill_envelopes` would append on `today`, as it would be written to the
+    /// journal, without appending it. Returns None if no envelopes need filling.
+    pub fn dry_fill_envelopes(&mut self, today: NaiveDate) -> Option<String> {
+        self.fill_entry(today)
+            .map(|e| e.as_parsable(&self.date_format, self.posting_format))
+    }
+
+    /// Returns an entry with postings that fill every envelope for `today`, or None if no
+    /// envelopes need filling
+    fn fill_entry(&mut self, today: NaiveDate) -> Option<Entry> {
+        let mut postings: Vec<Posting> = Vec::new();
+        let mut leftover = AmountPool::new();
+        for (name, account) in self.accounts.iter() {
+            let (mut account_postings, account_leftover) =
+                account.get_filling_postings_from(account.get_fillable_value(), today);
+            postings.append(&mut account_postings);
+
+            if name.starts_with("asset") {
+                leftover += account_leftover;
+            }
+        }
+
+        // ledger-level envelopes are filled with whatever the accounts' own envelopes didn't take
+        if let Some(pooled) = &mut self.pooled_envelopes {
+            pooled.set_pooled_value(leftover);
+            postings.append(&mut pooled.get_filling_postings(today));
+
+            // the leftover value was only for filling; the pooled account holds everything else
+            pooled.set_pooled_value(Self::get_pooled_value(&self.accounts));
+        }
+
+        // remove zero-magnitude postings, they're useless. postings smaller than `min_fill` are
+        // removed too; their envelopes are still short, so they're filled in a later run
+        postings.retain(|p| {
+            if let Some(a) = p.get_amount() {
+                a.mag != 0.0 && a.mag.abs() >= self.min_fill
+            } else {
+                false
+            }
+        });
+
+        // if no postings exist, forget adding an entry
+        if postings.is_empty() {
+            return None;
+        }
+
+        Some(Entry::new(
+            today,
+            EntryStatus::Cleared,
+            String::from("move to envelopes"),
+            None,
+            postings,
+            Some(String::from("automatically generated by silverfox")),
+        ))
+    }
+
+    /// Imports every csv file in `csv_files`, in order. The rules for each file are found like so:
+    ///
+    /// 1. `rules_file`, if it's given
+    /// 2. the file at `$SILVERFOX_RULES`, if it's set
+    /// 3. the rules file next to the csv file (see `CsvImporter::from_file`)
+    ///
+    /// Records that are already in the journal are skipped (see `import_entries`), unless the
+    /// `options` allow duplicates. Returns how many records were skipped.
+    pub fn import_csv(
+        &mut self,
+        csv_files: &[PathBuf],
+        rules_file: Option<&PathBuf>,
+        delimiter: Option<u8>,
+        options: &ImportOptions,
+    ) -> Result<usize, SilverfoxError> {
+        let default_rules_file = CsvImporter::get_env_rules_path();
+        let rules_file = rules_file.or(default_rules_file.as_ref());
+
+        let mut skipped = 0;
+        for csv_file in csv_files {
+            let account_set = self.accounts.keys().cloned().collect();
+
+            let mut imp = match rules_file {
+                Some(r) => CsvImporter::from_file_with_rules(csv_file, r, account_set, delimiter),
+                None => CsvImporter::from_file(csv_file, account_set, delimiter),
+            }?;
+            imp.set_verbose(options.verbose);
+
+            skipped += self.import_entries(imp, options)?;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Imports csv data that was read from somewhere other than a file, like stdin
+    pub fn import_csv_str(
+        &mut self,
+        csv_str: &str,
+        rules_file: &Path,
+        delimiter: Option<u8>,
+        options: &ImportOptions,
+    ) -> Result<usize, SilverfoxError> {
+        let account_set = self.accounts.keys().cloned().collect();
+        let mut imp =
+            CsvImporter::from_str_with_rules(csv_str, rules_file, account_set, delimiter)?;
+        imp.set_verbose(options.verbose);
+
+        self.import_entries(imp, options)
+    }
+
+    /// Imports each qif file in `qif_files` (see `QifImporter`). Every entry gets a posting to
+    /// `account`, which is the account the qif files were exported from. Duplicates are handled
+    /// like they are in `import_csv`.
+    pub fn import_qif(
+        &mut self,
+        qif_files: &[PathBuf],
+        account: &str,
+        options: &ImportOptions,
+    ) -> Result<usize, SilverfoxError> {
+        if !self.accounts.contains_key(account) {
+            return Err(SilverfoxError::from(
+                ValidationError::default()
+                    .set_message(&format!("qif transactions can't be imported into `{}` because it isn't an account in your journal", account)),
+            ));
+        }
+
+        let mut skipped = 0;
+        for qif_file in qif_files {
+            let imp = QifImporter::from_file(qif_file, account, self.decimal_symbol)?;
+            skipped += self.import_entries(imp, options)?;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Appends each imported entry to the journal, or on a dry run, prints it and only adds it to
+    /// the ledger. Unless the `options` allow duplicates, an entry is skipped if it's a duplicate
+    /// of one that was in the journal before the import (see `Entry::is_duplicate_of`). Each
+    /// existing entry only accounts for one imported entry, so identical transactions in the same
+    /// file are all kept. Returns how many were skipped.
+    fn import_entries<I>(
+        &mut self,
+        imp: I,
+        options: &ImportOptions,
+    ) -> Result<usize, SilverfoxError>
+    where
+        I: Iterator<Item = Result<Entry, SilverfoxError>>,
+    {
+        let imported = imp.collect::<Result<Vec<Entry>, SilverfoxError>>()?;
+
+        // marks the existing entries that have already been matched with an imported one
+        let mut matched = vec![false; self.entries.len()];
+        let mut new_entries = Vec::new();
+        for e in imported {
+            let duplicate = if options.allow_duplicates {
+                None
+            } else {
+                self.entries
+                    .iter()
+                    .enumerate()
+                    .position(|(i, x)| !matched[i] && e.is_duplicate_of(x))
+            };
+
+            match duplicate {
+                Some(i) => matched[i] = true,
+                None => new_entries.push(e),
+            }
+        }
+
+        let skipped = matched.iter().filter(|m| **m).count();
+        for e in new_entries {
+            if options.dry_run {
+                print!(
+                    "\n{}",
+                    e.as_parsable(&self.date_format, self.posting_format)
+                );
+                self.add_entry(e)?;
+            } else {
+                self.append_entry(e)?;
+            }
+        }
+
+        Ok(skipped)
+    }
+
+    /// Returns the entries whose postings don't balance (see `Entry::is_balanced`)
+    pub fn unbalanced_entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| !e.is_balanced())
+    }
+
+    /// Returns a warning for each envelope that probably can't be funded, judging by how much
+    /// money has flowed into its account so far.
+    ///
+    /// The heuristic: an account's average monthly inflow is the sum of its positive postings
+    /// divided by the number of months between the first and last entries in the journal (at
+    /// least one). An envelope's monthly requirement is its amount times how often it comes due
+    /// in a month (see `Frequency::periods_per_month`). If an envelope needs more each month than
+    /// its account takes in, in the envelope's currency, it's flagged. Envelopes that don't
+    /// repeat are skipped, since they can be saved for over any stretch of time.
+    pub fn lint_envelopes(&self) -> Vec<String> {
+        let (first, last) = match (self.entries.first(), self.entries.last()) {
+            (Some(f), Some(l)) => (*f.get_date(), *l.get_date()),
+            _ => return Vec::new(),
+        };
+
+        let months = ((last - first).num_days() as f64 / utils::DAYS_PER_MONTH).max(1.0);
+
+        let mut account_names = self.accounts.keys().collect::<Vec<&String>>();
+        account_names.sort();
+
+        let mut warnings = Vec::new();
+        for account_name in account_names {
+            let account = &self.accounts[account_name];
+
+            // blank postings count too, with the amounts they're inferred to have
+            let mut inflow = AmountPool::new();
+            for entry in &self.entries {
+                for posting in entry.get_postings() {
+                    if !posting.is_classic() || posting.get_account() != account_name {
+                        continue;
+                    }
+
+                    if let Ok(a) = entry.get_posting_amount(posting) {
+                        if a.mag > 0.0 {
+                            inflow += a;
+                        }
+                    }
+                }
+            }
+
+            for envelope in account.get_envelopes() {
+                let per_month = match envelope.get_freq().periods_per_month() {
+                    Some(p) => p,
+                    None => continue,
+                };
+
+                let amount = envelope.get_amount();
+                let required = amount.mag * per_month;
+                let average_inflow = inflow.only(&amount.symbol).mag / months;
+
+                if required > average_inflow {
+                    warnings.push(format!(
+                        "the envelope `{}` in `{}` needs about {:.2} a month, but only about {:.2} comes into the account each month",
+                        envelope.get_name(),
+                        account_name,
+                        required,
+                        average_inflow
+                    ));
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// Returns a warning for each entry whose date looks like a typo, like a year that's off by
+    /// one. An entry is flagged if it's at least `max_gap_days` days from the entries on both
+    /// sides of it (or from its only neighbor, at either end of the journal), or if it's at least
+    /// `max_gap_days` days after `today`.
+    pub fn validate_dates(&self, today: NaiveDate, max_gap_days: i64) -> Vec<String> {
+        let max_gap = chrono::Duration::days(max_gap_days);
+        let mut warnings = Vec::new();
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let date = *entry.get_date();
+            let describe = || {
+                format!(
+                    "`{} {}`",
+                    date.format(&self.date_format),
+                    entry.get_description()
+                )
+            };
+
+            if date - today >= max_gap {
+                warnings.push(format!(
+                    "the entry {} is dated {} or more days in the future. is the year right?",
+                    describe(),
+                    max_gap_days
+                ));
+                continue;
+            }
+
+            // entries are sorted by date, so the nearest entries are right next to this one
+            let gaps = [
+                i.checked_sub(1).map(|j| date - *self.entries[j].get_date()),
+                self.entries.get(i + 1).map(|e| *e.get_date() - date),
+            ];
+            let mut gaps = gaps.iter().flatten().peekable();
+            if gaps.peek().is_some() && gaps.all(|g| *g >= max_gap) {
+                warnings.push(format!(
+                    "the entry {} is {} or more days away from any other entry. is the year right?",
+                    describe(),
+                    max_gap_days
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    /// Returns every warning about the ledger: mistakes forgiven while parsing (see
+    /// `parse_lenient`), envelopes that probably can't be funded (see `lint_envelopes`), and
+    /// dates that look like typos (see `validate_dates`)
+    pub fn get_warnings(&self, today: NaiveDate, max_date_gap_days: i64) -> Vec<String> {
+        let mut warnings = self.parse_warnings.clone();
+        warnings.append(&mut self.lint_envelopes());
+        warnings.append(&mut self.validate_dates(today, max_date_gap_days));
+
+        warnings
+    }
+
+    /// Display a register of all transactions from `begin_date` (inclusive) to `end_date` (also
+    /// inclusive). Also filter out any entries that don't have an account matching
+    /// `account_match` (see `AccountMatcher`). If `table` is given, the register is drawn as a
+    /// table with borders in that style.
+    pub fn display_register(
+        &self,
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+        table: Option<TableStyle>,
+    ) -> Result<(), SilverfoxError> {
+        Register::display(
+            &self.entries,
+            &self.date_format,
+            begin_date,
+            end_date,
+            account_match,
+            tag_match,
+            table,
+        )
+    }
+
+    /// Displays the register as JSON (see `Register::display_json`)
+    pub fn display_register_json(
+        &self,
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+    ) -> Result<(), SilverfoxError> {
+        Register::display_json(
+            &self.entries,
+            begin_date,
+            end_date,
+            account_match,
+            tag_match,
+        )
+    }
+
+    /// Displays the register as JSON lines (see `Register::display_json_lines`)
+    pub fn display_register_json_lines(
+        &self,
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+    ) -> Result<(), SilverfoxError> {
+        Register::display_json_lines(
+            &self.entries,
+            begin_date,
+            end_date,
+            account_match,
+            tag_match,
+        )
+    }
+}
+
+impl Debug for Ledger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for ent in &self.entries {
+            Debug::fmt(&ent, f)?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays the entire journal, the same way it's written to a file (see `Ledger::as_parsable`)
+impl std::fmt::Display for Ledger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_parsable())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Yearly,
+    Monthly,
+    Weekly,
+    Daily, // ???
+}
+
+impl Period {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "yearly" | "year" => Ok(Self::Yearly),
+            "monthly" | "month" => Ok(Self::Monthly),
+            "weekly" | "week" => Ok(Self::Weekly),
+            "daily" | "day" => Ok(Self::Daily),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't a period silverfox understands. use `daily`, `weekly`, `monthly`, or `yearly`",
+                s
+            ))),
+        }
+    }
+
+    /// Returns the first date of the period that `date` falls in. Weeks start on Monday.
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Yearly => NaiveDate::from_ymd(date.year(), 1, 1),
+            Self::Monthly => NaiveDate::from_ymd(date.year(), date.month(), 1),
+            Self::Weekly => {
+                date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            Self::Daily => date,
+        }
+    }
+
+    /// Returns the last date of the period that `date` falls in. Weeks end on Sunday.
+    pub fn end_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Yearly => NaiveDate::from_ymd(date.year(), 12, 31),
+            Self::Monthly => utils::last_date_of_month(date),
+            Self::Weekly => {
+                date + chrono::Duration::days(6 - date.weekday().num_days_from_monday() as i64)
+            }
+            Self::Daily => date,
+        }
+    }
+
+    /// Returns the last date of every period from the one containing `begin` up to `end`. Only
+    /// dates on or before `end` are included.
+    pub fn period_ends(&self, begin: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+
+        let mut date = self.end_of(begin);
+        while date <= end {
+            dates.push(date);
+            date = self.end_of(date.succ());
+        }
+
+        dates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEDGER_STR: &str = "account assets:checking
+account assets:savings
+account expenses:groceries
+
+2020/01/02 ? Groceries
+    assets:checking     -50
+    expenses:groceries   50
+
+2020/01/05 ~ Transfer
+    assets:checking    -100
+    assets:savings      100
+
+2020/01/10 * Groceries
+    assets:checking     -20
+    expenses:groceries   20
+
+2020/02/01 ~ Groceries
+    assets:checking     -30
+    expenses:groceries   30
+
+2020/01/20 ~ Interest
+    assets:savings        1
+    expenses:groceries   -1";
+
+    fn ledger_from_str(s: &str) -> Ledger {
+        let mut ledger = Ledger::new();
+        if let Err(e) = ledger.add_from_str(s) {
+            panic!("{}", e);
+        }
+
+        ledger
+    }
+
+    #[test]
+    fn reconcile_test() {
+        let mut ledger = ledger_from_str(LEDGER_STR);
+
+        let count = ledger.reconcile("assets:checking", NaiveDate::from_ymd(2020, 1, 31));
+        assert_eq!(count, 2, "only the pending and cleared January entries should change");
+
+        let statuses = ledger
+            .entries
+            .iter()
+            .map(|e| e.get_status().to_char())
+            .collect::<String>();
+
+        // entries are sorted by date: groceries, transfer, groceries, interest, groceries
+        assert_eq!(statuses, "***~~");
+
+        // running it again shouldn't change anything
+        let count = ledger.reconcile("assets:checking", NaiveDate::from_ymd(2020, 1, 31));
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn balance_snapshots_test() {
+        let ledger = ledger_from_str(LEDGER_STR);
+        let dates = Period::Monthly.period_ends(
+            NaiveDate::from_ymd(2019, 12, 15),
+            NaiveDate::from_ymd(2020, 2, 29),
+        );
+
+        let mut buffer = Vec::new();
+        if let Err(e) = ledger.write_balance_snapshots(&mut buffer, &dates) {
+            panic!("{}", e);
+        }
+
+        let csv = String::from_utf8(buffer).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("account,2019/12/31,2020/01/31,2020/02/29")
+        );
+        assert_eq!(lines.next(), Some("assets:checking,,-170,-200"));
+        assert_eq!(lines.next(), Some("assets:savings,,101,101"));
+    }
+
+    #[test]
+    fn flat_balance_color_test() {
+        let ledger = ledger_from_str(LEDGER_STR);
+
+        let mut buffer = Vec::new();
+        if let Err(e) = ledger.write_flat_balance(&mut buffer, &None, None, ColorChoice::Always) {
+            panic!("{}", e);
+        }
+
+        // a Vec isn't a terminal, but `always` colors anyway
+        let output = String::from_utf8(buffer).unwrap();
+        let checking = output.lines().next().unwrap();
+        assert!(checking.starts_with("assets:checking"));
+        assert!(checking.ends_with("\x1b[31m-200\x1b[0m"));
+
+        let mut buffer = Vec::new();
+        if let Err(e) = ledger.write_flat_balance(&mut buffer, &None, None, ColorChoice::Never) {
+            panic!("{}", e);
+        }
+        assert!(!String::from_utf8(buffer).unwrap().contains('\x1b'));
+    }
+
+    #[test]
+    fn market_balance_test() {
+        let ledger = ledger_from_str(
+            "currency USD
+
+P 2024/01/01 BTC 42000 USD
+P 2024/03/01 BTC 60000 USD
+
+account assets:checking
+account assets:crypto
+account assets:euros
+
+2024/01/05 * Buying bitcoin
+    assets:crypto       0.5 BTC @ 40000
+    assets:checking
+
+2024/01/06 * Buying euros
+    assets:euros        100 EUR @ 1.1
+    assets:checking",
+        );
+
+        let write = |market: Option<NaiveDate>| {
+            let mut buffer = Vec::new();
+            ledger
+                .write_flat_balance(&mut buffer, &None, market, ColorChoice::Never)
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let line = |output: &str, account: &str| {
+            output
+                .lines()
+                .find(|l| l.starts_with(account))
+                .unwrap()
+                .trim_start_matches(account)
+                .trim()
+                .to_string()
+        };
+
+        let held = write(None);
+        assert_eq!(line(&held, "assets:crypto"), "0.5 BTC");
+
+        // the latest price on or before the day is used
+        let valued = write(Some(NaiveDate::from_ymd(2024, 2, 1)));
+        assert_eq!(line(&valued, "assets:crypto"), "21000");
+        let valued = write(Some(NaiveDate::from_ymd(2024, 3, 1)));
+        assert_eq!(line(&valued, "assets:crypto"), "30000");
+
+        // commodities without prices stay as they are
+        assert_eq!(line(&valued, "assets:euros"), "100 EUR");
+    }
+
+    #[test]
+    fn filtered_balance_test() {
+        let ledger = ledger_from_str(LEDGER_STR);
+        let write = |matcher: AccountMatcher| {
+            let mut buffer = Vec::new();
+            ledger
+                .write_flat_balance(&mut buffer, &Some(matcher), None, ColorChoice::Never)
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+
+        let assets = write(AccountMatcher::Substring(String::from("assets:")));
+        assert!(!assets.is_empty());
+        assert!(assets.lines().all(|l| l.starts_with("assets:")));
+
+        assert!(write(AccountMatcher::Substring(String::from("nothing"))).is_empty());
+    }
+
+    #[test]
+    fn command_include_needs_permission_test() {
+        let err = Ledger::new()
+            .add_from_str("include !echo account assets:checking")
+            .unwrap_err();
+        assert!(err.to_string().contains("--allow-exec"));
+    }
+
+    #[test]
+    #[cfg(feature = "exec-include")]
+    fn command_include_test() {
+        let options = LedgerOptions {
+            allow_exec: true,
+            ..LedgerOptions::default()
+        };
+        let mut ledger = Ledger::with_options(Path::new("journal.sfox"), &options);
+        ledger
+            .add_from_str("include !echo 'account assets:checking' ; echo 'currency $'")
+            .unwrap();
+
+        assert!(ledger.accounts.contains_key("assets:checking"));
+        assert_eq!(ledger.default_currency, "$");
+    }
+
+    #[test]
+    #[cfg(feature = "exec-include")]
+    fn circular_command_include_test() {
+        let path = std::env::temp_dir().join("silverfox_circular_command_include_test.sfox");
+        let include = format!("include !cat {}", path.display());
+        fs::write(&path, format!("account assets:checking\n\n{}\n", include)).unwrap();
+
+        let options = LedgerOptions {
+            allow_exec: true,
+            ..LedgerOptions::default()
+        };
+        let mut ledger = Ledger::with_options(Path::new("journal.sfox"), &options);
+        let result = ledger.add_from_str(&include);
+        fs::remove_file(&path).unwrap();
+
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("in a loop"), "{}", message);
+        assert_eq!(message.matches("`!cat ").count(), 2, "{}", message);
+    }
+
+    #[test]
+    fn circular_include_test() {
+        let dir = std::env::temp_dir().join("silverfox_circular_include_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let main_path = dir.join("main.sfox");
+        fs::write(
+            &main_path,
+            "account assets:checking\n\ninclude other.sfox\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("other.sfox"),
+            "account expenses:food\n\ninclude main.sfox\n",
+        )
+        .unwrap();
+
+        let result = Ledger::from_file(&main_path, &LedgerOptions::default());
+        let lenient = Ledger::from_file_lenient(&main_path, &LedgerOptions::default());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // reading leniently finds the same loop, instead of reading the main journal twice
+        let (_, errors) = lenient.unwrap();
+        assert_eq!(errors.len(), 1);
+        let lenient_message = errors[0].to_string();
+        assert!(lenient_message.contains("in a loop"));
+        assert!(lenient_message.find("main.sfox") < lenient_message.find("other.sfox"));
+
+        // the error names every file in the loop, in the order they include each other
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("in a loop"), "{}", message);
+        let main_at = message.find("main.sfox").unwrap();
+        let other_at = message.find("other.sfox").unwrap();
+        assert!(main_at < other_at);
+        assert!(message.rfind("main.sfox").unwrap() > other_at);
+    }
+
+    #[test]
+    fn pooled_envelopes_test() {
+        let journal = "account assets:checking
+account assets:savings
+account income:salary
+account expenses:travel
+
+goal vacation due 2020/06/01
+    amount 400
+    for expenses:travel
+    funding aggressive
+
+2020/01/01 * Paycheck
+    assets:checking     300
+    assets:savings      200
+    income:salary";
+
+        // envelopes outside of accounts need the flag
+        assert!(Ledger::new().add_from_str(journal).is_err());
+
+        let options = LedgerOptions {
+            merge_envelopes: true,
+            ..LedgerOptions::default()
+        };
+        let mut ledger = Ledger::with_options(Path::new("journal.sfox"), &options);
+        ledger.add_from_str(journal).unwrap();
+
+        // the envelope is filled from both accounts together
+        let pooled = ledger.pooled_envelopes.as_ref().unwrap();
+        let postings = pooled.get_filling_postings(NaiveDate::from_ymd(2020, 1, 2));
+        assert_eq!(postings.len(), 1);
+        assert_eq!(postings[0].get_amount().unwrap().mag, 400.0);
+
+        ledger
+            .add_from_str(
+                "2020/01/02 * move to envelopes
+    envelope * vacation 400
+
+2020/01/10 * Plane tickets
+    expenses:travel     150
+    assets:savings",
+            )
+            .unwrap();
+
+        // spending from either account comes out of the envelope
+        let pooled = ledger.pooled_envelopes.as_ref().unwrap();
+        let vacation = pooled.get_envelope("vacation").unwrap();
+        assert_eq!(
+            vacation.get_now_amount().mag + vacation.get_next_amount().mag,
+            250.0
+        );
+        assert_eq!(pooled.get_available_value().only(&None).mag, 100.0);
+    }
+
+    #[test]
+    fn tree_balance_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+account assets:cash
+account assets2
+account expenses:food:groceries
+account expenses:food:dining_out
+account income:salary
+
+2020/01/01 * Paycheck
+    assets:checking     1000
+    assets2             10
+    income:salary
+
+2020/01/02 * Groceries
+    expenses:food:groceries     50
+    assets:checking
+
+2020/01/03 * Pizza
+    expenses:food:dining_out    20
+    assets:cash",
+        );
+
+        let mut buffer = Vec::new();
+        ledger
+            .write_tree_balance(&mut buffer, &None, None, ColorChoice::Never)
+            .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines = output
+            .lines()
+            .map(|l| {
+                let (name, amount) = l.split_at(35);
+                (name.trim_end(), amount.trim())
+            })
+            .collect::<Vec<(&str, &str)>>();
+
+        assert_eq!(
+            lines,
+            vec![
+                ("assets", "930"),
+                ("  cash", "-20"),
+                ("  checking", "950"),
+                ("assets2", "10"),
+                ("expenses", "70"),
+                ("  food", "70"),
+                ("    dining_out", "20"),
+                ("    groceries", "50"),
+                ("income", "-1010"),
+                ("  salary", "-1010"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tsv_balance_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+account expenses:travel
+
+2020/01/02 * Train tickets
+    assets:checking     $-80.5
+    expenses:travel     $80.5
+
+2020/01/03 * Hotel
+    assets:checking     -120 EUR
+    expenses:travel      120 EUR",
+        );
+
+        let mut buffer = Vec::new();
+        if let Err(e) = ledger.write_tsv_balance(&mut buffer) {
+            panic!("{}", e);
+        }
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "assets:checking\t-80.5\t$
+assets:checking\t-120\tEUR
+expenses:travel\t80.5\t$
+expenses:travel\t120\tEUR
+"
+        );
+    }
+
+    #[test]
+    fn ledger_format_test() {
+        let journal = "2020/01/02 * Paycheck
+    Assets:Checking    $1000
+    Income:Salary
+
+2020/01/03 Grocery Store
+    Expenses:Food    $50
+    Assets:Checking
+";
+        let path = std::env::temp_dir().join("silverfox_ledger_format_test.ledger");
+        fs::write(&path, journal).unwrap();
+
+        let ledger = Ledger::from_file(&path, &LedgerOptions::default());
+        fs::remove_file(&path).unwrap();
+
+        let ledger = match ledger {
+            Ok(l) => l,
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(ledger.format, JournalFormat::Ledger);
+
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(
+            totals["Assets:Checking"].only(&Some(String::from("$"))).mag,
+            950.0
+        );
+        assert_eq!(
+            totals["Expenses:Food"].only(&Some(String::from("$"))).mag,
+            50.0
+        );
+
+        // ledger journals can't be written to
+        assert!(ledger.write_to_file().is_err());
+    }
+
+    #[test]
+    fn ledger_export_test() {
+        let journal = "account assets:checking
+    expense rent due every 5th
+        amount $300
+
+account expenses:rent
+account expenses:food
+account expenses:household
+account income:job
+
+P 2020/01/01 BTC $7000
+
+2020/01/02 ~ paycheck [acme] // january
+    // :work:
+    assets:checking    $1000
+    income:job
+
+2020/01/03 ? groceries
+    assets:checking    $-90 ! $910
+    expenses:food    weight 2
+    expenses:household    weight 1
+
+2020/01/05 * rent
+    // category: housing
+    expenses:rent    $300
+    assets:checking
+    envelope assets:checking rent    $-300
+";
+        let ledger = ledger_from_str(journal);
+        let exported = ledger.export_ledger_format();
+
+        assert!(exported.contains("P 2020-01-01 BTC $ 7000\n"));
+        assert!(exported.contains(
+            "2020-01-02 * paycheck  ; january
+    ; payee: acme
+    ; :work:
+    assets:checking  $ 1000
+    income:job
+"
+        ));
+        assert!(exported.contains(
+            "2020-01-03 ! groceries
+    assets:checking     $-90 = $ 910
+    expenses:food       $ 60
+    expenses:household  $ 30
+"
+        ));
+        assert!(exported.contains("    ; category: housing\n"));
+        assert!(exported.contains("    ; envelope assets:checking rent $-300\n"));
+
+        // reading the export back as a ledger journal should give the same totals
+        let path = std::env::temp_dir().join("silverfox_ledger_export_test.ledger");
+        fs::write(&path, &exported).unwrap();
+        let exported_ledger = Ledger::from_file(&path, &LedgerOptions::default());
+        fs::remove_file(&path).unwrap();
+
+        let exported_ledger = match exported_ledger {
+            Ok(l) => l,
+            Err(e) => panic!("{}", e),
+        };
+
+        let dollars = Some(String::from("$"));
+        let totals = ledger.get_totals().unwrap();
+        let exported_totals = exported_ledger.get_totals().unwrap();
+        for account in [
+            "assets:checking",
+            "expenses:food",
+            "expenses:household",
+            "expenses:rent",
+            "income:job",
+        ] {
+            assert_eq!(
+                totals[account].only(&dollars).mag,
+                exported_totals[account].only(&dollars).mag,
+                "{} doesn't match",
+                account
+            );
+        }
+    }
+
+    #[test]
+    fn assertions_report_test() {
+        let journal = "account assets:checking
+account income:job
+account expenses:food
+
+2020/01/02 ~ paycheck
+    assets:checking    1000 ! 1000
+    income:job
+
+2020/01/03 ~ groceries
+    expenses:food    50
+    assets:checking    ! 900
+";
+
+        // a failing assertion keeps the journal from being read normally
+        let mut ledger = Ledger::new();
+        assert!(ledger.add_from_str(journal).is_err());
+
+        let mut ledger = Ledger::new();
+        ledger.skip_balance_assertions = true;
+        if let Err(e) = ledger.add_from_str(journal) {
+            panic!("{}", e);
+        }
+
+        let report = ledger.assertions_report();
+        assert_eq!(report.len(), 2);
+
+        assert_eq!(report[0].account, "assets:checking");
+        assert_eq!(report[0].expected.mag, 1000.0);
+        assert!(report[0].holds());
+
+        assert_eq!(report[1].description, "groceries");
+        assert_eq!(report[1].expected.mag, 900.0);
+        assert_eq!(report[1].actual.mag, 950.0);
+        assert!(!report[1].holds());
+
+        let mut output = Vec::new();
+        assertions::write(&mut output, &report, "%Y/%m/%d", ColorChoice::Never).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("2020/01/02  assets:checking  expected 1000  has 1000  ok  (paycheck)")
+        );
+        assert!(output
+            .contains("2020/01/03  assets:checking  expected  900  has  950  failed  (groceries)"));
+        assert!(output.ends_with("1 of 2 balance assertions failed\n"));
+    }
+
+    #[test]
+    fn confirmed_only_test() {
+        let journal = "account assets:checking
+    expense rent due every 5th
+        amount 300
+        funding aggressive
+account income:salary
+
+2020/01/01 ? Paycheck
+    assets:checking     600
+    income:salary";
+
+        let mut ledger = Ledger::new();
+        ledger.confirmed_only = true;
+        if let Err(e) = ledger.add_from_str(journal) {
+            panic!("{}", e);
+        }
+
+        // the pending paycheck hasn't arrived, so there's nothing to fill the envelope with
+        let checking = &ledger.accounts["assets:checking"];
+        assert!(checking.get_available_value().is_zero());
+        assert!(checking
+            .get_filling_postings(NaiveDate::from_ymd(2020, 1, 15))
+            .iter()
+            .all(|p| p.get_amount().unwrap().mag == 0.0));
+
+        let ledger = ledger_from_str(journal);
+        let postings = ledger.accounts["assets:checking"]
+            .get_filling_postings(NaiveDate::from_ymd(2020, 1, 15));
+        assert_eq!(postings[0].get_amount().unwrap().mag, 300.0);
+    }
+
+    #[test]
+    fn as_of_test() {
+        let journal = "account assets:checking
+    expense rent due every 5th
+        amount 300
+        funding aggressive
+account income:salary
+account expenses:rent
+
+2020/01/01 * Paycheck
+    assets:checking     600
+    income:salary
+
+2020/02/05 * Rent
+    assets:checking     -1000
+    expenses:rent";
+
+        let mut ledger = Ledger::new();
+        ledger.as_of = Some(NaiveDate::from_ymd(2020, 1, 15));
+        if let Err(e) = ledger.add_from_str(journal) {
+            panic!("{}", e);
+        }
+
+        // the scheduled rent payment hasn't happened yet
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["assets:checking"].only(&None).mag, 600.0);
+        assert!(!totals.contains_key("expenses:rent"));
+        let postings = ledger.accounts["assets:checking"]
+            .get_filling_postings(NaiveDate::from_ymd(2020, 1, 15));
+        assert_eq!(postings[0].get_amount().unwrap().mag, 300.0);
+
+        // without a date, every entry counts
+        let ledger = ledger_from_str(journal);
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["assets:checking"].only(&None).mag, -400.0);
+        assert_eq!(totals["expenses:rent"].only(&None).mag, 1000.0);
+    }
+
+    #[test]
+    fn merge_test() {
+        let mut ledger = ledger_from_str(
+            "account assets:checking
+    expense rent due every 5th
+        amount 300
+        funding aggressive
+account income:salary
+
+2020/01/01 * Paycheck
+    assets:checking     1000
+    income:salary",
+        );
+
+        let other = ledger_from_str(
+            "account assets:checking
+    expense groceries due every 20th
+        amount 200
+        funding aggressive
+account expenses:food
+
+2020/01/03 * Groceries
+    assets:checking     -50
+    expenses:food",
+        );
+
+        if let Err(e) = ledger.merge(other) {
+            panic!("{}", e);
+        }
+
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["assets:checking"].only(&None).mag, 950.0);
+        assert_eq!(totals["expenses:food"].only(&None).mag, 50.0);
+        assert_eq!(ledger.entries.len(), 2);
+
+        // envelopes from both definitions of the account are there
+        let checking = &ledger.accounts["assets:checking"];
+        assert!(checking.get_envelope("rent").is_some());
+        assert!(checking.get_envelope("groceries").is_some());
+        assert_eq!(checking.get_available_value().only(&None).mag, 950.0);
+
+        // conflicting directives can't be merged
+        let other = ledger_from_str("date_format %d/%m/%Y");
+        assert!(ledger.merge(other).is_err());
+    }
+
+    #[test]
+    fn totals_test() {
+        let journal = "account assets:checking
+    expense rent due every 5th
+        amount 300
+account assets:wallet
+account assets:unused
+account income:salary
+account expenses:food
+
+2020/01/05 * Groceries
+    expenses:food       12.34
+    assets:wallet       5 EUR = 6
+    assets:checking
+
+2020/01/01 * Paycheck
+    assets:checking     1000.10
+    income:salary
+
+2020/01/02 * Rent
+    envelope assets:checking rent   -300
+    assets:checking     -300
+    expenses:food       300
+
+2020/01/03 ? Lunch
+    expenses:food       0.1
+    assets:checking     -0.1";
+
+        let totals_as_strings = |totals: HashMap<String, AmountPool>| {
+            let mut v = totals
+                .into_iter()
+                .map(|(name, pool)| format!("{} {:?}", name, pool.iter().collect::<Vec<_>>()))
+                .collect::<Vec<String>>();
+            v.sort();
+            v
+        };
+
+        let ledger = ledger_from_str(journal);
+        let totals = ledger.get_totals().unwrap();
+        assert!(!totals.contains_key("assets:unused"));
+        assert_eq!(
+            totals_as_strings(totals),
+            totals_as_strings(ledger.get_totals_of(|e| ledger.is_in_effect(e)).unwrap())
+        );
+
+        // pending entries still count when only confirmed entries are read
+        let mut ledger = Ledger::new();
+        ledger.confirmed_only = true;
+        ledger.add_from_str(journal).unwrap();
+        assert_eq!(
+            totals_as_strings(ledger.get_totals().unwrap()),
+            totals_as_strings(ledger.get_totals_of(|e| ledger.is_in_effect(e)).unwrap())
+        );
+    }
+
+    #[test]
+    fn summary_line_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+account assets:savings
+account liabilities:credit_card
+account income:salary
+account expenses:food
+
+2020/01/01 * Paycheck
+    assets:checking     $1000
+    income:salary
+
+2020/01/02 * Savings
+    assets:savings      50 CAD
+    income:salary
+
+2020/01/03 * Groceries
+    liabilities:credit_card     $-200
+    expenses:food",
+        );
+
+        assert_eq!(
+            ledger.summary_line().unwrap(),
+            "assets: $ 1000, 50 CAD | liabilities: $-200 | net: $ 800, 50 CAD"
+        );
+    }
+
+    #[test]
+    fn summary_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+    expense rent due every 5th
+        amount $300
+        funding aggressive
+account liabilities:credit_card
+account income:salary
+account expenses:food
+
+2020/01/01 * Paycheck
+    assets:checking     $1000
+    income:salary
+
+2020/01/03 * Groceries
+    liabilities:credit_card     $-200
+    expenses:food
+
+2020/01/04 * Move to envelopes
+    envelope assets:checking rent    $120",
+        );
+
+        let mut output = Vec::new();
+        ledger.write_summary(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "net worth       $ 800
+in envelopes    $ 120
+dates           2020/01/01 to 2020/01/04
+entries         3
+accounts        4
+"
+        );
+    }
+
+    #[test]
+    fn income_and_expense_accounts_test() {
+        let ledger = ledger_from_str(
+            "income_accounts ingresos revenue
+expense_accounts gastos
+
+account activos:banco
+account ingresos:salario
+account revenue:consulting
+account gastos:comida
+account income:other
+
+2020/01/01 * Paycheck
+    activos:banco       1000
+    ingresos:salario
+
+2020/01/02 * Consulting
+    activos:banco       200
+    revenue:consulting
+
+2020/01/03 * Groceries
+    activos:banco       -50
+    gastos:comida",
+        );
+
+        assert!(ledger.is_income_account("ingresos:salario"));
+        assert!(ledger.is_income_account("revenue:consulting"));
+        assert!(ledger.is_expense_account("gastos:comida"));
+
+        // the english prefixes don't apply once they're replaced
+        assert!(!ledger.is_income_account("income:other"));
+        assert!(!ledger.is_expense_account("expenses:food"));
+        assert!(!ledger.is_income_account("activos:banco"));
+
+        let (income, expenses) = ledger.get_income_and_expenses().unwrap();
+        assert_eq!(income.to_string().trim(), "-1200");
+        assert_eq!(expenses.to_string().trim(), "50");
+
+        // the prefixes survive a round trip through the journal
+        let reparsed = ledger_from_str(&ledger.as_parsable());
+        assert!(reparsed.is_income_account("revenue:consulting"));
+        assert!(reparsed.is_expense_account("gastos:comida"));
+
+        // and the defaults are the english prefixes
+        let default = ledger_from_str("account expenses:food");
+        assert!(default.is_income_account("income:salary"));
+        assert!(default.is_expense_account("expenses:food"));
+        assert!(!default.as_parsable().contains("income_accounts"));
+    }
+
+    #[test]
+    fn account_detail_test() {
+        let mut journal = String::from(
+            "account assets:checking
+    expense rent due every 15th
+        amount 500
+        for expenses:rent
+
+account expenses:rent
+account income:salary
+",
+        );
+        for day in 1..=12 {
+            journal.push_str(&format!(
+                "\n2020/01/{:02} * Paycheck {}\n    assets:checking  100\n    income:salary\n",
+                day, day
+            ));
+        }
+        let ledger = ledger_from_str(&journal);
+
+        let mut buffer = Vec::new();
+        ledger
+            .write_account_detail(
+                &mut buffer,
+                "assets:checking",
+                NaiveDate::from_ymd(2020, 1, 31),
+                ColorChoice::Never,
+            )
+            .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0], "balance");
+        assert_eq!(lines[1].trim(), "1200");
+        assert!(lines.contains(&"envelopes"));
+        assert!(output.contains("rent"));
+
+        // only the latest 10 entries are listed, but the running total counts them all
+        let register = &lines[lines.iter().position(|l| *l == "latest entries").unwrap() + 1..];
+        assert_eq!(register.len(), 10);
+        assert!(register[0].contains("Paycheck 3"));
+        assert!(register[9].contains("Paycheck 12"));
+        assert!(register[9].ends_with("1200"));
+
+        let err = ledger
+            .write_account_detail(
+                Vec::new(),
+                "assets:cheking",
+                NaiveDate::from_ymd(2020, 1, 31),
+                ColorChoice::Never,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("`assets:cheking` is not defined"));
+    }
+
+    #[test]
+    fn envelopes_json_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+    expense rent due every 15th
+        amount 300
+    goal vacation due 2020/06/01
+        amount 2000",
+        );
+
+        let mut buffer = Vec::new();
+        ledger
+            .write_envelopes_json(&mut buffer, NaiveDate::from_ymd(2020, 1, 10))
+            .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+
+        assert!(output.starts_with('[') && output.trim_end().ends_with(']'));
+        assert_eq!(output.matches("\"account\":\"assets:checking\"").count(), 2);
+        assert!(output.contains("\"name\":\"rent\""));
+        assert!(output.contains("\"due_date\":\"2020-01-15\",\"days_until_due\":5"));
+    }
+
+    #[test]
+    fn validate_dates_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+account expenses:food
+
+2024/02/20 * Groceries
+    assets:checking     -20
+    expenses:food
+
+2023/02/10 * Groceries
+    assets:checking     -30
+    expenses:food
+
+2024/02/10 * Groceries
+    assets:checking     -30
+    expenses:food
+
+2024/03/01 * Groceries
+    assets:checking     -25
+    expenses:food",
+        );
+
+        let today = NaiveDate::from_ymd(2024, 3, 15);
+        let warnings = ledger.validate_dates(today, DEFAULT_MAX_DATE_GAP_DAYS);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("the entry `2023/02/10 Groceries` is 365 or more days"));
+
+        // far future entries are flagged, too
+        let warnings = ledger.validate_dates(NaiveDate::from_ymd(2022, 1, 1), 365);
+        assert_eq!(warnings.len(), 4);
+        assert!(warnings.iter().all(|w| w.contains("in the future")));
+
+        // a bigger gap is allowed when asked for
+        assert!(ledger.validate_dates(today, 400).is_empty());
+    }
+
+    #[test]
+    fn flat_envelopes_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+    expense rent due every 15th
+        amount 300
+    goal rainy_day no date
+        amount 1000
+account assets:savings
+    goal vacation due 2020/06/01
+        amount 2000
+    expense insurance due every 12th
+        amount 50",
+        );
+
+        let mut buffer = Vec::new();
+        ledger
+            .write_flat_envelopes(&mut buffer, NaiveDate::from_ymd(2020, 1, 10))
+            .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+
+        // untracked envelopes aren't due, so they're left out
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("2020/01/12  assets:savings   insurance"));
+        assert!(lines[1].starts_with("2020/01/15  assets:checking  rent"));
+        assert!(lines[2].starts_with("2020/06/01  assets:savings   vacation"));
+    }
+
+    #[test]
+    fn new_entry_test() {
+        let path = std::env::temp_dir().join("silverfox_new_entry_test.sfox");
+        fs::write(
+            &path,
+            "account assets:checking\naccount expenses:food\naccount income:salary\n",
+        )
+        .unwrap();
+        let mut ledger = Ledger::from_file(&path, &LedgerOptions::default()).unwrap();
+        let date = NaiveDate::from_ymd(2020, 1, 2);
+        let postings = |p: &[&str]| p.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        ledger
+            .new_entry_and_save(
+                date,
+                EntryStatus::Cleared,
+                "Groceries",
+                Some("Corner store"),
+                &postings(&["expenses:food 20", "assets:checking"]),
+            )
+            .unwrap();
+
+        // postings that don't balance, or accounts that aren't defined, aren't written
+        assert!(ledger
+            .new_entry_and_save(
+                date,
+                EntryStatus::Cleared,
+                "Groceries",
+                None,
+                &postings(&["expenses:food 20", "assets:checking -10"]),
+            )
+            .is_err());
+        assert!(ledger
+            .new_entry_and_save(
+                date,
+                EntryStatus::Cleared,
+                "Groceries",
+                None,
+                &postings(&["expenses:snacks 20", "assets:checking"]),
+            )
+            .is_err());
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(written.contains("2020/01/02 ~ Groceries [Corner store]"));
+
+        let reparsed = ledger_from_str(&written);
+        assert_eq!(reparsed.entries.len(), 1);
+        assert_eq!(
+            reparsed.get_totals().unwrap()["assets:checking"]
+                .only(&None)
+                .mag,
+            -20.0
+        );
+    }
+
+    #[test]
+    fn rename_account_test() {
+        let journal = "default_balance_account expenses:food:snacks
+expense_accounts expenses:food expenses:foodstuffs
+
+account assets:checking
+    expense food due every 5th
+        amount 100
+        for expenses:food
+account expenses:food
+account expenses:food:snacks
+account expenses:foodstuffs
+
+2020/01/02 ~ Groceries
+    assets:checking          -50
+    expenses:food             40
+    expenses:food:snacks      10
+
+2020/01/03 ~ Not food
+    assets:checking          -5
+    expenses:foodstuffs       5";
+
+        let mut ledger = ledger_from_str(journal);
+        let count = match ledger.rename_account("expenses:food", "expenses:groceries") {
+            Ok(c) => c,
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(count, 1);
+
+        let output = ledger.as_parsable();
+        assert!(!output.contains("expenses:food\n"));
+        assert!(!output.contains("expenses:food:"));
+        assert!(output.contains("account expenses:groceries\n"));
+        assert!(output.contains("account expenses:groceries:snacks\n"));
+        assert!(output.contains("for expenses:groceries\n"));
+        assert!(output.contains("\n    expenses:groceries:snacks "));
+        assert!(output.contains("default_balance_account expenses:groceries:snacks\n"));
+        assert!(output.contains("expense_accounts expenses:groceries expenses:foodstuffs\n"));
+        assert!(ledger.is_expense_account("expenses:groceries:snacks"));
+
+        // only whole account names are matched
+        assert!(output.contains("account expenses:foodstuffs\n"));
+
+        // the renamed journal still parses
+        ledger_from_str(&output);
+
+        assert!(ledger
+            .rename_account("expenses:groceries", "expenses:foodstuffs")
+            .is_err());
+        assert!(ledger
+            .rename_account("expenses:nothing", "expenses:something")
+            .is_err());
+    }
+
+    #[test]
+    fn default_balance_account_test() {
+        let journal = "default_balance_account expenses:unknown
+
+account assets:checking
+account expenses:groceries
+account expenses:unknown
+
+2020/01/02 ~ Groceries
+    assets:checking     -50
+    expenses:groceries   45";
+
+        let ledger = ledger_from_str(journal);
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["expenses:unknown"].only(&None).mag, 5.0);
+        assert_eq!(ledger.unbalanced_entries().count(), 0);
+
+        // the balancing posting is kept when the journal is written back
+        assert!(ledger.as_parsable().contains("\n    expenses:unknown"));
+
+        // the account has to be defined
+        let mut ledger = Ledger::new();
+        assert!(ledger
+            .add_from_str(&journal.replace("account expenses:unknown\n", ""))
+            .is_err());
+    }
+
+    /// Tests that read or change environment variables can't run at the same time
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn import_multiple_csv_test() {
+        let _env_guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join("silverfox_import_multiple_csv_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let journal_path = dir.join("journal.sfox");
+        fs::write(
+            &journal_path,
+            "account assets:checking\naccount liabilities:credit_card\naccount expenses:unknown\naccount income:unknown\n",
+        )
+        .unwrap();
+
+        let checking_csv = dir.join("checking.csv");
+        fs::write(
+            &checking_csv,
+            "date,description,amount\n2020/01/02,Paycheck,1000\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("checking.csv.rules"),
+            "fields date, description, amount\naccount assets:checking\n",
+        )
+        .unwrap();
+
+        let card_csv = dir.join("card.csv");
+        fs::write(
+            &card_csv,
+            "date,description,amount\n2020/01/03,Groceries,-40\n2020/01/04,Gas,-25\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("card.csv.rules"),
+            "fields date, description, amount\naccount liabilities:credit_card\n",
+        )
+        .unwrap();
+
+        let mut ledger = Ledger::from_file(&journal_path, &LedgerOptions::default()).unwrap();
+        let result = ledger.import_csv(
+            &[checking_csv, card_csv],
+            None,
+            None,
+            &ImportOptions::default(),
+        );
+        let reread = Ledger::from_file(&journal_path, &LedgerOptions::default());
+        fs::remove_dir_all(&dir).unwrap();
+
+        if let Err(e) = result {
+            panic!("{}", e);
+        }
+
+        // each file used its own rules, and all of the entries made it to the journal
+        let dates = ledger
+            .entries
+            .iter()
+            .map(|e| e.get_date().format("%Y/%m/%d").to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(dates, vec!["2020/01/02", "2020/01/03", "2020/01/04"]);
+
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["assets:checking"].only(&None).mag, 1000.0);
+        assert_eq!(totals["liabilities:credit_card"].only(&None).mag, -65.0);
+        assert_eq!(reread.unwrap().entries.len(), 3);
+    }
+
+    #[test]
+    fn lint_envelopes_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+    expense rent due every 1st
+        amount 1500
+    expense internet due every 1st
+        amount 60
+
+account income:salary
+
+2020/01/01 * Paycheck
+    assets:checking  800
+    income:salary
+
+2020/02/01 * Paycheck
+    assets:checking  800
+    income:salary
+
+2020/03/01 * Paycheck
+    assets:checking  800
+    income:salary
+",
+        );
+
+        // about $1200 comes in a month, which isn't enough for rent
+        let warnings = ledger.lint_envelopes();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].contains("`rent`"));
+        assert!(warnings[0].contains("`assets:checking`"));
+    }
+
+    #[test]
+    fn lint_envelopes_blank_posting_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+    expense internet due every 1st
+        amount 60
+
+account income:salary
+
+2020/01/01 * Paycheck
+    income:salary    -800
+    assets:checking
+
+2020/02/01 * Paycheck
+    income:salary    -800
+    assets:checking
+",
+        );
+
+        // the paychecks land on blank postings, but they still count as inflow
+        let warnings = ledger.lint_envelopes();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn import_duplicates_test() {
+        let dir = std::env::temp_dir().join("silverfox_import_duplicates_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let journal_path = dir.join("journal.sfox");
+        fs::write(
+            &journal_path,
+            "account assets:checking\naccount expenses:unknown\naccount income:unknown\n",
+        )
+        .unwrap();
+
+        let rules_path = dir.join("checking.rules");
+        fs::write(
+            &rules_path,
+            "fields date, description, amount\naccount assets:checking\n",
+        )
+        .unwrap();
+
+        let first_csv = dir.join("first.csv");
+        fs::write(
+            &first_csv,
+            "date,description,amount\n2020/01/02,Paycheck,1000\n2020/01/03,Coffee,-3\n2020/01/03,Coffee,-3\n",
+        )
+        .unwrap();
+
+        // overlaps with the first file, but has a third coffee
+        let second_csv = dir.join("second.csv");
+        fs::write(
+            &second_csv,
+            "date,description,amount\n2020/01/03,Coffee,-3.001\n2020/01/03,Coffee,-3\n2020/01/03,Coffee,-3\n2020/01/02,Paycheck,1000\n",
+        )
+        .unwrap();
+
+        let mut ledger = Ledger::from_file(&journal_path, &LedgerOptions::default()).unwrap();
+        let first_csv = [first_csv];
+        let options = ImportOptions::default();
+        let first = ledger.import_csv(&first_csv, Some(&rules_path), None, &options);
+        let first_count = ledger.entries.len();
+        let second = ledger.import_csv(&[second_csv], Some(&rules_path), None, &options);
+        let second_count = ledger.entries.len();
+        let options = ImportOptions {
+            allow_duplicates: true,
+            ..ImportOptions::default()
+        };
+        let third = ledger.import_csv(&first_csv, Some(&rules_path), None, &options);
+        let third_count = ledger.entries.len();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // identical records in the same file are all imported
+        assert_eq!(first.unwrap(), 0);
+        assert_eq!(first_count, 3);
+
+        // records that were already imported are skipped, even if they're off by a rounding error
+        assert_eq!(second.unwrap(), 3);
+        assert_eq!(second_count, 4);
+
+        assert_eq!(third.unwrap(), 0);
+        assert_eq!(third_count, 7);
+    }
+
+    #[test]
+    fn import_dry_run_test() {
+        let dir = std::env::temp_dir().join("silverfox_import_dry_run_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let journal = "account assets:checking\naccount expenses:food\n";
+        let journal_path = dir.join("journal.sfox");
+        fs::write(&journal_path, journal).unwrap();
+
+        let rules_path = dir.join("checking.rules");
+        fs::write(
+            &rules_path,
+            "fields date, description, amount\naccount assets:checking\naccount2 expenses:food\n",
+        )
+        .unwrap();
+
+        let csv_path = dir.join("checking.csv");
+        fs::write(
+            &csv_path,
+            "date,description,amount\n2020/01/02,Groceries,-40\n",
+        )
+        .unwrap();
+
+        let mut ledger = Ledger::from_file(&journal_path, &LedgerOptions::default()).unwrap();
+        let options = ImportOptions {
+            dry_run: true,
+            ..ImportOptions::default()
+        };
+        let result = ledger.import_csv(&[csv_path], Some(&rules_path), None, &options);
+        let written = fs::read_to_string(&journal_path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        if let Err(e) = result {
+            panic!("{}", e);
+        }
+
+        // the entry is in the ledger, but not in the journal
+        assert_eq!(ledger.entries.len(), 1);
+        assert_eq!(ledger.entries[0].get_description(), "Groceries");
+        assert_eq!(written, journal);
+    }
+
+    #[test]
+    fn env_rules_test() {
+        let _env_guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join("silverfox_env_rules_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let journal_path = dir.join("journal.sfox");
+        fs::write(
+            &journal_path,
+            "account assets:checking\naccount expenses:food\n",
+        )
+        .unwrap();
+
+        // there's no rules file next to this one
+        let csv_path = dir.join("bank.csv");
+        fs::write(
+            &csv_path,
+            "date,description,amount\n2020/01/02,Groceries,-40\n",
+        )
+        .unwrap();
+
+        let rules_path = dir.join("default.rules");
+        fs::write(
+            &rules_path,
+            "fields date, description, amount\naccount assets:checking\naccount2 expenses:food\n",
+        )
+        .unwrap();
+
+        let mut ledger = Ledger::from_file(&journal_path, &LedgerOptions::default()).unwrap();
+        std::env::set_var("SILVERFOX_RULES", &rules_path);
+        let result = ledger.import_csv(&[csv_path], None, None, &ImportOptions::default());
+        std::env::remove_var("SILVERFOX_RULES");
+        fs::remove_dir_all(&dir).unwrap();
+
+        if let Err(e) = result {
+            panic!("{}", e);
+        }
+
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["assets:checking"].only(&None).mag, -40.0);
+        assert_eq!(totals["expenses:food"].only(&None).mag, 40.0);
+    }
+
+    #[test]
+    fn parse_lenient_test() {
+        let journal = "account assets:checking
+account expenses:groceries
+
+2020/01/02 ~ Groceries
+    assets:checking     -50
+    expenses:food        50
+
+2020/01/03 ~ Groceries
+    assets:checking     -20
+    expenses:groceries   20
+
+2020/01/04 x Groceries
+    assets:checking     -30
+    expenses:groceries   30";
+
+        let (ledger, errors) = Ledger::parse_lenient(journal);
+
+        assert_eq!(errors.len(), 2, "both bad entries should be reported");
+        assert_eq!(ledger.accounts.len(), 2);
+        assert_eq!(
+            ledger.entries.len(),
+            1,
+            "the good entry should still be loaded"
+        );
+        assert_eq!(
+            ledger.entries[0].get_date(),
+            &NaiveDate::from_ymd(2020, 1, 3)
+        );
+    }
+
+    #[test]
+    fn cross_account_envelope_transfer_test() {
+        // 100 moves from checking to savings, but only 60 of it comes out of the food envelope.
+        // the envelope postings don't cancel each other out, so they can't be included when
+        // inferring the blank amount
+        let journal = "account assets:checking
+    expense food due every 1st
+        amount 300
+account assets:savings
+    goal vacation due every year starting 2020/06/01
+        amount 1000
+
+2020/01/05 ~ Saving for vacation
+    assets:checking                     -100
+    assets:savings
+    envelope assets:checking food        -60
+    envelope assets:savings vacation     100";
+
+        let ledger = ledger_from_str(journal);
+
+        let totals = ledger.get_totals().unwrap();
+        assert_eq!(totals["assets:savings"].only(&None).mag, 100.0);
+
+        let checking = &ledger.accounts["assets:checking"];
+        let food = checking.get_envelope("food").unwrap();
+        assert_eq!(
+            food.get_now_amount().mag + food.get_next_amount().mag,
+            -60.0
+        );
+        assert_eq!(checking.get_available_value().only(&None).mag, -40.0);
+
+        let savings = &ledger.accounts["assets:savings"];
+        let vacation = savings.get_envelope("vacation").unwrap();
+        assert_eq!(
+            vacation.get_now_amount().mag + vacation.get_next_amount().mag,
+            100.0
+        );
+        assert_eq!(savings.get_available_value().only(&None).mag, 0.0);
+    }
+
+    #[test]
+    fn as_parsable_test() {
+        let ledger = ledger_from_str(LEDGER_STR);
+        let reparsed = ledger_from_str(&ledger.as_parsable());
+
+        assert_eq!(format!("{:?}", ledger), format!("{:?}", reparsed));
+    }
+
+    #[test]
+    fn display_round_trip_test() {
+        let ledger = ledger_from_str(
+            "currency $
+income_accounts income revenue
+default_balance_account expenses:unknown
+
+account assets:checking
+    buffer $100
+    expense rent due every 5th
+        amount $300
+        funding aggressive
+account assets:wallet
+account expenses:food
+account expenses:household
+account expenses:unknown
+account income:salary
+
+2020/01/15 * Groceries and supplies [Corner store]
+    assets:checking     $-90
+    expenses:food       weight 2
+    expenses:household  weight 1
+
+2020/01/01 * Paycheck
+    assets:checking     $1000
+    income:salary
+
+2020/01/02 ~ Cash
+    assets:wallet       50 EUR @ $1.10
+    assets:checking     $-55
+
+2020/01/03 * Rent savings
+    envelope assets:checking rent    $200
+
+2020/01/20 * Snacks
+    assets:checking     $-10",
+        );
+        let serialized = ledger.to_string();
+        let reparsed = ledger_from_str(&serialized);
+
+        // entries come out in date order
+        let paycheck = serialized.find("Paycheck").unwrap();
+        let groceries = serialized.find("Groceries and supplies").unwrap();
+        assert!(paycheck < groceries);
+
+        let sorted_totals = |l: &Ledger| {
+            let mut totals = l
+                .get_totals()
+                .unwrap()
+                .into_iter()
+                .map(|(account, pool)| (account, format!("{:?}", pool)))
+                .collect::<Vec<_>>();
+            totals.sort();
+            totals
+        };
+        assert_eq!(sorted_totals(&ledger), sorted_totals(&reparsed));
+        assert_eq!(
+            ledger.summary_line().unwrap(),
+            reparsed.summary_line().unwrap()
+        );
+
+        let rent = |l: &Ledger| {
+            let envelope = l.accounts["assets:checking"].get_envelope("rent").unwrap();
+            envelope.get_now_amount().mag + envelope.get_next_amount().mag
+        };
+        assert_eq!(rent(&ledger), 200.0);
+        assert_eq!(rent(&reparsed), 200.0);
+
+        // serializing again doesn't change anything
+        assert_eq!(reparsed.to_string(), serialized);
+    }
+
+    #[test]
+    fn budget_export_test() {
+        let ledger = ledger_from_str(
+            "currency USD
+
+account assets:checking
+    expense rent due every 1st
+        amount 1200
+        for expenses:rent
+    expense car_insurance due every other 1st starting 2024/01/01
+        amount 100
+    goal vacation due 2024/12/01
+        amount 2000
+account expenses:rent
+account income:job
+
+2024/01/02 * Paycheck
+    assets:checking     3000
+    income:job
+
+2024/01/03 * Saving for rent
+    envelope assets:checking rent 300",
+        );
+
+        let export = |format| {
+            let mut buffer = Vec::new();
+            ledger
+                .write_budget_export(&mut buffer, format, NaiveDate::from_ymd(2024, 1, 5))
+                .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+
+        let beancount = export(BudgetFormat::Beancount);
+        assert_eq!(
+            beancount,
+            r#"2024-01-01 custom "budget" Assets:Checking:Car-insurance "monthly" 50 USD
+  envelope: "car_insurance"
+  saved: 0 USD
+
+2024-01-05 custom "budget" Expenses:Rent "monthly" 1200 USD
+  envelope: "rent"
+  saved: 300 USD
+
+; `vacation` isn't exported because it doesn't repeat
+"#
+        );
+
+        // each directive is a date, `custom "budget"`, an account, a period, and an amount
+        for line in beancount.lines().filter(|l| l.contains("custom")) {
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            assert_eq!(tokens.len(), 7);
+            assert!(NaiveDate::parse_from_str(tokens[0], "%Y-%m-%d").is_ok());
+            assert_eq!(&tokens[1..3], &["custom", "\"budget\""]);
+            assert!(tokens[3]
+                .split(':')
+                .all(|c| c.starts_with(char::is_uppercase)));
+            assert!(tokens[4].starts_with('"') && tokens[4].ends_with('"'));
+            assert!(tokens[5].parse::<f64>().is_ok());
+        }
+
+        let ledger_export = export(BudgetFormat::Ledger);
+        assert!(ledger_export.contains("~ Monthly\n    expenses:rent"));
+        assert!(ledger_export.contains("~ Monthly from 2024/01/01\n"));
+
+        // beancount can't have accounts outside of its five roots
+        let odd = ledger_from_str(
+            "currency USD
+
+account savings
+    expense fees due every 1st
+        amount 5",
+        );
+        assert!(odd
+            .write_budget_export(
+                Vec::new(),
+                BudgetFormat::Beancount,
+                NaiveDate::from_ymd(2024, 1, 5)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn allocation_test() {
+        let ledger = ledger_from_str(
+            "account assets:checking
+    expense groceries due every 1st
+        amount 200
+account income:job
+
+2024/01/02 * Paycheck
+    assets:checking     100
+    income:job",
+        );
+        let date = NaiveDate::from_ymd(2024, 1, 5);
+
+        let (entry, warning) = ledger
+            .allocation_entry(date, "50", "assets:checking", "groceries")
+            .unwrap();
+        assert_eq!(warning, None);
+        assert_eq!(entry.get_date(), &date);
+        assert_eq!(entry.get_postings().len(), 1);
+        match &entry.get_postings()[0] {
+            Posting::Envelope(p) => {
+                assert_eq!(p.get_account_name(), "assets:checking");
+                assert_eq!(p.get_envelope_name(), "groceries");
+                assert_eq!(p.get_amount().mag, 50.0);
+            }
+            p => panic!("expected an envelope posting, got `{}`", p),
+        }
+        assert!(entry.is_balanced());
+
+        // more than the account has unassigned is allowed, but warned about
+        let (_, warning) = ledger
+            .allocation_entry(date, "150", "assets:checking", "groceries")
+            .unwrap();
+        assert!(warning.unwrap().contains("100"));
+
+        assert!(ledger
+            .allocation_entry(date, "50", "assets:checking", "rent")
+            .is_err());
+        assert!(ledger
+            .allocation_entry(date, "50", "assets:savings", "groceries")
+            .is_err());
+    }
+
+    #[test]
+    fn dry_fill_test() {
+        let path = std::env::temp_dir().join("silverfox_dry_fill_test.sfox");
+        let journal = "account assets:checking
+    expense rent due every 15th
+        amount 100
+        funding aggressive
+account income:salary
+
+2020/01/01 * Paycheck
+    assets:checking     300
+    income:salary
+";
+        fs::write(&path, journal).unwrap();
+        let mut ledger = Ledger::from_file(&path, &LedgerOptions::default()).unwrap();
+        let today = NaiveDate::from_ymd(2020, 1, 2);
+
+        let dry = ledger.dry_fill_envelopes(today).unwrap();
+        assert!(dry.starts_with("2020/01/02 ~ move to envelopes"));
+        assert_eq!(
+            dry.lines()
+                .nth(1)
+                .unwrap()
+                .split_whitespace()
+                .collect::<Vec<&str>>(),
+            ["envelope", "assets:checking", "rent", "100"]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), journal);
+
+        // the entry that's appended is exactly the one that was shown
+        ledger.fill_envelopes(today).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, format!("{}\n{}", journal, dry));
+
+        // once the envelopes are full, there's nothing to fill
+        assert_eq!(ledger.dry_fill_envelopes(today), None);
+    }
+
+    #[test]
+    fn min_fill_test() {
+        let journal = "account assets:checking
+    expense rent due every 15th
+        amount 100
+        funding aggressive
+    expense stamps due every 15th
+        amount 0.5
+        funding aggressive
+account income:salary
+
+2020/01/01 * Paycheck
+    assets:checking     300
+    income:salary
+";
+        let today = NaiveDate::from_ymd(2020, 1, 2);
+        let filled_envelopes = |journal: &str| {
+            let entry = ledger_from_str(journal).dry_fill_envelopes(today).unwrap();
+            entry
+                .lines()
+                .skip(1)
+                .filter_map(|l| l.split_whitespace().nth(2).map(String::from))
+                .collect::<Vec<String>>()
+        };
+
+        let mut all = filled_envelopes(journal);
+        all.sort();
+        assert_eq!(all, ["rent", "stamps"]);
+
+        // the stamps envelope only needs 0.5, so it isn't filled
+        let journal = format!("min_fill 1\n\n{}", journal);
+        assert_eq!(filled_envelopes(&journal), ["rent"]);
+
+        // the directive is kept when the journal is written out again
+        let written = ledger_from_str(&journal).as_parsable();
+        assert!(written.contains("min_fill 1\n"));
+        assert_eq!(filled_envelopes(&written), ["rent"]);
+    }
+
+    #[test]
+    fn same_date_order_test() {
+        let journal = "account assets:checking
+account expenses:food
+
+2020/01/02 * Lunch
+    assets:checking     -10
+    expenses:food
+
+2020/01/01 * Breakfast
+    assets:checking     -5
+    expenses:food
+
+2020/01/02 * Dinner
+    assets:checking     -20
+    expenses:food
+
+2020/01/01 * Brunch
+    assets:checking     -8
+    expenses:food
+";
+        let descriptions = |ledger: &Ledger| {
+            ledger
+                .entries
+                .iter()
+                .map(|e| e.get_description().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        // entries are sorted by date, but same-date entries keep their file order
+        let ledger = ledger_from_str(journal);
+        assert_eq!(
+            descriptions(&ledger),
+            ["Breakfast", "Brunch", "Lunch", "Dinner"]
+        );
+
+        // and that order survives being written out and read again
+        let reparsed = ledger_from_str(&ledger.as_parsable());
+        assert_eq!(descriptions(&reparsed), descriptions(&ledger));
+    }
 }
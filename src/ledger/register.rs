@@ -1,62 +1,154 @@
-use crate::{amount::AmountPool, entry::Entry, entry::EntryRegisterData, errors::SilverfoxError};
+use crate::{
+    account::AccountMatcher, amount::AmountPool, entry::Entry, entry::EntryRegisterData,
+    errors::SilverfoxError,
+};
 use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
 
 pub struct Register;
 
 impl Register {
+    /// Displays the register, either as plain columns or as a table with borders if `table` is
+    /// given. Only entries with the tag in `tag_match` are shown, if it's given (see
+    /// `matches_tag`).
     pub fn display(
         entries: &[Entry],
         date_format: &str,
         begin_date: Option<NaiveDate>,
         end_date: Option<NaiveDate>,
-        account_match: Option<String>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+        table: Option<TableStyle>,
     ) -> Result<(), SilverfoxError> {
-        let console_width = if let Some(s) = terminal_size::terminal_size() {
-            (s.0).0 as usize
-        } else {
-            return Err(SilverfoxError::Basic(String::from(
-                "couldn't figure out the width of your terminal. are you in a terminal?",
-            )));
-        };
+        let filtered = filter_entries(entries, begin_date, end_date, &account_match, &tag_match);
+
+        // a total is only meaningful when every amount is about the same account
+        let show_footer = account_match.is_some();
+
+        let mut register_data_vec = Vec::new();
 
-        // a "focused" account is the focus of the register. in other words, numbers displayed
-        // revolve around the focused account. if money flows into the account, it is displayed as
-        // a positive number on the register. if money flows out, it is displayed as a negative
-        // number.
-        let is_account_name_focused = |account_name: &str| match &account_match {
-            Some(match_str) => account_name.contains(match_str),
-            // TODO: an issue ticket is open to further solidify whether or not an account is an
-            // "asset", so this will be changed soon (it's kinda dumb right now)
-            None => account_name.starts_with("asset"),
+        let maximums = get_maximum_lengths(
+            &filtered,
+            date_format,
+            account_match,
+            &mut register_data_vec,
+        )?;
+
+        let result = match table {
+            // tables size themselves, so they don't need to know how wide the terminal is
+            Some(style) => register_table(&register_data_vec).write(io::stdout(), style),
+            None => {
+                let console_width = if let Some(s) = terminal_size::terminal_size() {
+                    (s.0).0 as usize
+                } else {
+                    return Err(SilverfoxError::Basic(String::from(
+                        "couldn't figure out the width of your terminal. are you in a terminal?",
+                    )));
+                };
+
+                write_lines(
+                    io::stdout(),
+                    &maximums,
+                    &register_data_vec,
+                    console_width,
+                    show_footer,
+                    0,
+                )
+            }
         };
 
-        let filtered: Vec<&Entry> = entries
+        result.map_err(|e| SilverfoxError::Basic(format!("couldn't display a register: {}", e)))
+    }
+
+    /// Displays the register as a JSON array with an object for each row (see
+    /// `EntryRegisterData::as_json`). Dates are written like `2020-01-31`, no matter the date
+    /// format of the journal.
+    pub fn display_json(
+        entries: &[Entry],
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+    ) -> Result<(), SilverfoxError> {
+        Self::write_json(
+            io::stdout(),
+            entries,
+            begin_date,
+            end_date,
+            account_match,
+            tag_match,
+        )
+    }
+
+    fn write_json<W: Write>(
+        mut writer: W,
+        entries: &[Entry],
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+    ) -> Result<(), SilverfoxError> {
+        let objects = json_rows(entries, begin_date, end_date, account_match, tag_match)?
             .iter()
-            .filter(|e| {
-                let has_focused_account = e
-                    .get_postings()
-                    .iter()
-                    .any(|p| is_account_name_focused(p.get_account()));
-
-                let date_in_range = match begin_date {
-                    Some(begin) => match end_date {
-                        Some(end) => e.get_date() <= &end && e.get_date() >= &begin,
-                        None => e.get_date() >= &begin,
-                    },
-                    None => match end_date {
-                        Some(end) => e.get_date() <= &end,
-                        None => true,
-                    },
-                };
+            .map(|rd| rd.as_json())
+            .collect::<Vec<String>>();
 
-                // entries must have at least one focused account and be within the range between the
-                // start date and end date (both inclusive)
-                has_focused_account && date_in_range
-            })
-            .collect();
+        writeln!(writer, "[{}]", objects.join(",\n"))
+            .map_err(|e| SilverfoxError::Basic(format!("couldn't display a register: {}", e)))
+    }
 
-        let mut register_data_vec = Vec::new();
+    /// Displays the register as JSON lines: the same objects as `display_json`, one per line,
+    /// without an array around them. Each line can be read on its own, so the output can be
+    /// piped to tools like `jq` as it's written.
+    pub fn display_json_lines(
+        entries: &[Entry],
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+    ) -> Result<(), SilverfoxError> {
+        Self::write_json_lines(
+            io::stdout().lock(),
+            entries,
+            begin_date,
+            end_date,
+            account_match,
+            tag_match,
+        )
+    }
+
+    fn write_json_lines<W: Write>(
+        mut writer: W,
+        entries: &[Entry],
+        begin_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+        account_match: Option<AccountMatcher>,
+        tag_match: Option<(String, Option<String>)>,
+    ) -> Result<(), SilverfoxError> {
+        for rd in json_rows(entries, begin_date, end_date, account_match, tag_match)? {
+            writeln!(writer, "{}", rd.as_json()).map_err(|e| {
+                SilverfoxError::Basic(format!("couldn't display a register: {}", e))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last `count` rows of the register for the accounts matching `account_match`,
+    /// with entries up to `end_date`. Running totals still count every entry before those rows.
+    pub fn write_latest<W: Write>(
+        writer: W,
+        entries: &[Entry],
+        date_format: &str,
+        end_date: Option<NaiveDate>,
+        account_match: AccountMatcher,
+        count: usize,
+    ) -> Result<(), SilverfoxError> {
+        let account_match = Some(account_match);
+        let filtered = filter_entries(entries, None, end_date, &account_match, &None);
 
+        let mut register_data_vec = Vec::new();
         let maximums = get_maximum_lengths(
             &filtered,
             date_format,
@@ -64,12 +156,264 @@ impl Register {
             &mut register_data_vec,
         )?;
 
-        print_lines(&maximums, &register_data_vec, console_width);
+        write_lines(
+            writer,
+            &maximums,
+            &register_data_vec,
+            0,
+            false,
+            register_data_vec.len().saturating_sub(count),
+        )
+        .map_err(|e| SilverfoxError::Basic(format!("couldn't display a register: {}", e)))
+    }
+}
 
-        Ok(())
+/// Returns the entries with at least one focused account (see below), dated from `begin_date` to
+/// `end_date` (both inclusive), that match `tag_match` if it's given
+fn filter_entries<'a>(
+    entries: &'a [Entry],
+    begin_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    account_match: &Option<AccountMatcher>,
+    tag_match: &Option<(String, Option<String>)>,
+) -> Vec<&'a Entry> {
+    // a "focused" account is the focus of the register. in other words, numbers displayed
+    // revolve around the focused account. if money flows into the account, it is displayed as
+    // a positive number on the register. if money flows out, it is displayed as a negative
+    // number.
+    let is_account_name_focused = |account_name: &str| match account_match {
+        Some(matcher) => matcher.matches(account_name),
+        // TODO: an issue ticket is open to further solidify whether or not an account is an
+        // "asset", so this will be changed soon (it's kinda dumb right now)
+        None => account_name.starts_with("asset"),
+    };
+
+    entries
+        .iter()
+        .filter(|e| {
+            let has_focused_account = e
+                .get_postings()
+                .iter()
+                .any(|p| is_account_name_focused(p.get_account()));
+
+            let date_in_range = match begin_date {
+                Some(begin) => match end_date {
+                    Some(end) => e.get_date() <= &end && e.get_date() >= &begin,
+                    None => e.get_date() >= &begin,
+                },
+                None => match end_date {
+                    Some(end) => e.get_date() <= &end,
+                    None => true,
+                },
+            };
+
+            let has_tag = match tag_match {
+                Some(t) => matches_tag(e, t),
+                None => true,
+            };
+
+            // entries must have at least one focused account and be within the range between the
+            // start date and end date (both inclusive)
+            has_focused_account && date_in_range && has_tag
+        })
+        .collect()
+}
+
+/// Returns true if any of the entry's postings has the tag in `tag_match`, either of its own or
+/// from the entry. A key with a value matches metadata with exactly that value (`category:
+/// groceries`); a key alone matches a tag (`:reviewed:`) or metadata with any value.
+fn matches_tag(entry: &Entry, (key, value): &(String, Option<String>)) -> bool {
+    let matches = |metadata: HashMap<String, String>, tags: HashSet<String>| match value {
+        Some(v) => metadata.get(key) == Some(v),
+        None => tags.contains(key) || metadata.contains_key(key),
+    };
+
+    entry
+        .get_postings()
+        .iter()
+        .any(|p| matches(p.effective_metadata(entry), p.effective_tags(entry)))
+}
+
+/// The characters used to draw a table's borders
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableStyle {
+    /// Box-drawing characters
+    Unicode,
+
+    /// Plain ASCII, for terminals that can't show box-drawing characters
+    Ascii,
+}
+
+impl TableStyle {
+    /// Returns the characters for the left edge, the column separator, the right edge, and the
+    /// horizontal line of a border. `position` is 0 for the top border, 1 for the border under
+    /// the headers, and 2 for the bottom border.
+    fn border_chars(self, position: usize) -> (char, char, char, char) {
+        match self {
+            Self::Unicode => match position {
+                0 => ('┌', '┬', '┐', '─'),
+                1 => ('├', '┼', '┤', '─'),
+                _ => ('└', '┴', '┘', '─'),
+            },
+            Self::Ascii => match position {
+                1 => ('+', '+', '+', '='),
+                _ => ('+', '+', '+', '-'),
+            },
+        }
+    }
+
+    /// Returns the character that separates cells in a row
+    fn vertical(self) -> char {
+        match self {
+            Self::Unicode => '│',
+            Self::Ascii => '|',
+        }
+    }
+}
+
+/// A table with a row of headers, where each column is as wide as its widest cell
+struct Table {
+    headers: Vec<String>,
+    right_aligned: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Returns a table with no rows. Each header is given with whether its column is aligned to
+    /// the right, like numbers are.
+    fn new(headers: &[(&str, bool)]) -> Self {
+        Self {
+            headers: headers.iter().map(|(h, _)| h.to_string()).collect(),
+            right_aligned: headers.iter().map(|(_, r)| *r).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row to the table. Missing cells are left blank.
+    fn add_row(&mut self, mut row: Vec<String>) {
+        row.resize(self.headers.len(), String::new());
+        self.rows.push(row);
+    }
+
+    /// Returns the width of each column, in characters
+    fn column_widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        widths
+    }
+
+    fn write<W: Write>(&self, mut writer: W, style: TableStyle) -> io::Result<()> {
+        let widths = self.column_widths();
+
+        let border = |position: usize| {
+            let (left, middle, right, line) = style.border_chars(position);
+            let lines = widths
+                .iter()
+                .map(|w| line.to_string().repeat(w + 2))
+                .collect::<Vec<String>>();
+            format!("{}{}{}", left, lines.join(&middle.to_string()), right)
+        };
+
+        let row_line = |row: &[String], right_aligned: &[bool]| {
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .zip(right_aligned)
+                .map(|((cell, width), right)| {
+                    // pad by characters, since box-drawing characters and the like take more
+                    // than one byte
+                    let padding = " ".repeat(width - cell.chars().count());
+                    if *right {
+                        format!(" {}{} ", padding, cell)
+                    } else {
+                        format!(" {}{} ", cell, padding)
+                    }
+                })
+                .collect::<Vec<String>>();
+            let vertical = style.vertical().to_string();
+            format!("{}{}{}", vertical, cells.join(&vertical), vertical)
+        };
+
+        writeln!(writer, "{}", border(0))?;
+        writeln!(
+            writer,
+            "{}",
+            row_line(&self.headers, &vec![false; self.headers.len()])
+        )?;
+        writeln!(writer, "{}", border(1))?;
+        for row in &self.rows {
+            writeln!(writer, "{}", row_line(row, &self.right_aligned))?;
+        }
+        writeln!(writer, "{}", border(2))
     }
 }
 
+/// Returns the rows of the register for JSON output, with dates written like `2020-01-31`
+fn json_rows(
+    entries: &[Entry],
+    begin_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    account_match: Option<AccountMatcher>,
+    tag_match: Option<(String, Option<String>)>,
+) -> Result<Vec<EntryRegisterData>, SilverfoxError> {
+    let filtered = filter_entries(entries, begin_date, end_date, &account_match, &tag_match);
+
+    let mut register_data_vec = Vec::new();
+    get_maximum_lengths(&filtered, "%Y-%m-%d", account_match, &mut register_data_vec)?;
+
+    Ok(register_data_vec)
+}
+
+/// Returns a table of the register, with one row for each amount of each entry and a running
+/// total in the last column
+fn register_table(register_data: &[EntryRegisterData]) -> Table {
+    let mut table = Table::new(&[
+        ("date", false),
+        ("status", false),
+        ("description", false),
+        ("from", false),
+        ("to", false),
+        ("amount", true),
+        ("total", true),
+    ]);
+
+    for rd in register_data {
+        for (i, amount) in rd.amounts.iter().enumerate() {
+            let amount_cells = vec![
+                amount.to_string().trim_start().to_string(),
+                rd.running_balance
+                    .only(&amount.symbol)
+                    .to_string()
+                    .trim_start()
+                    .to_string(),
+            ];
+
+            // only the first row of an entry says what the entry is
+            let mut row = if i == 0 {
+                vec![
+                    rd.date.clone(),
+                    rd.status.to_string(),
+                    rd.description.clone(),
+                    rd.account_flow.0.clone(),
+                    rd.account_flow.1.clone(),
+                ]
+            } else {
+                vec![String::new(); 5]
+            };
+            row.extend(amount_cells);
+
+            table.add_row(row);
+        }
+    }
+
+    table
+}
+
 #[derive(Default)]
 struct MaximumLens {
     date: usize,
@@ -86,7 +430,7 @@ struct MaximumLens {
 fn get_maximum_lengths(
     filtered_entries: &[&Entry],
     date_format: &str,
-    account_match: Option<String>,
+    account_match: Option<AccountMatcher>,
     register_data_vec: &mut Vec<EntryRegisterData>,
 ) -> Result<MaximumLens, SilverfoxError> {
     let mut m = MaximumLens::default();
@@ -94,7 +438,7 @@ fn get_maximum_lengths(
     let mut running_total = AmountPool::new();
 
     for entry in filtered_entries {
-        let reg_data = match entry.as_register_data(date_format, &account_match) {
+        let mut reg_data = match entry.as_register_data(date_format, &account_match) {
             Ok(o) => {
                 if let Some(r) = o {
                     if !r.amounts.is_empty() {
@@ -132,7 +476,10 @@ fn get_maximum_lengths(
                 .unwrap(),
         );
 
+        // the running balance starts from zero at the first entry in the register, so entries
+        // filtered out before `begin_date` don't count towards it
         running_total += &reg_data.amounts;
+        reg_data.running_balance = running_total.clone();
         m.running_total = m.running_total.max(
             running_total
                 .iter()
@@ -147,12 +494,35 @@ fn get_maximum_lengths(
     Ok(m)
 }
 
-fn print_lines(maximums: &MaximumLens, register_data: &[EntryRegisterData], _console_width: usize) {
-    let mut running_total = AmountPool::new();
+/// Writes a line for each entry in the register, ending with its running balance. If
+/// `show_footer` is true, the total of all amounts is written at the bottom, one currency per
+/// line. The first `skip` entries aren't written, but they still count towards the running
+/// balance.
+fn write_lines<W: Write>(
+    mut writer: W,
+    maximums: &MaximumLens,
+    register_data: &[EntryRegisterData],
+    _console_width: usize,
+    show_footer: bool,
+    skip: usize,
+) -> io::Result<()> {
+    let mut prelude_len = 0;
 
-    for rd in register_data {
-        running_total += &rd.amounts;
+    // the last running balance is the total of everything
+    let total = register_data
+        .last()
+        .map(|rd| rd.running_balance.clone())
+        .unwrap_or_default();
+
+    // make room for the totals in the amount column, if they'll be shown
+    let mut amount_len = maximums.amount;
+    if show_footer {
+        for amount in total.iter() {
+            amount_len = amount_len.max(format!("{}", amount).len());
+        }
+    }
 
+    for rd in register_data.iter().skip(skip) {
         let mut amount_iter = rd.amounts.iter();
 
         if let Some(first_amount) = amount_iter.next() {
@@ -170,30 +540,383 @@ fn print_lines(maximums: &MaximumLens, register_data: &[EntryRegisterData], _con
             );
 
             // TODO: Have Amount::display handle formatting arguments
-            print!("{}", prelude);
-            println!(
+            write!(writer, "{}", prelude)?;
+            writeln!(
+                writer,
                 "{:>amount_len$}  {:>running_total_len$}",
                 format!("{}", first_amount),
-                format!("{}", running_total.only(&first_amount.symbol)),
-                amount_len = maximums.amount,
+                format!("{}", rd.running_balance.only(&first_amount.symbol)),
+                amount_len = amount_len,
                 running_total_len = maximums.running_total,
-            );
+            )?;
 
-            let prelude_space = spaces(prelude.len());
+            prelude_len = prelude.chars().count();
+            let prelude_space = spaces(prelude_len);
             for amount in amount_iter {
-                println!(
+                writeln!(
+                    writer,
                     "{}{:>amount_len$}  {:>running_total_len$}",
                     prelude_space,
                     format!("{}", amount),
-                    format!("{}", running_total.only(&amount.symbol)),
-                    amount_len = maximums.amount,
+                    format!("{}", rd.running_balance.only(&amount.symbol)),
+                    amount_len = amount_len,
                     running_total_len = maximums.running_total,
-                );
+                )?;
             }
         }
     }
+
+    if show_footer && !total.is_empty() {
+        writeln!(writer, "{}{}", spaces(prelude_len), "-".repeat(amount_len))?;
+
+        // the label goes at the end of the prelude, so the totals line up with the amounts
+        let mut label = "total";
+        for amount in total.iter() {
+            writeln!(
+                writer,
+                "{:>label_len$}  {:>amount_len$}",
+                label,
+                format!("{}", amount),
+                label_len = prelude_len.saturating_sub(2),
+                amount_len = amount_len,
+            )?;
+            label = "";
+        }
+    }
+
+    Ok(())
 }
 
 fn spaces(n: usize) -> String {
     " ".repeat(n)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn footer_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * Groceries\n    assets:checking  -50\n    expenses:groceries  50",
+            "2020/01/03 * Refund\n    assets:checking  20\n    expenses:groceries  -20",
+            "2020/01/04 * Groceries\n    assets:checking  -5.5\n    expenses:groceries  5.5",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+        let entry_refs = entries.iter().collect::<Vec<&Entry>>();
+
+        let mut register_data = Vec::new();
+        let maximums = get_maximum_lengths(
+            &entry_refs,
+            "%Y/%m/%d",
+            Some(AccountMatcher::Substring(checking.clone())),
+            &mut register_data,
+        )
+        .unwrap();
+
+        let mut buffer = Vec::new();
+        write_lines(&mut buffer, &maximums, &register_data, 80, true, 0).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+
+        // three rows, a rule, and one total
+        assert_eq!(lines.len(), 5);
+        let total_line = lines[4];
+        assert!(total_line.trim_start().starts_with("total"));
+        assert!(total_line.ends_with("-35.5"));
+
+        // the total lines up with the amounts above it
+        assert_eq!(
+            total_line.len(),
+            lines[2].len() - maximums.running_total - 2
+        );
+
+        let mut buffer = Vec::new();
+        write_lines(&mut buffer, &maximums, &register_data, 80, false, 0).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn running_balance_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * Groceries\n    assets:checking  -50\n    expenses:groceries  50",
+            "2020/01/03 * Refund\n    assets:checking  20\n    expenses:groceries  -20",
+            "2020/01/04 * Groceries\n    assets:checking  -5.5\n    expenses:groceries  5.5",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+        let matcher = Some(AccountMatcher::Substring(checking.clone()));
+
+        let running_balances = |begin_date: Option<NaiveDate>| {
+            let filtered = filter_entries(&entries, begin_date, None, &matcher, &None);
+            let mut register_data = Vec::new();
+            get_maximum_lengths(&filtered, "%Y/%m/%d", matcher.clone(), &mut register_data)
+                .unwrap();
+            register_data
+                .iter()
+                .map(|rd| rd.running_balance.only(&None).mag)
+                .collect::<Vec<f64>>()
+        };
+
+        assert_eq!(running_balances(None), vec![-50.0, -30.0, -35.5]);
+
+        // the balance starts from zero at the beginning date
+        assert_eq!(
+            running_balances(Some(NaiveDate::from_ymd(2020, 1, 3))),
+            vec![20.0, 14.5]
+        );
+    }
+
+    #[test]
+    fn json_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * Groceries [Corner \"store\"]\n    assets:checking  $-50\n    expenses:groceries",
+            "2020/01/03 ~ Refund\n    assets:checking  20\n    expenses:groceries  -20",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+
+        let mut buffer = Vec::new();
+        Register::write_json(
+            &mut buffer,
+            &entries,
+            None,
+            None,
+            Some(AccountMatcher::Substring(checking.clone())),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            r#"[{"date":"2020-01-02","status":"*","description":"Groceries","payee":"Corner \"store\"","account_flow":{"from":"assets:checking","to":"expenses:groceries"},"amounts":[{"symbol":"$","mag":-50}],"running_balance":[{"symbol":"$","mag":-50}]},
+{"date":"2020-01-03","status":"~","description":"Refund","payee":null,"account_flow":{"from":"expenses:groceries","to":"assets:checking"},"amounts":[{"symbol":null,"mag":20}],"running_balance":[{"symbol":"$","mag":-50},{"symbol":null,"mag":20}]}]
+"#
+        );
+    }
+
+    #[test]
+    fn json_lines_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * Groceries [Corner \"{store}\"]\n    assets:checking  $-50\n    expenses:groceries",
+            "2020/01/03 ~ Refund\n    assets:checking  20\n    expenses:groceries  -20",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+
+        let write = |lines: bool| {
+            let mut buffer = Vec::new();
+            let account_match = Some(AccountMatcher::Substring(checking.clone()));
+            if lines {
+                Register::write_json_lines(&mut buffer, &entries, None, None, account_match, None)
+            } else {
+                Register::write_json(&mut buffer, &entries, None, None, account_match, None)
+            }
+            .unwrap();
+
+            String::from_utf8(buffer).unwrap()
+        };
+
+        // each line is a whole JSON object: its brackets (outside of strings) close on the line
+        let is_json_object = |line: &str| {
+            let mut depth = 0;
+            let mut in_string = false;
+            let mut escaped = false;
+            for (i, c) in line.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if in_string => escaped = true,
+                    '"' => in_string = !in_string,
+                    '{' | '[' if !in_string => depth += 1,
+                    '}' | ']' if !in_string => {
+                        depth -= 1;
+                        if depth == 0 && i != line.len() - 1 {
+                            return false;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            line.starts_with('{') && depth == 0 && !in_string
+        };
+
+        let json_lines = write(true);
+        let lines = json_lines.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| is_json_object(l)), "{}", json_lines);
+
+        // the objects are the same ones in the JSON array
+        let json = write(false);
+        let array = json
+            .trim_end()
+            .strip_prefix('[')
+            .unwrap()
+            .strip_suffix(']')
+            .unwrap();
+        assert_eq!(array, lines.join(",\n"));
+    }
+
+    #[test]
+    fn account_regex_test() {
+        let cash = String::from("assets:cash");
+        let checking = String::from("assets:checking");
+        let cash_back = String::from("income:cash-back");
+        let accounts: HashSet<&String> = [&cash, &checking, &cash_back].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * ATM\n    assets:cash  20\n    assets:checking  -20",
+            "2020/01/03 * Rewards\n    assets:checking  5\n    income:cash-back  -5",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+        let entry_refs = entries.iter().collect::<Vec<&Entry>>();
+
+        let row_count = |matcher: AccountMatcher| {
+            let mut register_data = Vec::new();
+            get_maximum_lengths(&entry_refs, "%Y/%m/%d", Some(matcher), &mut register_data)
+                .unwrap();
+            register_data.len()
+        };
+
+        // a substring matches the cash back account too
+        assert_eq!(
+            row_count(AccountMatcher::Substring(String::from("cash"))),
+            2
+        );
+
+        // an anchored regex only matches the cash account
+        assert_eq!(
+            row_count(AccountMatcher::regex("^assets:cash$").unwrap()),
+            1
+        );
+
+        assert!(AccountMatcher::regex("assets:(cash").is_err());
+    }
+
+    #[test]
+    fn table_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * Groceries\n    assets:checking  -50\n    expenses:groceries  50",
+            "2020/01/03 * Refund\n    assets:checking  20\n    expenses:groceries  -20",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+        let entry_refs = entries.iter().collect::<Vec<&Entry>>();
+
+        let mut register_data = Vec::new();
+        get_maximum_lengths(
+            &entry_refs,
+            "%Y/%m/%d",
+            Some(AccountMatcher::Substring(checking.clone())),
+            &mut register_data,
+        )
+        .unwrap();
+
+        let table = register_table(&register_data);
+        let widths = table.column_widths();
+
+        let mut buffer = Vec::new();
+        table.write(&mut buffer, TableStyle::Unicode).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+
+        // top border, headers, separator, two rows, bottom border
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with('┌') && lines[0].ends_with('┐'));
+        assert!(lines[2].starts_with('├'));
+        assert!(lines[5].starts_with('└'));
+
+        // every line is as wide as the others, and each cell is padded to its column's width
+        let width = lines[0].chars().count();
+        assert!(lines.iter().all(|l| l.chars().count() == width));
+
+        let header_cells = lines[1].split('│').collect::<Vec<&str>>();
+        assert_eq!(header_cells[1].trim(), "date");
+        assert_eq!(header_cells[7].trim(), "total");
+
+        let row_cells = lines[3].split('│').collect::<Vec<&str>>();
+        assert_eq!(row_cells[1], " 2020/01/02 ");
+        assert_eq!(row_cells[3].trim(), "Groceries");
+        assert_eq!(row_cells[6].trim(), "-50");
+        for (cell, width) in row_cells[1..8].iter().zip(&widths) {
+            assert_eq!(cell.chars().count(), width + 2);
+        }
+
+        // the ascii style has the same layout
+        let mut buffer = Vec::new();
+        table.write(&mut buffer, TableStyle::Ascii).unwrap();
+        let ascii = String::from_utf8(buffer).unwrap();
+        assert!(ascii.is_ascii());
+        assert_eq!(
+            ascii
+                .lines()
+                .nth(3)
+                .unwrap()
+                .split('|')
+                .collect::<Vec<&str>>(),
+            row_cells
+        );
+    }
+
+    #[test]
+    fn tag_filter_test() {
+        let checking = String::from("assets:checking");
+        let groceries = String::from("expenses:groceries");
+        let accounts: HashSet<&String> = [&checking, &groceries].iter().cloned().collect();
+
+        let entries = [
+            "2020/01/02 * Groceries\n    ; category: groceries\n    assets:checking  -50\n    expenses:groceries  50",
+            "2020/01/03 * Snacks\n    ; :reviewed:\n    assets:checking  -5\n    expenses:groceries  5 ; category: snacks",
+            "2020/01/04 * Refund\n    assets:checking  20\n    expenses:groceries  -20",
+        ]
+        .iter()
+        .map(|s| Entry::parse(s, "%Y/%m/%d", '.', &accounts).unwrap())
+        .collect::<Vec<Entry>>();
+
+        let descriptions = |key: &str, value: Option<&str>| {
+            let tag_match = Some((key.to_string(), value.map(String::from)));
+            filter_entries(&entries, None, None, &None, &tag_match)
+                .iter()
+                .map(|e| e.get_description())
+                .collect::<Vec<&str>>()
+        };
+
+        // a value has to match exactly, from the entry or from a posting
+        assert_eq!(descriptions("category", Some("groceries")), ["Groceries"]);
+        assert_eq!(descriptions("category", Some("snacks")), ["Snacks"]);
+        assert!(descriptions("category", Some("grocer")).is_empty());
+
+        // a key alone matches tags, or metadata with any value
+        assert_eq!(descriptions("reviewed", None), ["Snacks"]);
+        assert_eq!(descriptions("category", None), ["Groceries", "Snacks"]);
+    }
+}
@@ -1,5 +1,6 @@
 pub mod account;
 pub mod amount;
+pub mod color;
 pub mod entry;
 pub mod envelope;
 pub mod errors;
@@ -7,13 +8,17 @@ pub mod flags;
 pub mod importer;
 pub mod ledger;
 pub mod posting;
+pub mod prices;
 pub mod utils;
 
 fn main() {
-    match flags::CommandFlags::parse_from_env() {
-        Ok(f) => if let Err(e) = f.execute() {
-            eprintln!("{}", e)
-        },
-        Err(e) => eprintln!("{}", e),
+    let error_format = flags::ErrorFormat::from_env();
+
+    let result = flags::CommandFlags::parse_from_env().and_then(|f| f.execute());
+
+    // exit with a failure so that scripts (and CI) can tell something went wrong
+    if let Err(e) = result {
+        error_format.print(&e, None);
+        std::process::exit(1);
     }
 }
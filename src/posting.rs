@@ -1,15 +1,58 @@
-use crate::amount::Amount;
+use crate::amount::{Amount, AmountFormat};
+use crate::entry::Entry;
 use crate::errors::*;
+use crate::prices::Prices;
 use crate::utils;
-use std::collections::HashSet;
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
+/// The width of the account column in displayed postings, when no width is given
+const ACCOUNT_WIDTH: usize = 50;
+
+/// The narrowest the account column can be when it's sized to fit the accounts in an entry
+pub const MIN_ACCOUNT_WIDTH: usize = 30;
+
+/// In aligned postings, the number of columns (after the account column) that the integer part of
+/// an amount is right-aligned to
+const ALIGNED_INTEGER_WIDTH: usize = 12;
+
+/// How postings are laid out when they're written out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PostingFormat {
+    /// Amounts start right after the account column
+    #[default]
+    Classic,
+
+    /// Amounts are right-aligned so that their decimal points line up, like in ledger-cli
+    Aligned,
+}
+
+impl PostingFormat {
+    pub fn parse(s: &str) -> Result<Self, SilverfoxError> {
+        match s {
+            "classic" => Ok(Self::Classic),
+            "aligned" => Ok(Self::Aligned),
+            _ => Err(SilverfoxError::Basic(format!(
+                "`{}` isn't a posting format silverfox knows. try `classic` or `aligned`",
+                s
+            ))),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ClassicPosting {
     amount: Option<Amount>,
     account: String,
     cost_assertion: Option<Cost>,
     balance_assertion: Option<Amount>,
+    metadata: HashMap<String, String>,
+    tags: HashSet<String>,
+
+    /// How much of the entry's leftover amount this posting takes, if it's one of several blank
+    /// postings (see `Entry::get_posting_amount`)
+    weight: Option<f64>,
 }
 
 #[derive(Clone, Debug)]
@@ -17,6 +60,8 @@ pub struct EnvelopePosting {
     account_name: String,
     envelope_name: String,
     amount: Amount,
+    metadata: HashMap<String, String>,
+    tags: HashSet<String>,
 }
 
 impl EnvelopePosting {
@@ -25,6 +70,8 @@ impl EnvelopePosting {
             account_name,
             envelope_name,
             amount,
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
         }
     }
 
@@ -61,6 +108,8 @@ impl EnvelopePosting {
             account_name,
             envelope_name,
             amount,
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
         })
     }
 
@@ -69,6 +118,11 @@ impl EnvelopePosting {
         &self.envelope_name
     }
 
+    /// Returns everything that's written before the amount of the posting
+    fn account_column(&self) -> String {
+        format!("envelope {} {}", self.account_name, self.envelope_name)
+    }
+
     /// Returns the account name associated with this posting
     pub fn get_account_name(&self) -> &String {
         &self.account_name
@@ -86,6 +140,8 @@ impl Default for EnvelopePosting {
             account_name: String::new(),
             envelope_name: String::new(),
             amount: Amount::zero(),
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
         }
     }
 }
@@ -114,9 +170,14 @@ impl Posting {
         decimal_symbol: char,
         accounts: &HashSet<&String>,
     ) -> Result<Self, SilverfoxError> {
+        // a comment after the posting can be metadata or tags
+        let comment = utils::get_comment(line);
+        let metadata = comment.and_then(utils::parse_metadata);
+        let tags = comment.map(utils::parse_tags).unwrap_or_default();
+
         // match first token, to decide on parsing an envelope posting or a classic posting
         line = utils::remove_comments(line).trim();
-        match line.split_whitespace().next() {
+        let mut posting = match line.split_whitespace().next() {
             Some(t) => {
                 if t == "envelope" {
                     Ok(Posting::from(EnvelopePosting::parse(
@@ -136,7 +197,16 @@ impl Posting {
                 message: Some("nothing to parse for a Posting".to_string()),
                 context: None,
             })),
+        }?;
+
+        if let Some((key, value)) = metadata {
+            posting.set_metadata(key, value);
+        }
+        for tag in tags {
+            posting.add_tag(tag);
         }
+
+        Ok(posting)
     }
 
     // getters
@@ -149,6 +219,92 @@ impl Posting {
         }
     }
 
+    /// Renames the Posting's account (see `utils::renamed_account`). Returns true if the account
+    /// was renamed.
+    pub fn rename_account(&mut self, old: &str, new: &str) -> bool {
+        let account = match self {
+            Self::Classic(c) => &mut c.account,
+            Self::Envelope(e) => &mut e.account_name,
+        };
+
+        match utils::renamed_account(account, old, new) {
+            Some(a) => {
+                *account = a;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the amount the Posting's account should have after it, if the Posting has a balance
+    /// assertion (`! AMOUNT`). Envelope postings never have one.
+    pub fn get_balance_assertion(&self) -> Option<&Amount> {
+        match self {
+            Self::Classic(c) => c.balance_assertion.as_ref(),
+            Self::Envelope(_) => None,
+        }
+    }
+
+    /// Returns the Posting's own metadata
+    pub fn get_metadata(&self) -> &HashMap<String, String> {
+        match self {
+            Self::Classic(c) => &c.metadata,
+            Self::Envelope(e) => &e.metadata,
+        }
+    }
+
+    /// Sets `key` to `value` in the Posting's metadata
+    pub fn set_metadata(&mut self, key: String, value: String) {
+        match self {
+            Self::Classic(c) => c.metadata.insert(key, value),
+            Self::Envelope(e) => e.metadata.insert(key, value),
+        };
+    }
+
+    /// Returns the metadata of the Posting along with the metadata of `entry`, which the Posting
+    /// is expected to belong to. The Posting's own values are used over the entry's.
+    pub fn effective_metadata(&self, entry: &Entry) -> HashMap<String, String> {
+        let mut metadata = entry.get_metadata().clone();
+        metadata.extend(
+            self.get_metadata()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+
+        metadata
+    }
+
+    /// Returns the Posting's own tags
+    pub fn get_tags(&self) -> &HashSet<String> {
+        match self {
+            Self::Classic(c) => &c.tags,
+            Self::Envelope(e) => &e.tags,
+        }
+    }
+
+    /// Adds `tag` to the Posting's tags
+    pub fn add_tag(&mut self, tag: String) {
+        match self {
+            Self::Classic(c) => c.tags.insert(tag),
+            Self::Envelope(e) => e.tags.insert(tag),
+        };
+    }
+
+    /// Returns the tags of the Posting along with the tags of `entry`, which the Posting is
+    /// expected to belong to
+    pub fn effective_tags(&self, entry: &Entry) -> HashSet<String> {
+        entry.get_tags().union(self.get_tags()).cloned().collect()
+    }
+
+    /// Returns the weight of a blank posting, if it has one. Envelope postings never have a
+    /// weight.
+    pub fn get_weight(&self) -> Option<f64> {
+        match self {
+            Self::Classic(c) => c.weight,
+            Self::Envelope(_) => None,
+        }
+    }
+
     /// Returns the Posting's account
     pub fn get_account(&self) -> &String {
         match self {
@@ -164,17 +320,98 @@ impl Posting {
         }
     }
 
-    // TODO later
-    // pub fn get_native_value_now(&self, prices: Prices) -> Option<f64> {
-    //     match self {
-    //         Self::Envelope(e) => None, // not applicable to envelope postings
-    //         Self::Classic(c) => c.get_original_native_value(),
-    //     }
-    // }
+    /// Returns the value of this posting's amount in the native currency on `date`, using the
+    /// latest price in `prices` instead of the cost recorded with the posting
+    pub fn get_native_value_now(&self, prices: &Prices, date: NaiveDate) -> Option<f64> {
+        match self {
+            Self::Envelope(_) => None, // not applicable to envelope postings
+            Self::Classic(c) => c.get_native_value_now(prices, date),
+        }
+    }
+
+    /// Returns the number of characters the Posting takes up in the account column, which is
+    /// everything before the amount
+    pub fn get_account_column_len(&self) -> usize {
+        match self {
+            Self::Classic(c) => c.account.chars().count(),
+            Self::Envelope(e) => e.account_column().chars().count(),
+        }
+    }
+
+    /// Returns a String that can be written in a file and parsed later on, giving the same result.
+    /// The account column is padded to `account_width` characters.
+    pub fn as_parsable(&self, format: PostingFormat, account_width: usize) -> String {
+        let _plain = AmountFormat::plain_scope();
 
-    /// Returns a String that can be written in a file and parsed later on, giving the same result
-    pub fn as_parsable(&self) -> String {
-        format!("{}", self)
+        match format {
+            PostingFormat::Classic => format!("{:w$}", self, w = account_width),
+            PostingFormat::Aligned => format!("{:#w$}", self, w = account_width),
+        }
+    }
+
+    /// Returns the posting as ledger-cli and hledger write it, with at least two spaces between the
+    /// account (padded to `account_width` characters) and the amount. `amount` is written in
+    /// place of a blank amount, if it's given. Envelope postings have no equivalent in ledger, so
+    /// they return None.
+    pub fn as_ledger(&self, account_width: usize, amount: Option<&Amount>) -> Option<String> {
+        let c = self.as_classic()?;
+        let _plain = AmountFormat::plain_scope();
+
+        let mut postlude = String::new();
+        if let Some(a) = c.amount.as_ref().or(amount) {
+            postlude.push_str(a.to_string().trim());
+
+            // in ledger, `@@` is a total cost
+            match &c.cost_assertion {
+                Some(Cost::UnitCost(u)) => {
+                    postlude.push_str(&format!(" @ {}", u.to_string().trim()))
+                }
+                Some(Cost::TotalCost(t)) => {
+                    postlude.push_str(&format!(" @@ {}", t.to_string().trim()))
+                }
+                None => (),
+            }
+        }
+
+        // and `=` is a balance assertion
+        if let Some(b) = &c.balance_assertion {
+            postlude.push_str(&format!(" = {}", b.to_string().trim()));
+        }
+
+        if postlude.is_empty() {
+            Some(c.account.clone())
+        } else {
+            Some(format!(
+                "{:w$}  {}",
+                c.account,
+                postlude.trim_start(),
+                w = account_width
+            ))
+        }
+    }
+
+    /// Returns a copy of the Posting with its amount negated, for reversing an entry. Total costs
+    /// are negated along with the amount. Balance assertions are left out, since they wouldn't
+    /// hold anymore. Blank amounts stay blank.
+    pub fn negated(&self) -> Self {
+        match self {
+            Self::Classic(c) => Self::Classic(ClassicPosting {
+                amount: c.amount.clone().map(|a| -a),
+                account: c.account.clone(),
+                cost_assertion: c.cost_assertion.clone().map(|cost| match cost {
+                    Cost::TotalCost(a) => Cost::TotalCost(-a),
+                    unit => unit,
+                }),
+                balance_assertion: None,
+                metadata: c.metadata.clone(),
+                tags: c.tags.clone(),
+                weight: c.weight,
+            }),
+            Self::Envelope(e) => Self::Envelope(EnvelopePosting {
+                amount: -e.amount.clone(),
+                ..e.clone()
+            }),
+        }
     }
 
     pub fn is_envelope(&self) -> bool {
@@ -184,6 +421,37 @@ impl Posting {
     pub fn is_classic(&self) -> bool {
         matches!(self, Self::Classic(_))
     }
+
+    /// Returns the ClassicPosting inside, if this is a classic posting
+    pub fn as_classic(&self) -> Option<&ClassicPosting> {
+        match self {
+            Self::Classic(c) => Some(c),
+            Self::Envelope(_) => None,
+        }
+    }
+
+    /// Returns the EnvelopePosting inside, if this is an envelope posting
+    pub fn as_envelope(&self) -> Option<&EnvelopePosting> {
+        match self {
+            Self::Classic(_) => None,
+            Self::Envelope(e) => Some(e),
+        }
+    }
+
+    /// Returns which kind of posting this is
+    pub fn kind(&self) -> PostingKind {
+        match self {
+            Self::Classic(_) => PostingKind::Classic,
+            Self::Envelope(_) => PostingKind::Envelope,
+        }
+    }
+}
+
+/// The kinds of Postings, without their contents
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostingKind {
+    Classic,
+    Envelope,
 }
 
 impl fmt::Display for Posting {
@@ -207,6 +475,9 @@ impl ClassicPosting {
             amount,
             balance_assertion,
             cost_assertion,
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
+            weight: None,
         }
     }
 
@@ -216,6 +487,9 @@ impl ClassicPosting {
             account: String::new(),
             cost_assertion: None,
             balance_assertion: None,
+            metadata: HashMap::new(),
+            tags: HashSet::new(),
+            weight: None,
         }
     }
 
@@ -230,21 +504,52 @@ impl ClassicPosting {
         let trimmed_line = utils::remove_comments(line).trim();
         let tokens = trimmed_line.split_whitespace().collect::<Vec<&str>>();
 
-        let amount_tokens: Vec<&str>;
+        let mut amount_tokens: Vec<&str>;
         posting.account = tokens[0].to_string();
         amount_tokens = tokens[1..].to_vec();
 
-        if let Err(e) = posting.parse_amount(&amount_tokens, decimal_symbol) {
+        if let Err(e) = posting.parse_weight(&mut amount_tokens) {
+            Err(SilverfoxError::from(e))
+        } else if let Err(e) = posting.parse_amount(&amount_tokens, decimal_symbol) {
             Err(SilverfoxError::from(e))
         } else if let Err(e) = posting.parse_assertion_amounts(&amount_tokens, decimal_symbol) {
             Err(SilverfoxError::from(e))
         } else if let Err(e) = posting.validate(accounts) {
             Err(SilverfoxError::from(e))
+        } else if posting.weight.is_some() && posting.amount.is_some() {
+            Err(SilverfoxError::from(ParseError {
+                message: Some(String::from("only blank postings can have a weight, since other postings already have an amount")),
+                context: Some(trimmed_line.to_string()),
+            }))
         } else {
             Ok(posting)
         }
     }
 
+    /// Parses and removes `weight N` from the amount tokens, if it's there
+    fn parse_weight(&mut self, amount_tokens: &mut Vec<&str>) -> Result<(), ParseError> {
+        let i = match amount_tokens.iter().position(|&t| t == "weight") {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+
+        let raw_weight = amount_tokens.get(i + 1).copied().unwrap_or("");
+        match raw_weight.parse::<f64>() {
+            Ok(w) if w > 0.0 && w.is_finite() => {
+                self.weight = Some(w);
+                amount_tokens.drain(i..i + 2);
+                Ok(())
+            }
+            _ => Err(ParseError {
+                message: Some(format!(
+                    "`{}` isn't a weight silverfox can use. weights need to be positive numbers, like `weight 2`",
+                    raw_weight
+                )),
+                context: Some(amount_tokens.join(" ")),
+            }),
+        }
+    }
+
     fn parse_amount(
         &mut self,
         amount_tokens: &[&str],
@@ -346,6 +651,12 @@ impl ClassicPosting {
         }
     }
 
+    /// Returns the value of this posting's amount in the native currency on `date` (see
+    /// `Prices::native_value`). Blank postings have no value.
+    pub fn get_native_value_now(&self, prices: &Prices, date: NaiveDate) -> Option<f64> {
+        prices.native_value(self.amount.as_ref()?, date)
+    }
+
     pub fn get_original_native_value(&self) -> Option<f64> {
         // calculate native price of this posting. posting.amount must exist for this to work
         // (since this is literally used primarily for calculating the value of blank posting
@@ -383,12 +694,19 @@ impl ClassicPosting {
     }
 }
 
+/// Displays the posting with its amount right after the account column. The width of the account
+/// column can be given as the width of the formatter (`{:30}`). With the alternate flag (`{:#}`),
+/// the amount is aligned on its decimal point instead (see `PostingFormat::Aligned`).
 impl fmt::Display for ClassicPosting {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut postlude = String::new();
 
         if let Some(a) = &self.amount {
-            postlude.push_str(&format!("{}", a));
+            if f.alternate() {
+                postlude.push_str(&aligned_amount(a));
+            } else {
+                postlude.push_str(&format!("{}", a));
+            }
         }
 
         if let Some(c) = &self.cost_assertion {
@@ -399,17 +717,60 @@ impl fmt::Display for ClassicPosting {
             postlude.push_str(&format!(" ! {}", b));
         }
 
-        write!(f, "{:50} {}", self.account, postlude)
+        if let Some(w) = self.weight {
+            postlude.push_str(&format!(" weight {}", w));
+        }
+
+        write!(
+            f,
+            "{:account_width$} {}",
+            self.account,
+            postlude,
+            account_width = f.width().unwrap_or(ACCOUNT_WIDTH)
+        )
     }
 }
 
+/// Displays the posting like a `ClassicPosting`, including the alternate flag
 impl fmt::Display for EnvelopePosting {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let prelude = format!("envelope {} {}", self.account_name, self.envelope_name);
-        write!(f, "{:50} {}", prelude, self.amount)
+        let prelude = self.account_column();
+        let amount = if f.alternate() {
+            aligned_amount(&self.amount)
+        } else {
+            format!("{}", self.amount)
+        };
+
+        write!(
+            f,
+            "{:account_width$} {}",
+            prelude,
+            amount,
+            account_width = f.width().unwrap_or(ACCOUNT_WIDTH)
+        )
     }
 }
 
+/// Returns the amount padded on the left so that the end of its integer part (where the decimal
+/// point is, or would be) falls `ALIGNED_INTEGER_WIDTH` columns in
+fn aligned_amount(amount: &Amount) -> String {
+    let s = format!("{}", amount);
+
+    let integer_end = match s.find('.') {
+        Some(i) => i,
+        None => s
+            .rfind(|c: char| c.is_ascii_digit())
+            .map(|i| i + 1)
+            .unwrap_or_else(|| s.len()),
+    };
+
+    format!(
+        "{}{}",
+        " ".repeat(ALIGNED_INTEGER_WIDTH.saturating_sub(integer_end)),
+        s
+    )
+}
+
 #[derive(Clone, Debug)]
 pub enum Cost {
     TotalCost(Amount),
@@ -456,3 +817,81 @@ where
         None => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_test() {
+        let classic = Posting::from(ClassicPosting::new(
+            "assets:checking",
+            Some(Amount::zero()),
+            None,
+            None,
+        ));
+        assert_eq!(classic.kind(), PostingKind::Classic);
+        assert_eq!(classic.as_classic().unwrap().account, "assets:checking");
+        assert!(classic.as_envelope().is_none());
+
+        let envelope = Posting::from(EnvelopePosting::new(
+            String::from("assets:checking"),
+            Amount::zero(),
+            String::from("rent"),
+        ));
+        assert_eq!(envelope.kind(), PostingKind::Envelope);
+        assert_eq!(envelope.as_envelope().unwrap().get_envelope_name(), "rent");
+        assert!(envelope.as_classic().is_none());
+    }
+
+    #[test]
+    fn aligned_format_test() {
+        let posting = |mag: f64| {
+            Posting::Classic(ClassicPosting::new(
+                "assets:checking",
+                Some(Amount {
+                    mag,
+                    symbol: Some(String::from("$")),
+                }),
+                None,
+                None,
+            ))
+        };
+
+        let lines = [posting(5.25), posting(1250.75), posting(-30.5)]
+            .iter()
+            .map(|p| p.as_parsable(PostingFormat::Aligned, 50))
+            .collect::<Vec<String>>();
+
+        // the decimal points all line up
+        let decimal_column = lines[0].find('.').unwrap();
+        for line in &lines {
+            assert_eq!(line.find('.'), Some(decimal_column), "{:?}", lines);
+        }
+
+        // amounts without a fractional part line up where their decimal point would be
+        let whole = posting(100.0).as_parsable(PostingFormat::Aligned, 50);
+        assert_eq!(whole.len(), decimal_column);
+
+        // the classic format is unchanged
+        assert_eq!(
+            posting(5.25).as_parsable(PostingFormat::Classic, 50),
+            format!("{:50} $ 5.25", "assets:checking")
+        );
+    }
+
+    #[test]
+    fn weight_round_trip_test() {
+        let account = String::from("expenses:x");
+        let accounts: HashSet<&String> = [&account].iter().cloned().collect();
+
+        let posting = ClassicPosting::parse("expenses:x ! 100 weight 2", '.', &accounts).unwrap();
+        let written = format!("{}", posting);
+        assert!(written.ends_with("100 weight 2"), "{:?}", written);
+
+        // what's written can be read again, assertion and weight included
+        let reparsed = Posting::from(ClassicPosting::parse(&written, '.', &accounts).unwrap());
+        assert_eq!(reparsed.get_balance_assertion().unwrap().mag, 100.0);
+        assert_eq!(reparsed.get_weight(), Some(2.0));
+    }
+}
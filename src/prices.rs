@@ -0,0 +1,170 @@
+use crate::amount::{Amount, AmountFormat, AmountPool};
+use crate::errors::ParseError;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+/// The prices of commodities over time, read from lines like `P 2024/01/01 BTC 42000`. Prices in
+/// the native currency are what allow holdings to be valued in it (see `native_value`).
+#[derive(Debug, Default)]
+pub struct Prices {
+    /// The prices of each commodity, by symbol, in date order
+    prices: HashMap<String, Vec<(NaiveDate, Amount)>>,
+}
+
+impl Prices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a price line, `P DATE SYMBOL PRICE`, and returns the symbol, the date, and the
+    /// price. The price is an amount like any other, so it can be native (`42000`) or in another
+    /// currency (`42000 USD`).
+    pub fn parse_line(
+        line: &str,
+        date_format: &str,
+        decimal_symbol: char,
+    ) -> Result<(String, NaiveDate, Amount), ParseError> {
+        let err = |message: &str| ParseError {
+            message: Some(message.to_string()),
+            context: Some(line.to_string()),
+        };
+
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("P") {
+            return Err(err("price lines start with `P`"));
+        }
+
+        let date = match tokens.next() {
+            Some(d) => NaiveDate::parse_from_str(d, date_format)
+                .map_err(|_| err("this price's date doesn't match your date format"))?,
+            None => return Err(err("this price doesn't have a date")),
+        };
+
+        let symbol = match tokens.next() {
+            Some(s) => s.to_string(),
+            None => return Err(err("this price doesn't say which commodity it's for")),
+        };
+
+        let price_str = tokens.collect::<Vec<&str>>().join(" ");
+        if price_str.is_empty() {
+            return Err(err("this price doesn't have an amount"));
+        }
+        let price = Amount::parse(&price_str, decimal_symbol)?;
+
+        Ok((symbol, date, price))
+    }
+
+    /// Records the price of one unit of `symbol` on `date`
+    pub fn add(&mut self, symbol: String, date: NaiveDate, price: Amount) {
+        let prices = self.prices.entry(symbol).or_default();
+        let index = prices.partition_point(|(d, _)| *d <= date);
+        prices.insert(index, (date, price));
+    }
+
+    /// Adds every price in `other` to these prices
+    pub fn merge(&mut self, other: Prices) {
+        for (symbol, prices) in other.prices {
+            for (date, price) in prices {
+                self.add(symbol.clone(), date, price);
+            }
+        }
+    }
+
+    /// Returns every price as a `P` line that can be parsed again, sorted by commodity and date
+    pub fn as_parsable(&self, date_format: &str) -> String {
+        let _plain = AmountFormat::plain_scope();
+
+        let mut symbols = self.prices.keys().collect::<Vec<&String>>();
+        symbols.sort();
+
+        let mut s = String::new();
+        for symbol in symbols {
+            for (date, price) in &self.prices[symbol] {
+                s.push_str(&format!(
+                    "P {} {} {}\n",
+                    date.format(date_format),
+                    symbol,
+                    price.to_string().trim()
+                ));
+            }
+        }
+
+        s
+    }
+
+    /// Returns the latest price of one unit of `symbol` on or before `date`
+    pub fn price_on(&self, symbol: &str, date: NaiveDate) -> Option<&Amount> {
+        self.prices
+            .get(symbol)?
+            .iter()
+            .rev()
+            .find(|(d, _)| *d <= date)
+            .map(|(_, p)| p)
+    }
+
+    /// Returns the value of `amount` in the native currency on `date`. Native amounts are worth
+    /// what they are; anything else needs a native price on or before `date`.
+    pub fn native_value(&self, amount: &Amount, date: NaiveDate) -> Option<f64> {
+        match &amount.symbol {
+            None => Some(amount.mag),
+            Some(s) => match self.price_on(s, date) {
+                Some(p) if p.symbol.is_none() => Some(amount.mag * p.mag),
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns `pool` with every amount that has a native price on `date` converted to the native
+    /// currency. Amounts without a price are left as they are.
+    pub fn to_native(&self, pool: &AmountPool, date: NaiveDate) -> AmountPool {
+        let mut converted = AmountPool::new();
+        for amount in pool.iter() {
+            match self.native_value(amount, date) {
+                Some(mag) => converted += Amount { mag, symbol: None },
+                None => converted += amount.clone(),
+            }
+        }
+
+        converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_on_test() {
+        let mut prices = Prices::new();
+        for line in &[
+            "P 2024/03/01 BTC 60000",
+            "P 2024/01/01 BTC 42000",
+            "P 2024/01/01 EUR 1.1 USD",
+        ] {
+            let (symbol, date, price) = Prices::parse_line(line, "%Y/%m/%d", '.').unwrap();
+            prices.add(symbol, date, price);
+        }
+
+        let btc = Amount {
+            mag: 0.5,
+            symbol: Some(String::from("BTC")),
+        };
+        let on = |y, m, d| NaiveDate::from_ymd(y, m, d);
+
+        // the latest price on or before the date is used
+        assert_eq!(prices.native_value(&btc, on(2024, 2, 1)), Some(21000.0));
+        assert_eq!(prices.native_value(&btc, on(2024, 3, 1)), Some(30000.0));
+        assert_eq!(prices.native_value(&btc, on(2023, 12, 31)), None);
+
+        // prices in other currencies can't be used for native values
+        let eur = Amount {
+            mag: 10.0,
+            symbol: Some(String::from("EUR")),
+        };
+        assert!(prices.price_on("EUR", on(2024, 2, 1)).is_some());
+        assert_eq!(prices.native_value(&eur, on(2024, 2, 1)), None);
+
+        assert!(Prices::parse_line("P 2024/01/01 BTC", "%Y/%m/%d", '.').is_err());
+        assert!(Prices::parse_line("P 01-01-2024 BTC 5", "%Y/%m/%d", '.').is_err());
+    }
+}
@@ -1,3 +1,8 @@
+use chrono::{Datelike, NaiveDate};
+
+/// The number of days in an average month
+pub const DAYS_PER_MONTH: f64 = 365.25 / 12.0;
+
 pub fn remove_comments(s: &str) -> &str {
     // get indices
     let double_slash_index = s.find("//");
@@ -23,3 +28,115 @@ pub fn remove_comments(s: &str) -> &str {
         s
     }
 }
+
+/// Returns the comment in `s` (see `remove_comments`), without its `;` or `//` and trimmed. Returns
+/// None if there's no comment, or if the comment is blank.
+pub fn get_comment(s: &str) -> Option<&str> {
+    let rest = &s[remove_comments(s).len()..];
+    let comment = rest
+        .strip_prefix("//")
+        .or_else(|| rest.strip_prefix(';'))?
+        .trim();
+
+    if comment.is_empty() {
+        None
+    } else {
+        Some(comment)
+    }
+}
+
+/// Parses metadata from a comment, written like `key: value`. Keys can't have any whitespace in
+/// them. Returns None if the comment isn't metadata.
+pub fn parse_metadata(comment: &str) -> Option<(String, String)> {
+    let (key, value) = comment.split_once(':')?;
+    let key = key.trim();
+
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        None
+    } else {
+        Some((key.to_string(), value.trim().to_string()))
+    }
+}
+
+/// Parses tags from a comment, written like `:reviewed:` (or `:reviewed:paid:` for more than one).
+/// Tags can be anywhere in the comment. Returns an empty Vec if the comment has no tags.
+pub fn parse_tags(comment: &str) -> Vec<String> {
+    comment
+        .split_whitespace()
+        .filter(|w| w.len() > 2 && w.starts_with(':') && w.ends_with(':'))
+        .flat_map(|w| w.split(':'))
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the last day of the date's month
+pub fn last_date_of_month(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
+        .unwrap_or_else(|| NaiveDate::from_ymd(date.year() + 1, 1, 1))
+        .pred()
+}
+
+/// Returns the date `num` months before `date`. If the day doesn't exist in the resulting month,
+/// the last day of that month is used instead.
+pub fn subtract_months(date: NaiveDate, num: i32) -> NaiveDate {
+    checked_subtract_months(date, num).expect("date out of range")
+}
+
+/// Like `subtract_months`, but returns None instead of panicking if the resulting date is out of
+/// range
+pub fn checked_subtract_months(date: NaiveDate, num: i32) -> Option<NaiveDate> {
+    let months0 = (date.year() * 12 + date.month0() as i32).checked_sub(num)?;
+    let new_year = months0.div_euclid(12);
+    let new_month = months0.rem_euclid(12) as u32 + 1;
+
+    NaiveDate::from_ymd_opt(new_year, new_month, date.day())
+        .or_else(|| NaiveDate::from_ymd_opt(new_year, new_month, 1).map(last_date_of_month))
+}
+
+/// Returns the new name of `account` if it's renamed from `old` to `new`. Subaccounts are renamed
+/// along with their parents, so renaming `expenses:food` also renames `expenses:food:snacks`.
+pub fn renamed_account(account: &str, old: &str, new: &str) -> Option<String> {
+    if account == old {
+        Some(new.to_string())
+    } else {
+        account
+            .strip_prefix(old)
+            .filter(|rest| rest.starts_with(':'))
+            .map(|rest| format!("{}{}", new, rest))
+    }
+}
+
+/// Cuts `s` down to at most `width` characters, replacing the end with an ellipsis if anything
+/// had to be removed
+pub fn truncate_with_ellipsis(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        s.to_string()
+    } else if width == 0 {
+        String::new()
+    } else {
+        let mut truncated: String = s.chars().take(width - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Returns `s` as a JSON string, in quotes and with any special characters escaped
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}